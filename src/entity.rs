@@ -3,6 +3,9 @@ pub mod supfile;
 pub mod help_displayer;
 pub mod playbook;
 pub mod env;
+pub mod tag_selector;
+pub mod secret;
+pub mod error_catalog;
 
 use clap::Parser;
 
@@ -12,8 +15,9 @@ use clap::Parser;
 pub struct CommandLineArgs {
     #[arg(short = 'f', default_value = "", required = false)]
     pub file: String,
-    #[arg(short = 'e', long = "env", default_value = "", required = false)]
-    pub env: String,
+    /// KEY=VALUE pairs, or @FILE to load a dotenv file; may be repeated
+    #[arg(short = 'e', long = "env", required = false)]
+    pub env: Vec<String>,
     #[arg(short = 'D', long = "debug", required = false, default_value = "false")]
     pub debug: bool,
     #[arg(long = "sshconfig", required = false, default_value = "")]
@@ -25,7 +29,19 @@ pub struct CommandLineArgs {
     #[arg( long = "except", required = false, default_value = "")]
     pub excepthosts: String,
     #[arg( long = "only", required = false, default_value = "")]
-    pub onlyhosts: String
+    pub onlyhosts: String,
+    #[arg( long = "refresh-inventory", required = false, default_value = "false")]
+    pub refresh_inventory: bool,
+    #[arg( long = "tags", required = false, default_value = "")]
+    pub tags: String,
+    #[arg( long = "networks-parallel", required = false, default_value = "false")]
+    pub networks_parallel: bool,
+    /// answer yes to every confirmation and take the default of every prompt
+    #[arg(short = 'y', long = "yes", required = false, default_value = "false")]
+    pub yes: bool,
+    /// NETWORK[,NETWORK...] COMMAND|TARGET [...], or TARGET [...] / COMMAND [...]
+    #[arg(trailing_var_arg = true)]
+    pub args: Vec<String>,
 }
 
 
@@ -1,16 +1,37 @@
 // Environment variable constants
 pub const CSUP_PASSWD_ENV: &str = "SUP_PASSWORD";
 pub const CSUP_DO_SUDO_ENV: &str = "SUP_SUDO";
+pub const SUP_NETWORK_ENV: &str = "SUP_NETWORK";
+pub const SUP_HOST_ENV: &str = "SUP_HOST";
+pub const SUP_USER_ENV: &str = "SUP_USER";
+pub const SUP_TIME_ENV: &str = "SUP_TIME";
+pub const SUP_ENV_ENV: &str = "SUP_ENV";
+pub const SUP_TARGET_ENV: &str = "SUP_TARGET";
+pub const SUP_COMMAND_ENV: &str = "SUP_COMMAND";
+pub const SUP_RUN_ID_ENV: &str = "SUP_RUN_ID";
 
 // Separator constants
 pub const PASS_SEPARATOR: &str = " | ";
 pub const TUBE_NAME_SEPARATOR: &str = " << ";
+pub const TAGS_START: &str = " [";
 
 // Other constants
+pub const ALL_NETWORKS: &str = "all";
+
+// Reserved first arguments that select a subcommand instead of a network
+pub const ENV_SUBCOMMAND: &str = "env";
+pub const SECRETS_SUBCOMMAND: &str = "secrets";
+pub const EXPLAIN_SUBCOMMAND: &str = "explain";
+pub const CHECK_SUBCOMMAND: &str = "check";
+pub const LINT_SUBCOMMAND: &str = "lint";
 pub const MAIN_SCRIPT: &str = "_ssup_run";
 pub const VARS_FILE: &str = "_ssup_env";
 pub const HASHED_PASS: &str = "_ssup_pass";
 pub const INJECTED_COMMANDS_FILE: &str = "_ssup_commands";
 pub const SSUP_WORK_FOLDER: &str = ".local/ssup/run/";
+pub const DEFAULT_INVENTORY_TIMEOUT_SECS: u64 = 60;
+pub const DEFAULT_SHELL_VALUE_TIMEOUT_SECS: u64 = 30;
 pub const VERSION: &str = "0.5";
-pub const SOURCE_DIRECTIVE: &str = "#source://";
\ No newline at end of file
+pub const SOURCE_DIRECTIVE: &str = "#source://";
+/// Everything after it on the command line is a positional parameter.
+pub const POSITIONAL_SEPARATOR: &str = "--";
@@ -1,7 +1,10 @@
 use indexmap::IndexMap; // Use IndexMap to preserve insertion order from YAML
-use serde::Deserialize;
+use serde::de::{self, Deserializer, Visitor};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::fmt;
 use crate::gateways::logger::logger_func as l;
+use crate::entity::secret::REDACTED;
 
 /// Represents an environment variable key-value pair.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)] // Added common derives
@@ -9,6 +12,8 @@ pub struct EnvVar {
     // Fields are typically public in structs used like this
     pub key: String,
     pub value: String,
+    /// exported as-is inside double quotes, so the remote shell expands it
+    pub raw: bool,
 }
 
 // Implement Display trait for easy string conversion (like Go's String())
@@ -24,26 +29,253 @@ impl EnvVar {
         EnvVar {
             key: key.into(),
             value: value.into(),
+            raw: false,
         }
     }
 
-    /// Returns the environment variable as a bash export statement.
-    /// Note: This performs simple string embedding. For robust shell compatibility,
-    /// consider more sophisticated escaping if values can contain special characters.
+    /// Creates an EnvVar whose value is left for the remote shell to expand.
+    pub fn new_raw(key: impl Into<String>, value: impl Into<String>) -> Self {
+        EnvVar {
+            raw: true,
+            ..EnvVar::new(key, value)
+        }
+    }
+
+    /// Returns the environment variable as a POSIX sh export statement.
+    /// The value is single-quoted so the shell takes it literally, unless the
+    /// variable is raw, in which case `$VAR`, `$(cmd)` and friends expand remotely.
     pub fn as_export(&self) -> String {
-        // Use raw string literal r#""# for clarity if needed, but format! handles quotes well here.
-        format!(r#"export {}="{}";"#, self.key, self.value)
+        let value = if self.raw { double_quote(&self.value) } else { shell_quote(&self.value) };
+        format!("export {}={};", self.key, value)
+    }
+}
+
+/// Quotes `value` for POSIX sh: wrapped in single quotes, each `'` written as `'\''`.
+/// Every byte in between is taken literally, newlines included.
+pub fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// Splits on unquoted whitespace and removes the quoting, like a POSIX shell.
+/// Single quotes keep everything literally, double quotes allow `\"`, `\\`,
+/// `\$` and `` \` ``, a backslash outside quotes escapes the next character.
+/// Fails with the quote character left open.
+pub fn split_words(s: &str) -> Result<Vec<String>, char> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    // a word can be empty, e.g. `''`, so track that one was started
+    let mut in_word = false;
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            '\'' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err('\''),
+                    }
+                }
+            }
+            '"' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$' | '`')) => word.push(c),
+                            Some(c) => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                            None => return Err('"'),
+                        },
+                        Some(c) => word.push(c),
+                        None => return Err('"'),
+                    }
+                }
+            }
+            '\\' => {
+                in_word = true;
+                if let Some(c) = chars.next() {
+                    word.push(c);
+                }
+            }
+            c => {
+                in_word = true;
+                word.push(c);
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
     }
+    Ok(words)
+}
+
+/// Whether `name` can be exported as a shell variable.
+pub fn is_env_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Wraps `value` in double quotes for POSIX sh, escaping `"` and `\` so it
+/// cannot end the quoting early. `$` and `` ` `` are kept, they are what a
+/// raw value is for.
+fn double_quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', r"\\").replace('"', r#"\""#))
+}
+
+/// How a value is written in YAML: a plain string, `{raw: "..."}` to have
+/// the remote shell expand it, or `{value: "...", secret: true}` to keep it
+/// out of logs and output. `secret` works with `raw` too.
+/// `{prompt: "Release tag?"}` asks for the value before the run, with an
+/// optional `value` as the default.
+#[derive(Deserialize, Serialize)]
+#[serde(untagged)]
+enum EnvValue {
+    Plain(#[serde(deserialize_with = "scalar_string")] String),
+    Raw {
+        raw: String,
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        secret: bool,
+    },
+    // before `Detailed`, which would otherwise swallow `value` and drop the prompt
+    Prompt {
+        prompt: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        value: Option<String>,
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        secret: bool,
+    },
+    Detailed {
+        value: String,
+        #[serde(default)]
+        secret: bool,
+    },
+}
+
+/// A string, or a number or bool as YAML wrote it: `PORT: 8080`, `DEBUG: true`.
+fn scalar_string<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    struct ScalarVisitor;
+
+    impl Visitor<'_> for ScalarVisitor {
+        type Value = String;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a string, number or bool")
+        }
+
+        fn visit_str<E: de::Error>(self, value: &str) -> Result<String, E> {
+            Ok(value.to_string())
+        }
+
+        fn visit_i64<E: de::Error>(self, value: i64) -> Result<String, E> {
+            Ok(value.to_string())
+        }
+
+        fn visit_u64<E: de::Error>(self, value: u64) -> Result<String, E> {
+            Ok(value.to_string())
+        }
+
+        fn visit_f64<E: de::Error>(self, value: f64) -> Result<String, E> {
+            Ok(value.to_string())
+        }
+
+        fn visit_bool<E: de::Error>(self, value: bool) -> Result<String, E> {
+            Ok(value.to_string())
+        }
+    }
+
+    deserializer.deserialize_any(ScalarVisitor)
 }
 
 /// Represents a list of environment variables, deserialized from a YAML map
 /// while preserving the original order.
-#[derive(Debug, Clone, PartialEq, Eq, Default, Deserialize)]
-#[serde(transparent)] // Deserialize directly into the 'store' field
+#[derive(Clone, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(from = "IndexMap<String, EnvValue>", into = "IndexMap<String, EnvValue>")]
 pub struct EnvList {
     // Use IndexMap<String, String> to preserve insertion order.
     // If order doesn't matter after parsing, std::collections::HashMap is fine.
     store: IndexMap<String, String>,
+    // keys whose values are exported unquoted-for-expansion, see EnvVar::raw
+    raw: HashSet<String>,
+    // keys whose values are redacted in logs and output
+    secret: HashSet<String>,
+    // keys still to be asked for, with their question; the value is the default
+    prompt: IndexMap<String, String>,
+}
+
+impl From<IndexMap<String, EnvValue>> for EnvList {
+    fn from(map: IndexMap<String, EnvValue>) -> Self {
+        let mut list = EnvList::new();
+        for (key, value) in map {
+            match value {
+                EnvValue::Plain(value) => list.set(key, value),
+                EnvValue::Raw { raw, secret } => {
+                    if secret {
+                        list.mark_secret(&key);
+                    }
+                    list.set_raw(key, raw);
+                }
+                EnvValue::Prompt { prompt, value, secret } => {
+                    if secret {
+                        list.mark_secret(&key);
+                    }
+                    list.set(key.clone(), value.unwrap_or_default());
+                    list.prompt.insert(key, prompt);
+                }
+                EnvValue::Detailed { value, secret } => {
+                    if secret {
+                        list.mark_secret(&key);
+                    }
+                    list.set(key, value);
+                }
+            }
+        }
+        list
+    }
+}
+
+impl From<EnvList> for IndexMap<String, EnvValue> {
+    fn from(list: EnvList) -> Self {
+        list.store
+            .into_iter()
+            .map(|(key, value)| {
+                let secret = list.secret.contains(&key);
+                if let Some(prompt) = list.prompt.get(&key) {
+                    let value = Some(value).filter(|value| !value.is_empty());
+                    return (key, EnvValue::Prompt { prompt: prompt.clone(), value, secret });
+                }
+                let value = match (list.raw.contains(&key), secret) {
+                    (true, _) => EnvValue::Raw { raw: value, secret },
+                    (false, true) => EnvValue::Detailed { value, secret },
+                    (false, false) => EnvValue::Plain(value),
+                };
+                (key, value)
+            })
+            .collect()
+    }
+}
+
+/// Secret values are redacted, so lists can be logged and dumped safely.
+impl fmt::Debug for EnvList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(self.store.iter().map(|(key, value)| (key, self.display_value(key, value))))
+            .finish()
+    }
 }
 
 impl EnvList {
@@ -89,29 +321,72 @@ impl EnvList {
 
     /// Sets or updates a key-value pair in the list.
     /// Takes ownership of the key and value strings.
+    /// A value set this way is no longer asked for, see `prompt`.
     pub fn set(&mut self, key: String, value: String) {
         // The check for nil map in Go is not needed because `store` is initialized.
-        l(format!("Setting {} = {}", key, value).as_str()); // Use the log crate
+        l(format!("Setting {} = {}", key, self.display_value(&key, &value)).as_str()); // Use the log crate
+        self.raw.remove(&key);
+        self.prompt.shift_remove(&key);
         self.store.insert(key, value); // IndexMap::insert replaces the value if key exists
     }
 
-    /// Returns all environment variables as a single string of bash export statements.
-    /// Statements are generated in the original insertion order.
+    /// Like `set`, but the value is exported for the remote shell to expand.
+    pub fn set_raw(&mut self, key: String, value: String) {
+        l(format!("Setting raw {} = {}", key, self.display_value(&key, &value)).as_str());
+        self.raw.insert(key.clone());
+        self.prompt.shift_remove(&key);
+        self.store.insert(key, value);
+    }
+
+    /// Returns true if the key was set with `set_raw`.
+    pub fn is_raw(&self, key: &str) -> bool {
+        self.raw.contains(key)
+    }
+
+    /// Marks the key as secret; it stays secret when its value is replaced.
+    pub fn mark_secret(&mut self, key: &str) {
+        self.secret.insert(key.to_string());
+    }
+
+    /// The question to ask for the key's value, while it has not been answered.
+    pub fn prompt(&self, key: &str) -> Option<&str> {
+        self.prompt.get(key).map(String::as_str)
+    }
+
+    /// Returns true if the key holds a secret value.
+    pub fn is_secret(&self, key: &str) -> bool {
+        self.secret.contains(key)
+    }
+
+    /// The value as it may be shown, `****` for secrets.
+    fn display_value<'a>(&self, key: &str, value: &'a str) -> &'a str {
+        if self.is_secret(key) {
+            REDACTED
+        } else {
+            value
+        }
+    }
+
+    /// Returns all environment variables as a single string of export statements.
+    /// Statements are generated in the original insertion order, values are
+    /// quoted like `EnvVar::as_export` does.
     pub fn as_export_string(&self) -> String {
         if self.store.is_empty() {
             return String::new();
         }
 
-        let mut exports = String::new();
-        for (key, value) in self.store.iter() {
-             // Avoid creating an intermediate EnvVar struct just for formatting
-            exports.push_str(&format!(r#"export {}="{}"; "#, key, value));
-        }
-        // Remove the trailing space and semicolon
-        exports.pop(); // remove trailing space
-        // exports.pop(); // remove trailing semicolon - actually the go code didn't remove this
-
-        exports
+        self.store
+            .iter()
+            .map(|(key, value)| {
+                if self.is_raw(key) {
+                    EnvVar::new_raw(key.as_str(), value.as_str())
+                } else {
+                    EnvVar::new(key.as_str(), value.as_str())
+                }
+            })
+            .map(|var| var.as_export())
+            .collect::<Vec<_>>()
+            .join(" ")
     }
 
     /// Returns the number of environment variables.
@@ -123,6 +398,180 @@ impl EnvList {
     pub fn is_empty(&self) -> bool {
         self.store.is_empty()
     }
+
+    /// Returns true if the key is set.
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.store.contains_key(key)
+    }
+
+    /// Removes a key, keeping the order of the remaining ones.
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        self.raw.remove(key);
+        self.secret.remove(key);
+        self.prompt.shift_remove(key);
+        self.store.shift_remove(key)
+    }
+
+    /// Sets every pair of `other` in its order; existing keys keep their
+    /// position and take the new value, rawness and prompt. Secret keys of
+    /// either list stay secret.
+    pub fn extend(&mut self, other: &EnvList) {
+        for (key, value) in other.iter() {
+            if other.is_secret(key) {
+                self.mark_secret(key);
+            }
+            if other.is_raw(key) {
+                self.set_raw(key.clone(), value.clone());
+            } else {
+                self.set(key.clone(), value.clone());
+            }
+            if let Some(prompt) = other.prompt(key) {
+                self.prompt.insert(key.clone(), prompt.to_string());
+            }
+        }
+    }
+}
+
+impl FromIterator<(String, String)> for EnvList {
+    fn from_iter<I: IntoIterator<Item = (String, String)>>(iter: I) -> Self {
+        EnvList { store: iter.into_iter().collect(), ..EnvList::default() }
+    }
+}
+
+impl std::ops::Index<&str> for EnvList {
+    type Output = String;
+
+    /// Panics when the key is missing, like `HashMap`'s indexing.
+    fn index(&self, key: &str) -> &String {
+        &self.store[key]
+    }
+}
+
+impl<'a> IntoIterator for &'a EnvList {
+    type Item = (&'a String, &'a String);
+    type IntoIter = indexmap::map::Iter<'a, String, String>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.store.iter()
+    }
+}
+
+/// Where a resolved environment variable got its value from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EnvSource {
+    /// the `SUP_*` variables ssup sets itself
+    Ssup,
+    Supfile,
+    Network,
+    Host,
+    Command,
+    /// `-e`/`--env` on the command line
+    Cli,
+}
+
+impl fmt::Display for EnvSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            EnvSource::Ssup => "ssup",
+            EnvSource::Supfile => "supfile",
+            EnvSource::Network => "network",
+            EnvSource::Host => "host",
+            EnvSource::Command => "command",
+            EnvSource::Cli => "cli",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A variable of a resolved environment together with its provenance.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedVar {
+    pub value: String,
+    pub raw: bool,
+    /// set when any layer marked the key secret
+    pub secret: bool,
+    pub source: EnvSource,
+    /// lower layers that also set the key, in the order they were replaced
+    pub overrides: Vec<EnvSource>,
+    /// the question, when the layer that set the value asks for it
+    pub prompt: Option<String>,
+}
+
+/// The final environment after layering several `EnvList`s on top of each other.
+/// A key keeps the position of its first definition and the value of its last.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ResolvedEnv {
+    vars: IndexMap<String, ResolvedVar>,
+}
+
+impl ResolvedEnv {
+    pub fn new() -> Self {
+        ResolvedEnv::default()
+    }
+
+    /// Applies `env` on top of everything layered so far.
+    pub fn layer(&mut self, source: EnvSource, env: &EnvList) {
+        for (key, value) in env.iter() {
+            match self.vars.get_mut(key) {
+                Some(var) => {
+                    var.overrides.push(var.source);
+                    var.value = value.clone();
+                    var.raw = env.is_raw(key);
+                    var.secret |= env.is_secret(key);
+                    var.source = source;
+                    var.prompt = env.prompt(key).map(str::to_string);
+                }
+                None => {
+                    let var = ResolvedVar {
+                        value: value.clone(),
+                        raw: env.is_raw(key),
+                        secret: env.is_secret(key),
+                        source,
+                        overrides: Vec::new(),
+                        prompt: env.prompt(key).map(str::to_string),
+                    };
+                    self.vars.insert(key.clone(), var);
+                }
+            }
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn get(&self, key: &str) -> Option<&ResolvedVar> {
+        self.vars.get(key)
+    }
+
+    pub fn iter(&self) -> indexmap::map::Iter<'_, String, ResolvedVar> {
+        self.vars.iter()
+    }
+
+    /// Replaces the values of keys present in `values`, keeping their sources.
+    pub fn set_values(&mut self, values: &EnvList) {
+        for (key, value) in values.iter() {
+            if let Some(var) = self.vars.get_mut(key) {
+                var.value = value.clone();
+            }
+        }
+    }
+
+    /// Drops the provenance, keeping keys in order.
+    pub fn to_env_list(&self) -> EnvList {
+        let mut list = EnvList::new();
+        for (key, var) in self.vars.iter() {
+            if var.secret {
+                list.mark_secret(key);
+            }
+            if var.raw {
+                list.set_raw(key.clone(), var.value.clone());
+            } else {
+                list.set(key.clone(), var.value.clone());
+            }
+            if let Some(prompt) = &var.prompt {
+                list.prompt.insert(key.clone(), prompt.clone());
+            }
+        }
+        list
+    }
 }
 
 // Example Usage (typically in main.rs or tests)
@@ -133,7 +582,7 @@ fn main() {
     let yaml_data = r#"
     FIRST: "hello"
     SECOND: "world"
-    REF: "${FIRST}_${SECOND}" # NOTE: Variable substitution is NOT handled by this code
+    REF: "${FIRST}_${SECOND}" # NOTE: substitution is done by usecase::env_interpolation, not here
     "#;
 
     // Deserialize
@@ -150,7 +599,7 @@ fn main() {
             println!("String Vec: {:?}", list.to_string_vec()); // ["FIRST=hello", "SECOND=world", "REF=${FIRST}_${SECOND}"]
 
             println!("Export String: {}", list.as_export_string());
-            // export FIRST="hello"; export SECOND="world"; export REF="${FIRST}_${SECOND}";
+            // export FIRST='hello'; export SECOND='world'; export REF='${FIRST}_${SECOND}';
 
         }
         Err(e) => {
@@ -174,7 +623,14 @@ mod tests {
     #[test]
     fn test_env_var_as_export() {
          let ev = EnvVar::new("MY_VAR", "some data");
-         assert_eq!(ev.as_export(), r#"export MY_VAR="some data";"#);
+         assert_eq!(ev.as_export(), "export MY_VAR='some data';");
+         let ev = EnvVar::new("MY_VAR", "it's $HOME");
+         assert_eq!(ev.as_export(), r#"export MY_VAR='it'\''s $HOME';"#);
+         let ev = EnvVar::new_raw("MY_VAR", "$HOME/bin");
+         assert_eq!(ev.as_export(), r#"export MY_VAR="$HOME/bin";"#);
+         // a raw value cannot end its quotes early
+         let ev = EnvVar::new_raw("MY_VAR", r#"say "hi" \ $(whoami)"#);
+         assert_eq!(ev.as_export(), r#"export MY_VAR="say \"hi\" \\ $(whoami)";"#);
     }
 
      #[test]
@@ -206,7 +662,17 @@ mod tests {
 
          // Check order using as_export_string()
          let export_str = list.as_export_string();
-         assert_eq!(export_str, r#"export B_VAR="2"; export A_VAR="1"; export C_VAR="3";"#); // Note: Go version had extra trailing space
+         assert_eq!(export_str, "export B_VAR='2'; export A_VAR='1'; export C_VAR='3';"); // Note: Go version had extra trailing space
+     }
+
+      #[test]
+     fn test_numbers_and_bools_are_plain_values() {
+          let list: EnvList = serde_yaml::from_str("PORT: 8080\nDEBUG: true\nRATIO: 0.5\nOFFSET: -3").unwrap();
+          assert_eq!(list.get("PORT"), Some("8080"));
+          assert_eq!(list.get("DEBUG"), Some("true"));
+          assert_eq!(list.get("RATIO"), Some("0.5"));
+          assert_eq!(list.get("OFFSET"), Some("-3"));
+          assert!(!list.is_raw("PORT") && !list.is_secret("DEBUG"));
      }
 
       #[test]
@@ -232,4 +698,147 @@ mod tests {
            let keys = list.keys_owned();
            assert_eq!(keys, vec!["FIRST".to_string(), "SECOND".to_string()]);
      }
-}
\ No newline at end of file
+
+     #[test]
+     fn test_resolved_env_layers_and_sources() {
+          let supfile: EnvList = serde_yaml::from_str("A: supfile\nB: supfile").unwrap();
+          let network: EnvList = serde_yaml::from_str("C: network\nA: network").unwrap();
+          let cli: EnvList = serde_yaml::from_str("A: cli").unwrap();
+
+          let mut resolved = ResolvedEnv::new();
+          resolved.layer(EnvSource::Supfile, &supfile);
+          resolved.layer(EnvSource::Network, &network);
+          resolved.layer(EnvSource::Cli, &cli);
+
+          let a = resolved.get("A").unwrap();
+          assert_eq!(a.value, "cli");
+          assert_eq!(a.source, EnvSource::Cli);
+          assert_eq!(a.overrides, vec![EnvSource::Supfile, EnvSource::Network]);
+          assert_eq!(resolved.get("B").unwrap().source, EnvSource::Supfile);
+
+          // first definition decides the position
+          assert_eq!(resolved.to_env_list().to_string_vec(), vec!["A=cli", "B=supfile", "C=network"]);
+     }
+
+     #[test]
+     fn test_raw_values_from_yaml() {
+          let list: EnvList = serde_yaml::from_str("A: \"$HOME\"\nB:\n  raw: $HOME").unwrap();
+          assert!(!list.is_raw("A"));
+          assert!(list.is_raw("B"));
+          assert_eq!(list.as_export_string(), r#"export A='$HOME'; export B="$HOME";"#);
+
+          // rawness survives layering and a YAML round trip
+          let mut resolved = ResolvedEnv::new();
+          resolved.layer(EnvSource::Supfile, &list);
+          assert_eq!(resolved.to_env_list(), list);
+          let again: EnvList = serde_yaml::from_str(&serde_yaml::to_string(&list).unwrap()).unwrap();
+          assert_eq!(again, list);
+
+          // a plain value set on top of a raw one is plain again
+          let mut list = list;
+          list.set("B".to_string(), "$HOME".to_string());
+          assert!(!list.is_raw("B"));
+     }
+
+     #[test]
+     fn test_secret_values_are_redacted() {
+          let list: EnvList = serde_yaml::from_str("USER: deploy\nTOKEN:\n  value: t0ken\n  secret: true\nLATER:\n  raw: $KEY\n  secret: true").unwrap();
+          assert!(list.is_secret("TOKEN") && list.is_secret("LATER") && !list.is_secret("USER"));
+          assert!(list.is_raw("LATER"));
+          assert_eq!(list.get("TOKEN"), Some("t0ken"));
+          assert_eq!(format!("{:?}", list), r#"{"USER": "deploy", "TOKEN": "****", "LATER": "****"}"#);
+
+          // overriding the value, e.g. from the CLI, keeps it secret
+          let mut layered = list.clone();
+          layered.extend(&serde_yaml::from_str("TOKEN: other").unwrap());
+          assert!(layered.is_secret("TOKEN"));
+          let again: EnvList = serde_yaml::from_str(&serde_yaml::to_string(&list).unwrap()).unwrap();
+          assert_eq!(again, list);
+
+          let mut resolved = ResolvedEnv::new();
+          resolved.layer(EnvSource::Supfile, &list);
+          resolved.layer(EnvSource::Cli, &serde_yaml::from_str("TOKEN: cli").unwrap());
+          assert!(resolved.get("TOKEN").unwrap().secret);
+          assert!(resolved.to_env_list().is_secret("TOKEN"));
+     }
+
+     #[test]
+     fn test_prompted_values() {
+          let list: EnvList = serde_yaml::from_str("TAG:\n  prompt: Release tag?\n  value: latest\nTOKEN:\n  prompt: Token?\n  secret: true\nUSER: deploy").unwrap();
+          assert_eq!((list.prompt("TAG"), list.get("TAG")), (Some("Release tag?"), Some("latest")));
+          assert_eq!((list.prompt("TOKEN"), list.get("TOKEN")), (Some("Token?"), Some("")));
+          assert!(list.is_secret("TOKEN") && list.prompt("USER").is_none());
+          let again: EnvList = serde_yaml::from_str(&serde_yaml::to_string(&list).unwrap()).unwrap();
+          assert_eq!(again, list);
+
+          // a layer on top keeps asking, a value on top answers it
+          let mut layered: EnvList = serde_yaml::from_str("TAG: v1").unwrap();
+          layered.extend(&list);
+          assert_eq!(layered.prompt("TAG"), Some("Release tag?"));
+          layered.extend(&serde_yaml::from_str("TAG: v2").unwrap());
+          assert_eq!((layered.prompt("TAG"), layered.get("TAG")), (None, Some("v2")));
+
+          // resolving layers the same way
+          let mut resolved = ResolvedEnv::new();
+          resolved.layer(EnvSource::Supfile, &list);
+          resolved.layer(EnvSource::Host, &serde_yaml::from_str("TOKEN: t0ken").unwrap());
+          let final_env = resolved.to_env_list();
+          assert_eq!((final_env.prompt("TAG"), final_env.prompt("TOKEN")), (Some("Release tag?"), None));
+     }
+
+     /// xorshift, enough to get varied input without pulling in a crate
+     fn next(state: &mut u64) -> u64 {
+          *state ^= *state << 13;
+          *state ^= *state >> 7;
+          *state ^= *state << 17;
+          *state
+     }
+
+     fn random_value(state: &mut u64) -> String {
+          const TRICKY: &[char] = &['\'', '"', '$', '`', '\\', '\n', '\r', '\t', ' ', '!', '*', '?', ';', '&', '|', '(', ')', '{', '}', '#', '~', '=', 'é', '日', '\u{1F600}'];
+          let len = next(state) % 24;
+          (0..len)
+               .map(|_| {
+                    let pick = next(state);
+                    if pick.is_multiple_of(3) {
+                         TRICKY[(pick / 3) as usize % TRICKY.len()]
+                    } else {
+                         // anything but NUL, which no env value can hold
+                         char::from_u32((pick >> 8) as u32 % 0x250 + 1).unwrap_or('x')
+                    }
+               })
+               .collect()
+     }
+
+     #[test]
+     fn test_quoted_values_round_trip_through_sh() {
+          let mut state = 0x5eed_cafe_f00d_u64;
+          for round in 0..8 {
+               let list: EnvList = (0..40).map(|i| (format!("V{}", i), random_value(&mut state))).collect();
+               let mut script = list.as_export_string();
+               for key in list.keys() {
+                    script.push_str(&format!("\nprintf '%s\\000' \"${}\"", key));
+               }
+               let output = std::process::Command::new("/bin/sh").arg("-c").arg(&script).output().unwrap();
+               assert!(output.status.success(), "round {}: {}", round, String::from_utf8_lossy(&output.stderr));
+
+               let printed: Vec<String> = String::from_utf8(output.stdout)
+                    .unwrap()
+                    .split_terminator('\0')
+                    .map(str::to_string)
+                    .collect();
+               let expected: Vec<String> = list.values().cloned().collect();
+               assert_eq!(printed, expected, "round {}", round);
+          }
+     }
+
+     #[test]
+     fn test_raw_values_expand_but_stay_quoted() {
+          let mut list = EnvList::new();
+          list.set_raw("GREETING".to_string(), r#"say "hi" to $NAME \ $(echo done)"#.to_string());
+          let script = format!("NAME=sup\n{}\nprintf '%s' \"$GREETING\"", list.as_export_string());
+          let output = std::process::Command::new("/bin/sh").arg("-c").arg(&script).output().unwrap();
+          assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+          assert_eq!(String::from_utf8(output.stdout).unwrap(), r#"say "hi" to sup \ done"#);
+     }
+}
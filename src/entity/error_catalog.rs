@@ -0,0 +1,458 @@
+/// Guidance for one kind of failure. Several trace ids can share an entry
+/// when the same mistake is detected in different places.
+#[derive(Debug)]
+pub struct CatalogEntry {
+    pub ids: &'static [&'static str],
+    pub title: &'static str,
+    pub causes: &'static [&'static str],
+    pub remedies: &'static [&'static str],
+}
+
+/// Length of the id prefix printed next to errors, enough to be unique.
+pub const SHORT_ID_LEN: usize = 8;
+
+/// Every trace id ssup reports, see `ssup explain`.
+pub const CATALOG: &[CatalogEntry] = &[
+    CatalogEntry {
+        ids: &["C02C8E4A-8EA7-4BAA-ADAF-D741CC976BCB"],
+        title: "The Supfile could not be read",
+        causes: &[
+            "no Supfile.yml or Supfile in the current directory",
+            "the path given with -f is wrong or not readable",
+        ],
+        remedies: &["run ssup from the directory holding the Supfile, or pass it with -f PATH"],
+    },
+    CatalogEntry {
+        ids: &["C16D83BF-E7DE-48ED-ADE8-C5AD7CD118C4"],
+        title: "The Supfile is not valid",
+        causes: &[
+            "YAML syntax error, often a tab or a wrong indentation",
+            "a field has the wrong type, e.g. a string where a list is expected",
+            "a target line is empty; the grammar is COMMAND [NETWORK] [KEY=VAL...] [--serial N] [--once]",
+            "a target line has an unknown option, only --serial N and --once are known",
+            "--serial is missing its host count, or the count is not a positive number",
+            "a word after the network is not KEY=VAL, or KEY is not a valid variable name",
+            "a target line has an unclosed quote",
+            "a network extends an unknown network, or networks extend each other in a cycle",
+            "an invalid regex in a command's requires_env",
+            "a misspelled key; the message suggests the closest known one",
+        ],
+        remedies: &[
+            "fix the place named in the message; the line and column point into the Supfile",
+            "quote a target line's values that contain spaces, e.g. MSG=\"two words\"",
+        ],
+    },
+    CatalogEntry {
+        ids: &["DB0FAACD-3A80-4F1D-8FB0-0443D26F4A39"],
+        title: "A target refers to an unknown command or network",
+        causes: &[
+            "a typo in a target line under `targets:`",
+            "the command or network was renamed or removed but a target still lists it",
+        ],
+        remedies: &["fix every target line listed, the message suggests the closest known name"],
+    },
+    CatalogEntry {
+        ids: &["02C8CAB3-6225-41BE-A2CD-B99F695FA01D"],
+        title: "Could not change into the Supfile's directory",
+        causes: &["the directory was removed or is not accessible"],
+        remedies: &["check the permissions of the directory holding the Supfile"],
+    },
+    CatalogEntry {
+        ids: &[
+            "A2CDC038-8606-4808-9513-61447F899E4D",
+            "CE387BA5-D366-4437-8F8D-6DF97609CB77",
+            "F0AABC9B-9214-444D-B864-F6AE785EA695",
+            "C5A59F87-CB34-4660-B527-6FD1CCAAA144",
+            "18FECFC4-968C-489B-8108-924453B337B9",
+        ],
+        title: "Network not found",
+        causes: &[
+            "a typo in the network name on the command line",
+            "a target line names a network that is not under `networks:`",
+            "the first argument was meant to be a command or target, but is not defined",
+        ],
+        remedies: &[
+            "run ssup without arguments to list the networks",
+            "add the network under `networks:` or fix the target line",
+        ],
+    },
+    CatalogEntry {
+        ids: &["549C54DE-96F7-4108-9FC8-B0CCB6598BDB"],
+        title: "Target not found",
+        causes: &["a typo in the target name"],
+        remedies: &["run ssup without arguments to list the targets"],
+    },
+    CatalogEntry {
+        ids: &[
+            "64B2D565-8345-4108-B790-25606C2128C0",
+            "0325F68E-5D0C-45BA-970E-D97C5D38B07A",
+            "BC9FA2A7-73DB-413D-958C-91A2FCE16041",
+        ],
+        title: "A target refers to a command that does not exist",
+        causes: &[
+            "a typo in a target line under `targets:`",
+            "the command or nested target was renamed or removed but the target still lists it",
+        ],
+        remedies: &["fix the target line, or add the command under `commands:` or the target under `targets:`"],
+    },
+    CatalogEntry {
+        ids: &["36473A4D-0F4C-40A7-B778-FC11C30A47A9"],
+        title: "The parameters given to a command are not valid",
+        causes: &[
+            "a `NAME=value` names a parameter the command does not declare in `params:`",
+            "a required parameter was neither given nor set by the target step",
+            "a value is not one of the parameter's `choices` or does not match its `regex`",
+        ],
+        remedies: &[
+            "pass values as `ssup NETWORK COMMAND NAME=value` or in order after `--`",
+            "check the `params:` of the command in the Supfile",
+        ],
+    },
+    CatalogEntry {
+        ids: &["3B1F6F0E-5C47-4D8A-9E21-7A64C0D2B9F3"],
+        title: "Targets include each other in a cycle",
+        causes: &["a target lists another target that, directly or through further targets, lists the first one again"],
+        remedies: &["follow the chain in the message and remove one of the target lines that closes the loop"],
+    },
+    CatalogEntry {
+        ids: &["99478F08-E3ED-4A95-BAB5-DEC9383D2663"],
+        title: "Host not found in the network",
+        causes: &[
+            "the host is neither listed under `hosts:` nor returned by the inventory",
+            "the inventory cache is stale",
+        ],
+        remedies: &[
+            "give the host as it appears in the network, with or without user@",
+            "pass --refresh-inventory to re-run the inventory script",
+        ],
+    },
+    CatalogEntry {
+        ids: &["42DE50C7-0C67-4B49-8675-2619B6CFE8C0"],
+        title: "Invalid --tags selector",
+        causes: &["the selector does not follow the `key=value, !tag | other` grammar"],
+        remedies: &["use `,` for AND, `|` for OR and a leading `!` for NOT, e.g. --tags 'role=web, !canary'"],
+    },
+    CatalogEntry {
+        ids: &["A5F38A37-4E0C-4F6C-9464-9AFE878B73E7"],
+        title: "No host matches --tags",
+        causes: &["none of the network's hosts carries the selected tags"],
+        remedies: &["check the hosts' tags, or loosen the selector"],
+    },
+    CatalogEntry {
+        ids: &["001346BD-FB6E-42C0-B36D-3D246F833575"],
+        title: "Required environment is not satisfied",
+        causes: &[
+            "a command's `requires_env` names a variable no env layer sets",
+            "the value is set but does not match the variable's regex as a whole",
+        ],
+        remedies: &[
+            "pass the variable with -e KEY=VALUE, or set it in the Supfile, network, host or command env",
+            "`ssup env NETWORK [HOST]` shows the merged environment and where each value comes from",
+        ],
+    },
+    CatalogEntry {
+        ids: &[
+            "5B8D0E3F-6A21-4C7B-9F4E-E2A36C1D8B70",
+            "9C2E71B4-3A5D-4F08-B6E9-0D17A4C85F32",
+            "D41F6A08-2E9C-4B53-8A7D-3C5E0B96F1A4",
+            "71A3C5E9-0B4D-4E26-9F8A-B6D2E4071C3F",
+        ],
+        title: "Invalid --env value",
+        causes: &[
+            "a word is not KEY=VALUE, often a value with spaces that is not quoted",
+            "the key is not a valid variable name",
+            "a quote is not closed",
+            "the same mistakes on a line of an @FILE env file",
+        ],
+        remedies: &["quote values with spaces: -e 'MSG=\"hello world\"'"],
+    },
+    CatalogEntry {
+        ids: &["327EFFE0-4EFA-4A30-A312-49970BF9074F"],
+        title: "The --env @FILE could not be read",
+        causes: &["the path after @ does not exist or is not readable"],
+        remedies: &["paths are relative to the Supfile's directory"],
+    },
+    CatalogEntry {
+        ids: &["4F19D6A2-7C30-4B85-9E1A-D2C6F8B3057E"],
+        title: "Env value references an undefined variable",
+        causes: &["${NAME} is set neither in any env layer nor in the local environment"],
+        remedies: &["define the variable, or write \\$ for a literal dollar sign", "write $NAME or use `raw:` for values meant for the remote shell"],
+    },
+    CatalogEntry {
+        ids: &["B85E0C3D-1F7A-4E92-A6D4-5C3B8E9F0A21"],
+        title: "Env variables reference each other in a cycle",
+        causes: &["A references B and B references A, possibly through other variables"],
+        remedies: &["break the cycle shown in the message"],
+    },
+    CatalogEntry {
+        ids: &["E07A4C19-58B2-4D3F-8C6E-91A0B7D2F4C5"],
+        title: "Unterminated ${...} or $(...) in an env value",
+        causes: &["a closing brace or parenthesis is missing"],
+        remedies: &["close it, or escape the dollar sign as \\$"],
+    },
+    CatalogEntry {
+        ids: &["A5E08CB4-B68E-4BBF-B82A-DE18C60B1DA8", "1BDEE33B-C757-4D6D-852B-8012B665F9D6"],
+        title: "Value is not a $(command)",
+        causes: &["a value was treated as a command but does not start with $( or end with )"],
+        remedies: &["write the whole value as $(command)"],
+    },
+    CatalogEntry {
+        ids: &["6928F3B4-0D17-45FB-9633-DABA63E163A1"],
+        title: "A $(command) exited with an error",
+        causes: &["the command behind a password, id_file, bastion or env value failed"],
+        remedies: &["run the command by hand from the Supfile's directory to see its error"],
+    },
+    CatalogEntry {
+        ids: &["CE16720F-D992-4EA3-9E68-3F1A740A66C1", "0E5B9D27-C84A-4F31-A6E2-5D9174B3C08F"],
+        title: "A resolved value is not valid UTF-8",
+        causes: &["a $(command) or file: reference produced binary data"],
+        remedies: &["encode the value, e.g. with base64, and decode it where it is used"],
+    },
+    CatalogEntry {
+        ids: &["A3E1F7C0-49D2-4B6E-8C15-7F0D2B94E6A8"],
+        title: "An env: reference names an unset variable",
+        causes: &["`env:NAME` was used but NAME is not set in the local environment"],
+        remedies: &["export NAME before running ssup"],
+    },
+    CatalogEntry {
+        ids: &["6ED64A0A-4FC3-4216-9C50-F831D11DCD3C"],
+        title: "A file: reference could not be read",
+        causes: &["the file does not exist or is not readable"],
+        remedies: &["paths are relative to the Supfile's directory, ~/ is expanded"],
+    },
+    CatalogEntry {
+        ids: &[
+            "2E0C4B61-52F8-4E4B-A0C4-7D1F3B7E9A10",
+            "A41D7E90-3B2C-4C5D-9E8F-6A7B1C2D3E4F",
+            "13C9BFB9-4D2E-4ADE-A50F-64E35D439B1C",
+        ],
+        title: "Could not run /bin/sh",
+        causes: &["/bin/sh is missing, or the system is out of processes or memory"],
+        remedies: &["check that /bin/sh exists and can be started"],
+    },
+    CatalogEntry {
+        ids: &["9B3F1C2A-6D4E-4F7B-8A1C-2E5D7F9B0C34"],
+        title: "A local command timed out",
+        causes: &["an inventory script or $(command) hangs, e.g. waiting for input or the network"],
+        remedies: &["run it by hand; raise `inventory_timeout` for slow inventory scripts"],
+    },
+    CatalogEntry {
+        ids: &["679E60E9-4F30-4129-884C-AE13FFE66306"],
+        title: "The inventory script failed",
+        causes: &["the network's `inventory` command exited with an error"],
+        remedies: &["run the inventory command by hand from the Supfile's directory"],
+    },
+    CatalogEntry {
+        ids: &["0D4B9E27-3C61-4F8A-B5D2-7E1A9C3F6B80"],
+        title: "The inventory_file could not be read",
+        causes: &["the path does not exist or is not readable"],
+        remedies: &["paths are relative to the Supfile's directory"],
+    },
+    CatalogEntry {
+        ids: &["5A8C2F13-9D47-4B6E-A1F0-3C7E8B2D4A95"],
+        title: "The Ansible inventory_file is not valid",
+        causes: &["a malformed [group] header or host line at the line shown"],
+        remedies: &["fix the line, or set `inventory_format: ssup` for one host string per line"],
+    },
+    CatalogEntry {
+        ids: &["E3B7A041-6F2D-4C98-8E15-B9D0C4A7F362"],
+        title: "Unknown inventory_format",
+        causes: &["`inventory_format` is neither `ssup` nor `ansible`"],
+        remedies: &["use one of the two, or leave it unset to guess from the file"],
+    },
+    CatalogEntry {
+        ids: &["DAFC1896-5593-4868-8901-89BF5B5E2EEA"],
+        title: "The Supfile has no secrets file",
+        causes: &["`ssup secrets` was used but the Supfile has no `secrets:` key"],
+        remedies: &["add `secrets: secrets.enc.yml` to the Supfile; `ssup secrets set` creates it"],
+    },
+    CatalogEntry {
+        ids: &["3B1F6A0E-7C42-4D95-A8E3-5F20C9D74B16"],
+        title: "No such secret",
+        causes: &["the key is not in the secrets file"],
+        remedies: &["`ssup secrets edit` shows every key"],
+    },
+    CatalogEntry {
+        ids: &["FF3C21FE-39E9-430B-B905-0A773193B8F4", "82CC27A3-0CE9-44BE-84B9-44F10605FBA7"],
+        title: "The secrets file could not be read or written",
+        causes: &["the file or its directory is missing or not accessible"],
+        remedies: &["check the `secrets:` path and its permissions"],
+    },
+    CatalogEntry {
+        ids: &["C41E8B06-92D7-4A3F-B5E0-7F6A13D29C84"],
+        title: "The secrets file is malformed",
+        causes: &["the file was edited by hand or truncated"],
+        remedies: &["restore it from version control; only change it with `ssup secrets edit|set`"],
+    },
+    CatalogEntry {
+        ids: &["0B6D2F8A-41C3-4E97-8D5B-E3A7190C6F2D"],
+        title: "The secrets file could not be decrypted",
+        causes: &["wrong passphrase, often a stale $SUP_PASSWORD", "the file was modified"],
+        remedies: &["check $SUP_PASSWORD, or unset it to be asked for the passphrase"],
+    },
+    CatalogEntry {
+        ids: &[
+            "F2B7C91E-3D05-4A68-9E4C-18A6D0B5C273",
+            "5A90E3D1-C7B2-4F84-A16E-2D3B9C08F547",
+            "7E3C05A9-B814-4D26-92F1-6A0D8E4B3C57",
+        ],
+        title: "Encrypting the secrets failed",
+        causes: &["no system randomness available, or not enough memory for the key derivation"],
+        remedies: &["retry; report it if it persists"],
+    },
+    CatalogEntry {
+        ids: &[
+            "8C6E6D2B-1BB5-4A92-8E8E-105C31042F52",
+            "575A2D7D-15DD-46AA-BFFB-C65AD405813D",
+            "E07A4C59-2B8D-4F13-96C0-D1A8357F2E94",
+        ],
+        title: "Could not read the passphrase or value",
+        causes: &["ssup runs without a terminal, e.g. in CI", "the two passphrases typed differ"],
+        remedies: &["set $SUP_PASSWORD, and pipe the value in with `ssup secrets set KEY -`"],
+    },
+    CatalogEntry {
+        ids: &["860C3E69-E4E8-41D1-9BBA-F3338A88754E"],
+        title: "Variables with a `prompt:` could not be asked for",
+        causes: &["ssup runs without a terminal, e.g. in CI, and the variables have no default or --yes was not given"],
+        remedies: &["pass each variable with -e KEY=VALUE, or give it a `value:` default and pass --yes"],
+    },
+    CatalogEntry {
+        ids: &["2AB9A93E-9CAD-49B6-90B9-E3C6DA0F20D3"],
+        title: "The run needs a confirmation but there is no terminal",
+        causes: &[
+            "a command has `confirm:` or a network is `protected: true`, and ssup runs without a terminal",
+        ],
+        remedies: &["pass --yes to confirm, e.g. in a CI job that is allowed to touch the network"],
+    },
+    CatalogEntry {
+        ids: &["1224E847-206E-4340-A82F-810D0F4256E2"],
+        title: "A confirmation was declined",
+        causes: &["anything but `y` or `yes` was answered; nothing was run"],
+        remedies: &["run again and answer `y` if the run was intended"],
+    },
+    CatalogEntry {
+        ids: &["C3B1107F-0358-4EE3-A9A5-57DAD3C27570"],
+        title: "Could not read an answer from the terminal",
+        causes: &["stdin was closed while ssup was asking, e.g. with Ctrl-D"],
+        remedies: &["answer the question, or pass the values with -e and confirm with --yes"],
+    },
+    CatalogEntry {
+        ids: &["6D93B2F1-0E57-4A8C-B4D6-29C1E8F05A73"],
+        title: "Editing the secrets failed",
+        causes: &["the editor exited with an error", "the edited text is not a YAML map of strings"],
+        remedies: &["set $VISUAL or $EDITOR to a working editor; nothing was saved, so just retry"],
+    },
+    CatalogEntry {
+        ids: &["5FBDE38C-63F3-44AA-BCD1-BD4DBDD27E85"],
+        title: "Could not write the output",
+        causes: &["stdout was closed, e.g. piped into a command that exited early"],
+        remedies: &["check what ssup's output is piped into"],
+    },
+    CatalogEntry {
+        ids: &["32946188-602C-4519-B5A5-92162D97037B"],
+        title: "Unknown trace id",
+        causes: &["the id was mistyped, or comes from a different ssup version"],
+        remedies: &["run `ssup explain` to list every known id"],
+    },
+    CatalogEntry {
+        ids: &["E59C10ED-87A3-4998-85FE-05206359E97F"],
+        title: "ssup check found problems in the Supfile",
+        causes: &[
+            "a target line names a command or network that does not exist",
+            "an `upload` src or `#source://` file is missing relative to the Supfile directory",
+            "a host string or inventory script is malformed",
+        ],
+        remedies: &[
+            "fix every problem listed above the error, each one names the Supfile line when it is known",
+            "run `ssup check --json` to get the same list as a JSON report",
+        ],
+    },
+    CatalogEntry {
+        ids: &["44884DE3-C79D-4CF3-80F1-980F53E108FD"],
+        title: "shellcheck is not installed",
+        causes: &["`ssup lint` needs the shellcheck binary on the PATH"],
+        remedies: &["install shellcheck, e.g. `apt install shellcheck` or `brew install shellcheck`"],
+    },
+    CatalogEntry {
+        ids: &[
+            "068D85B9-24F5-4DBA-897D-CB93B7EB6388",
+            "FBCB3C6E-04A1-4CD9-A4BE-176342C6C10A",
+            "A3F948CB-FA0D-411B-8981-707D6F0C6B9A",
+        ],
+        title: "shellcheck could not be run",
+        causes: &[
+            "shellcheck could not be started or was killed",
+            "the installed shellcheck is too old to know `--format=json1`",
+        ],
+        remedies: &["run `shellcheck -V` and upgrade to 0.7 or newer"],
+    },
+    CatalogEntry {
+        ids: &["9C082286-CD52-41DE-AFE5-3173B9FE3722"],
+        title: "ssup lint found shellcheck findings",
+        causes: &["a command's `run:` or `local:` script, or a file it sources, has shellcheck findings"],
+        remedies: &[
+            "fix the findings listed above the error, each names the Supfile line it comes from",
+            "list codes that don't apply to a command under its `shellcheck_ignore:`",
+        ],
+    },
+];
+
+/// The shortened id printed next to errors.
+pub fn short_id(id: &str) -> &str {
+    id.get(..SHORT_ID_LEN).unwrap_or(id)
+}
+
+/// Entries with an id starting with `prefix`, ignoring case, so the short
+/// form printed with errors is enough.
+pub fn find_entries(prefix: &str) -> Vec<&'static CatalogEntry> {
+    let prefix = prefix.trim().to_uppercase();
+    if prefix.is_empty() {
+        return Vec::new();
+    }
+    CATALOG.iter().filter(|entry| entry.ids.iter().any(|id| id.starts_with(&prefix))).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::Regex;
+    use std::collections::HashSet;
+    use std::fs;
+    use std::path::Path;
+
+    fn source_files(dir: &Path, files: &mut Vec<String>) {
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                source_files(&path, files);
+            } else if path.extension().is_some_and(|ext| ext == "rs") && !path.ends_with("error_catalog.rs") {
+                files.push(fs::read_to_string(&path).unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn test_every_trace_id_is_cataloged() {
+        let mut files = Vec::new();
+        source_files(&Path::new(env!("CARGO_MANIFEST_DIR")).join("src"), &mut files);
+        let uuid = Regex::new(r#"(?:trace_id: |=> )"([0-9A-F]{8}-[0-9A-F]{4}-[0-9A-F]{4}-[0-9A-F]{4}-[0-9A-F]{12})""#).unwrap();
+        let used: HashSet<&str> = files.iter().flat_map(|file| uuid.captures_iter(file).map(|c| c.get(1).unwrap().as_str())).collect();
+        assert!(used.len() > 30, "found only {} ids", used.len());
+
+        let cataloged: Vec<&str> = CATALOG.iter().flat_map(|entry| entry.ids.iter().copied()).collect();
+        let missing: Vec<&&str> = used.iter().filter(|id| !cataloged.contains(id)).collect();
+        assert!(missing.is_empty(), "not in the catalog: {:?}", missing);
+        let short: HashSet<&str> = cataloged.iter().map(|id| short_id(id)).collect();
+        assert_eq!(short.len(), cataloged.len(), "short ids must be unique");
+    }
+
+    #[test]
+    fn test_find_by_prefix() {
+        let found = find_entries("64b2d565");
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].title, "A target refers to a command that does not exist");
+        assert_eq!(find_entries("C5A59F87-CB34-4660-B527-6FD1CCAAA144")[0].title, "Network not found");
+        assert!(find_entries("").is_empty());
+        assert!(find_entries("ZZZ").is_empty());
+    }
+}
@@ -1,7 +1,7 @@
-use std::process::Command as OsCmd; 
 use super::supfile::Supfile;
 use super::InitState;
 use crate::gateways::logger::logger_func as l;
+use crate::gateways::shellcheck;
 
 pub struct HelpDisplayer {
     pub show_networks: bool,
@@ -68,14 +68,7 @@ impl HelpDisplayer {
         let target_names = targets.names;
         for target_name in target_names {
             l(format!("getting affix for target name: {}", target_name).as_str());
-            let affixed_targets = match targets.targets.get(&target_name) {
-                Some(affixed_targets) => {
-                    affixed_targets.clone()
-                },
-                None => {
-                    panic!("74BB28F7-ADAB-4478-BB20-87EA04746575: failed to get affixed_targets for {}", target_name)
-                },
-            };
+            let affixed_targets = targets.targets.get(&target_name).into_iter().flatten();
 
             for affixed_target in affixed_targets {
                 println!("- {} -> {}", affixed_target.command, affixed_target.affixed_network);
@@ -113,7 +106,7 @@ impl HelpDisplayer {
     }
 
     fn print_out_mods_status(&self) {
-        let is_shellcheck_installed = OsCmd::new("shellcheck").arg("-V").output().is_ok();
+        let is_shellcheck_installed = shellcheck::is_installed();
         let shellcheck_sign = if is_shellcheck_installed { "✓" } else { "✖️" };
         println!("{} shellcheck", shellcheck_sign);
     }
@@ -1,11 +1,15 @@
 use crate::entity::supfile::networks::Network;
 use crate::entity::supfile::commands::Command;
+use crate::entity::env::EnvList;
 use crate::gateways::logger::logger_func as l;
+use std::fmt;
 
 
 pub struct PlayBook {
     pub plays: Vec<Play>,
     pub is_makefile: bool,
+    /// plays on different networks may run concurrently
+    pub networks_parallel: bool,
 }
 
 impl PlayBook {
@@ -13,6 +17,7 @@ impl PlayBook {
         Self {
             plays: Vec::new(),
             is_makefile: false,
+            networks_parallel: false,
         }
     }
 
@@ -31,11 +36,54 @@ impl PlayBook {
     pub fn is_makefile_mode(&self) -> bool {
         self.is_makefile
     }
+
+    /// Plays grouped by the name of their network, in first-seen order.
+    pub fn plays_by_network(&self) -> Vec<(String, Vec<&Play>)> {
+        let mut groups: Vec<(String, Vec<&Play>)> = Vec::new();
+        for play in &self.plays {
+            let name = play.network_name();
+            match groups.iter_mut().find(|(group, _)| *group == name) {
+                Some((_, plays)) => plays.push(play),
+                None => groups.push((name, vec![play])),
+            }
+        }
+        groups
+    }
+}
+
+impl fmt::Display for PlayBook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let groups = self.plays_by_network();
+        let mode = if self.networks_parallel && groups.len() > 1 { "in parallel" } else { "sequentially" };
+        writeln!(f, "Plan: {} network(s), {}", groups.len(), mode)?;
+        for (network, plays) in groups {
+            let hosts = plays.iter().map(|play| play.nets.as_ref().map_or(0, |n| n.hosts.len())).max().unwrap_or(0);
+            writeln!(f, "{} ({} hosts):", network, hosts)?;
+            for play in plays {
+                for command in &play.commands {
+                    writeln!(f, "  - {}", command.name)?;
+                }
+            }
+        }
+        Ok(())
+    }
 }
 
 pub struct Play {
     pub nets: Option<Network>,
     pub commands: Vec<Command>,
+    /// what each command gets on each host, filled by `usecase::env_resolver::resolve_playbook_env`
+    pub envs: Vec<CommandEnv>,
+}
+
+/// The environment one command of a play gets on one of its hosts, with
+/// every layer applied.
+pub struct CommandEnv {
+    /// `network/host`, or the network alone when it has no hosts
+    pub target: String,
+    /// index into `Play::commands`
+    pub command: usize,
+    pub env: EnvList,
 }
 
 impl Play {
@@ -43,6 +91,7 @@ impl Play {
         Self {
             nets: None,
             commands: Vec::new(),
+            envs: Vec::new(),
         }
     }
 
@@ -59,6 +108,10 @@ impl Play {
         &self.nets
     }
 
+    pub fn network_name(&self) -> String {
+        self.nets.as_ref().map(|n| n.name.clone()).unwrap_or_default()
+    }
+
     pub fn add_command(&mut self, command: Command) {
         self.commands.push(command);
     }
@@ -0,0 +1,94 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::{Mutex, OnceLock};
+
+/// What a secret is replaced with in output.
+pub const REDACTED: &str = "****";
+
+/// Secrets shorter than this are not masked in free text, they would
+/// replace too many unrelated characters.
+const MIN_MASKED_LEN: usize = 3;
+
+/// A sensitive value, such as a password. It prints as `****` through both
+/// `Debug` and `Display`; use `expose` where the real value is needed.
+#[derive(Clone, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(value: impl Into<String>) -> Self {
+        Secret(value.into())
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Secret({})", REDACTED)
+    }
+}
+
+impl fmt::Display for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", REDACTED)
+    }
+}
+
+fn known_secrets() -> &'static Mutex<Vec<String>> {
+    static SECRETS: OnceLock<Mutex<Vec<String>>> = OnceLock::new();
+    SECRETS.get_or_init(|| Mutex::new(Vec::new()))
+}
+
+/// Remembers `value` so `mask_secrets` hides it from now on.
+pub fn register_secret(value: &str) {
+    if value.chars().count() < MIN_MASKED_LEN {
+        return;
+    }
+    let mut secrets = known_secrets().lock().unwrap();
+    if !secrets.iter().any(|known| known == value) {
+        secrets.push(value.to_string());
+        // longest first, so a secret containing another one is masked whole
+        secrets.sort_by_key(|known| std::cmp::Reverse(known.len()));
+    }
+}
+
+/// Replaces every registered secret in `text` with `****`.
+pub fn mask_secrets(text: &str) -> String {
+    let secrets = known_secrets().lock().unwrap();
+    let mut masked = text.to_string();
+    for secret in secrets.iter() {
+        if masked.contains(secret.as_str()) {
+            masked = masked.replace(secret.as_str(), REDACTED);
+        }
+    }
+    masked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_secret_never_prints() {
+        let secret = Secret::new("hunter2");
+        assert_eq!(secret.to_string(), REDACTED);
+        assert_eq!(format!("{:?}", Some(secret.clone())), "Some(Secret(****))");
+        assert_eq!(secret.expose(), "hunter2");
+        let parsed: Secret = serde_yaml::from_str("hunter2").unwrap();
+        assert_eq!(parsed, secret);
+    }
+
+    #[test]
+    fn test_registered_values_are_masked() {
+        register_secret("s3cr3t-token");
+        register_secret("s3cr3t");
+        register_secret("ab");
+        assert_eq!(
+            mask_secrets("token=s3cr3t-token prefix=s3cr3t ab"),
+            "token=**** prefix=**** ab"
+        );
+    }
+}
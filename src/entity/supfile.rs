@@ -2,19 +2,25 @@ pub mod targets;
 pub mod networks;
 pub mod commands;
 use crate::entity::supfile::commands::Command;
-use crate::entity::supfile::targets::Targets;
+use crate::entity::supfile::targets::{Target, TargetCycle, Targets};
 use networks::Networks;
+use crate::entity::env::EnvList;
 use serde::{Deserialize, Serialize, Deserializer};
-use std::collections::HashMap;
+use indexmap::IndexMap;
 
 #[derive(Debug, Serialize, Clone)]
 pub struct Supfile {
     pub desc: String,
     pub version: String,
     pub networks: Networks,
-    pub env: HashMap<String, String>, 
-    pub commands: HashMap<String, Command>,
+    pub env: EnvList,
+    pub commands: IndexMap<String, Command>,
     pub targets: Targets,
+    pub shell_output: OutputPolicy,
+    /// encrypted file whose keys are merged into `env` as secret values
+    pub secrets: Option<String>,
+    /// drop target steps that repeat a command on the same network
+    pub dedup_targets: bool,
 }
 
 impl<'de> Deserialize<'de> for Supfile {
@@ -24,20 +30,24 @@ impl<'de> Deserialize<'de> for Supfile {
     {
         // First deserialize into a temporary struct that matches the YAML exactly
         #[derive(Deserialize, Debug, Clone)]
+        #[serde(deny_unknown_fields)]
         struct TempSupfile {
             pub version: String,
-            pub env: Option<HashMap<String, String>>,
+            pub env: Option<EnvList>,
             pub networks: Option<Networks>,
-            pub commands: HashMap<String, Command>,
+            pub commands: IndexMap<String, Command>,
             pub targets: Option<Targets>,
             pub desc: Option<String>,
+            pub shell_output: Option<OutputPolicy>,
+            pub secrets: Option<String>,
+            pub dedup_targets: Option<bool>,
         }
 
         let temp = TempSupfile::deserialize(deserializer)?;
         // dbg!(temp.clone());
 
         // Now create the proper Supfile with command names set
-        let mut commands = HashMap::new();
+        let mut commands = IndexMap::new();
         for (name, mut command) in temp.commands {
             command.name = name.clone();
             commands.insert(name, command);
@@ -45,41 +55,78 @@ impl<'de> Deserialize<'de> for Supfile {
 
         let null_networks = Networks {
             names: vec![],
-            nets: HashMap::new(),
+            nets: IndexMap::new(),
         };
 
         let null_targets = Targets {
             names: vec![],
-            targets: HashMap::new(),
+            targets: IndexMap::new(),
         };
 
         Ok(Supfile {
             version: temp.version,
-            env: temp.env.unwrap_or(HashMap::new()),
+            env: temp.env.unwrap_or_default(),
             networks: temp.networks.unwrap_or(null_networks),
             commands,
             targets: temp.targets.unwrap_or(null_targets),
             desc: temp.desc.unwrap_or("no description".to_string()), 
+            shell_output: temp.shell_output.unwrap_or_default(),
+            secrets: temp.secrets,
+            dedup_targets: temp.dedup_targets.unwrap_or(false),
         })
     }
 }
 
 
 impl Supfile {
+    /// The commands target `name` runs, with nested targets expanded.
+    pub fn target_steps(&self, name: &str) -> Result<Vec<Target>, TargetCycle> {
+        self.targets.expand(name, |step| self.commands.contains_key(step), self.dedup_targets)
+    }
+
     pub fn get_command(&self, name: &str) -> Option<&Command>{
         self.commands.get(name)
     }
 }
 
+/// What is kept of the output of `$(cmd)` and `file:` values.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputPolicy {
+    /// drop control characters other than whitespace, then the trailing newline
+    Strip,
+    /// drop only the trailing newline
+    #[default]
+    Trim,
+    /// keep the output byte for byte
+    Raw,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct UploadEntry {
     pub src: String,
     pub dst: String,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct FetchEntry {
     pub src: String,
     pub dst: String,
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_declaration_order_is_kept() {
+        let yaml = "version: 0.5\nnetworks:\n  staging: {hosts: [a]}\n  prod: {hosts: [b]}\n  dev: {hosts: [c]}\ncommands:\n  zip: {run: z}\n  build: {run: b}\n  migrate: {run: m}\n";
+        let supfile: Supfile = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(supfile.networks.names, vec!["staging", "prod", "dev"]);
+        assert_eq!(supfile.networks.nets.keys().collect::<Vec<_>>(), vec!["staging", "prod", "dev"]);
+        assert_eq!(supfile.commands.keys().collect::<Vec<_>>(), vec!["zip", "build", "migrate"]);
+    }
+}
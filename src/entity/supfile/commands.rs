@@ -1,19 +1,39 @@
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use serde::{Deserialize, Deserializer, Serialize};
+use indexmap::IndexMap;
+use regex::Regex;
 use std::fmt;
 use crate::entity::supfile::UploadEntry;
 use crate::entity::supfile::FetchEntry;
+use crate::entity::env::{is_env_name, EnvList};
 
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct Command {
     pub desc: Option<String>,
     pub run: Option<String>,
     pub upload: Option<Vec<UploadEntry>>, 
     pub fetch: Option<FetchEntry>,
-    pub env: Option<HashMap<String, String>>, 
+    pub env: Option<EnvList>,
     pub local: Option<String>,
     pub stdin: Option<bool>,
+    /// run on this many hosts at a time
+    pub serial: Option<usize>,
+    /// run on the first host only
+    #[serde(default)]
+    pub once: bool,
+    /// variables that must be set, checked before any host is contacted
+    #[serde(default)]
+    pub requires_env: Vec<RequiredEnv>,
+    /// asked before any host is contacted, `{{network}}` and `{{command}}`
+    /// are replaced; see `usecase::prompts`
+    pub confirm: Option<String>,
+    /// values given on the command line, see `usecase::params`
+    #[serde(default)]
+    pub params: Vec<Param>,
+    /// shellcheck codes `ssup lint` skips for this command, `SC2086` or `2086`
+    #[serde(default, deserialize_with = "shellcheck_codes")]
+    pub shellcheck_ignore: Vec<u32>,
     #[serde(skip)]
     pub name: String,
 }
@@ -25,3 +45,181 @@ impl fmt::Display for Command {
         write!(f, "{}", description)
     }
 }
+
+/// An entry of `requires_env`: either a bare `NAME`, or `NAME: regex` when
+/// the value must also match the regex as a whole.
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct RequiredEnv {
+    pub name: String,
+    pub pattern: Option<String>,
+}
+
+impl RequiredEnv {
+    /// The pattern anchored at both ends; it was validated while parsing.
+    pub fn regex(&self) -> Option<Regex> {
+        self.pattern
+            .as_ref()
+            .map(|pattern| Regex::new(&format!("^(?:{})$", pattern)).expect("validated while parsing"))
+    }
+}
+
+impl<'de> Deserialize<'de> for RequiredEnv {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Name(String),
+            WithPattern(IndexMap<String, String>),
+        }
+
+        let (name, pattern) = match Raw::deserialize(deserializer)? {
+            Raw::Name(name) => (name, None),
+            Raw::WithPattern(map) if map.len() == 1 => {
+                let (name, pattern) = map.into_iter().next().expect("one entry");
+                (name, Some(pattern))
+            }
+            Raw::WithPattern(_) => {
+                return Err(serde::de::Error::custom("requires_env entries are NAME or NAME: regex, one per item"));
+            }
+        };
+        if let Some(pattern) = &pattern {
+            Regex::new(&format!("^(?:{})$", pattern))
+                .map_err(|e| serde::de::Error::custom(format!("requires_env {}: invalid regex: {}", name, e)))?;
+        }
+        Ok(RequiredEnv { name, pattern })
+    }
+}
+
+/// A parameter of a command, given on the command line as `NAME=value` or,
+/// in declaration order, after `--`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(try_from = "ParamSpec")]
+pub struct Param {
+    pub name: String,
+    pub default: Option<String>,
+    pub required: bool,
+    pub choices: Vec<String>,
+    /// the whole value must match
+    pub regex: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ParamSpec {
+    name: String,
+    default: Option<String>,
+    #[serde(default)]
+    required: bool,
+    #[serde(default)]
+    choices: Vec<String>,
+    regex: Option<String>,
+}
+
+impl TryFrom<ParamSpec> for Param {
+    type Error = String;
+
+    fn try_from(spec: ParamSpec) -> Result<Param, String> {
+        if !is_env_name(&spec.name) {
+            return Err(format!("param `{}`: the name must be a valid variable name", spec.name));
+        }
+        if let Some(regex) = &spec.regex {
+            Regex::new(&format!("^(?:{})$", regex)).map_err(|e| format!("param {}: invalid regex: {}", spec.name, e))?;
+        }
+        Ok(Param {
+            name: spec.name,
+            default: spec.default,
+            required: spec.required,
+            choices: spec.choices,
+            regex: spec.regex,
+        })
+    }
+}
+
+impl Param {
+    /// Why `value` is not acceptable, if it isn't.
+    pub fn reject(&self, value: &str) -> Option<String> {
+        if !self.choices.is_empty() && !self.choices.iter().any(|choice| choice == value) {
+            return Some(format!("`{}` must be one of {}, got `{}`", self.name, self.choices.join(", "), value));
+        }
+        let regex = self.regex.as_ref()?;
+        let matcher = Regex::new(&format!("^(?:{})$", regex)).expect("validated while parsing");
+        if matcher.is_match(value) {
+            return None;
+        }
+        Some(format!("`{}` does not match '{}', got `{}`", self.name, regex, value))
+    }
+}
+
+fn shellcheck_codes<'de, D>(deserializer: D) -> Result<Vec<u32>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Code {
+        Number(u32),
+        Name(String),
+    }
+
+    Vec::<Code>::deserialize(deserializer)?
+        .into_iter()
+        .map(|code| match code {
+            Code::Number(number) => Ok(number),
+            Code::Name(name) => name
+                .strip_prefix("SC")
+                .unwrap_or(&name)
+                .parse()
+                .map_err(|_| serde::de::Error::custom(format!("shellcheck_ignore: '{}' is not a code like SC2086", name))),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_requires_env_forms() {
+        let yaml = "run: ./deploy\nrequires_env:\n- DB_URL\n- RELEASE: 'v[0-9]+'\n";
+        let command: Command = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(command.requires_env[0], RequiredEnv { name: "DB_URL".to_string(), pattern: None });
+        let release = &command.requires_env[1];
+        assert_eq!(release.name, "RELEASE");
+        let regex = release.regex().unwrap();
+        assert!(regex.is_match("v12"));
+        assert!(!regex.is_match("v12-rc"), "the pattern must match the whole value");
+
+        let err = serde_yaml::from_str::<Command>("requires_env:\n- RELEASE: '(unclosed'\n").unwrap_err();
+        assert!(err.to_string().contains("requires_env RELEASE: invalid regex"), "{}", err);
+        assert!(serde_yaml::from_str::<Command>("{}").unwrap().requires_env.is_empty());
+    }
+
+    #[test]
+    fn test_params() {
+        let yaml = "run: ./deploy\nparams:\n- name: version\n  required: true\n  regex: 'v[0-9.]+'\n- name: stage\n  default: prod\n  choices: [prod, canary]\n";
+        let command: Command = serde_yaml::from_str(yaml).unwrap();
+        let (version, stage) = (&command.params[0], &command.params[1]);
+        assert!(version.required);
+        assert_eq!(stage.default.as_deref(), Some("prod"));
+        assert_eq!(version.reject("v1.2.3"), None);
+        assert_eq!(version.reject("v1.2.3-rc"), Some("`version` does not match 'v[0-9.]+', got `v1.2.3-rc`".to_string()));
+        assert_eq!(stage.reject("dev"), Some("`stage` must be one of prod, canary, got `dev`".to_string()));
+
+        let err = serde_yaml::from_str::<Command>("params:\n- name: version\n  regex: '(v'\n").unwrap_err();
+        assert!(err.to_string().contains("param version: invalid regex"), "{}", err);
+        let err = serde_yaml::from_str::<Command>("params:\n- name: my-version\n").unwrap_err();
+        assert!(err.to_string().contains("the name must be a valid variable name"), "{}", err);
+        assert!(serde_yaml::from_str::<Command>("params:\n- name: v\n  requird: true\n").is_err());
+    }
+
+    #[test]
+    fn test_shellcheck_ignore_codes() {
+        let command: Command = serde_yaml::from_str("run: x\nshellcheck_ignore: [SC2086, 2034, '1090']\n").unwrap();
+        assert_eq!(command.shellcheck_ignore, vec![2086, 2034, 1090]);
+        let err = serde_yaml::from_str::<Command>("shellcheck_ignore: [quotes]\n").unwrap_err();
+        assert!(err.to_string().contains("'quotes' is not a code like SC2086"), "{}", err);
+    }
+}
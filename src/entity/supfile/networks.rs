@@ -1,33 +1,53 @@
+use serde::de::{self, MapAccess, Visitor};
 use serde::{Deserialize, Deserializer, Serialize};
-use std::collections::HashMap;
+use indexmap::IndexMap;
 use std::fmt::{self};
+use crate::entity::env::EnvList;
+use crate::entity::const_values::{PASS_SEPARATOR, TAGS_START, TUBE_NAME_SEPARATOR};
+use crate::entity::secret::{Secret, REDACTED};
 
 
 
 #[derive(Debug,Serialize, Clone)]
 pub struct Networks {
     pub names: Vec<String>,
-    pub nets: HashMap<String, Network>
+    pub nets: IndexMap<String, Network>
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct Network {
+    #[serde(default)]
     pub hosts: Vec<HostEntry>,
-    pub env: Option<HashMap<String, String>>,
+    pub env: Option<EnvList>,
     pub inventory: Option<String>,
+    /// seconds to reuse cached `inventory` output, unset disables caching
+    pub inventory_ttl: Option<u64>,
+    /// seconds before a hanging `inventory` script is killed
+    pub inventory_timeout: Option<u64>,
+    /// static host list, either one ssup host string per line or Ansible INI
+    pub inventory_file: Option<String>,
+    /// `ssup` or `ansible`, guessed from the file when unset
+    pub inventory_format: Option<String>,
     pub bastion: Option<String>,
     pub user: Option<String>,
-    pub pass: Option<String>,
+    pub pass: Option<Secret>,
     pub id_file: Option<String>,
+    /// name of a network to inherit env, user, bastion, id_file, inventory,
+    /// inventory_file and protected from
+    pub extends: Option<String>,
+    /// every run against it must be confirmed, on the terminal or with `--yes`;
+    /// inherited through `extends` unless the network sets it itself
+    pub protected: Option<bool>,
     #[serde(skip)]
     pub name: String,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Serialize, Clone)]
 #[serde(untagged)]
 pub enum HostEntry {
     Simple(String),
-    Detailed(HostDetails),
+    Detailed(Box<HostDetails>),
 }
 
 
@@ -45,21 +65,24 @@ pub enum HostEntry {
 
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct HostDetails {
     pub host: String,
     pub user: Option<String>,
-    pub pass: Option<String>,
+    pub pass: Option<Secret>,
     pub tube: Option<String>,
-    pub env: Option<HashMap<String, String>>,
+    pub env: Option<EnvList>,
     #[serde(default)]
     pub sudo: bool,
+    #[serde(default)]
+    pub tags: Vec<String>,
 }
 
 impl HostDetails {
     #[allow(unused_imports)]
     #[allow(dead_code)]
     pub fn new(host: String) -> HostDetails {
-        HostDetails { host, user: None, pass: None, tube: None, env: None, sudo: false }
+        HostDetails { host, user: None, pass: None, tube: None, env: None, sudo: false, tags: Vec::new() }
     }
 }
 
@@ -69,7 +92,7 @@ impl fmt::Display for HostEntry {
             HostEntry::Simple(s) => {
                 // split on " | "
                 let parts = s.split(" | ").collect::<Vec<&str>>();
-                let header = parts.get(0).unwrap();
+                let header = parts.first().unwrap();
                 // and print out the header, dropping sensitive information
                 write!(f, "{}", header)
             },
@@ -78,6 +101,58 @@ impl fmt::Display for HostEntry {
     }
 }
 
+/// `Simple` hosts may carry a password after ` | `, it is redacted.
+impl fmt::Debug for HostEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HostEntry::Simple(s) => f.debug_tuple("Simple").field(&redact_host_string(s)).finish(),
+            HostEntry::Detailed(details) => f.debug_tuple("Detailed").field(details).finish(),
+        }
+    }
+}
+
+/// Replaces the password of a `host | pass << tube [tags]` string with `****`.
+pub fn redact_host_string(host: &str) -> String {
+    let Some((header, rest)) = host.split_once(PASS_SEPARATOR) else {
+        return host.to_string();
+    };
+    let pass_end = [TUBE_NAME_SEPARATOR, TAGS_START]
+        .iter()
+        .filter_map(|separator| rest.find(separator))
+        .min()
+        .unwrap_or(rest.len());
+    format!("{}{}{}{}", header, PASS_SEPARATOR, REDACTED, &rest[pass_end..])
+}
+
+/// A string or a map, like `#[serde(untagged)]` would accept, but errors
+/// inside the map, such as a misspelled key, are reported as they are.
+impl<'de> Deserialize<'de> for HostEntry {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct HostEntryVisitor;
+
+        impl<'de> Visitor<'de> for HostEntryVisitor {
+            type Value = HostEntry;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a host string or a map with `host:`")
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<HostEntry, E> {
+                Ok(HostEntry::Simple(value.to_string()))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<HostEntry, A::Error> {
+                HostDetails::deserialize(de::value::MapAccessDeserializer::new(map)).map(|details| HostEntry::Detailed(Box::new(details)))
+            }
+        }
+
+        deserializer.deserialize_any(HostEntryVisitor)
+    }
+}
+
 impl fmt::Display for HostDetails {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "HostDetails {{ host: {:?}, pass: {:?}, tube: {:?}, env: {:?} }}", 
@@ -90,20 +165,75 @@ impl<'de> Deserialize<'de> for Networks {
     where
         D: Deserializer<'de>,
     {   
-        let map: HashMap<String, Network> = Deserialize::deserialize(deserializer)?;
-        let mut nets: HashMap<String, Network> = HashMap::new();
+        let map: IndexMap<String, Network> = Deserialize::deserialize(deserializer)?;
+        let mut nets: IndexMap<String, Network> = IndexMap::new();
         let mut names = Vec::new();
 
-        for (name, mut network) in map {
+        for name in map.keys() {
+            let mut network = resolve_extends(name, &map, &mut Vec::new()).map_err(serde::de::Error::custom)?;
             network.name = name.clone();
             nets.insert(name.clone(), network);
-            names.push(name);
+            names.push(name.clone());
         }
 
         Ok(Networks{ names, nets })
     }
 }
 
+/// Returns the network with everything it `extends:` folded in, base first.
+fn resolve_extends(name: &str, raw: &IndexMap<String, Network>, chain: &mut Vec<String>) -> Result<Network, String> {
+    if chain.iter().any(|seen| seen == name) {
+        chain.push(name.to_string());
+        return Err(format!("network inheritance cycle: {}", chain.join(" -> ")));
+    }
+    let network = match raw.get(name) {
+        Some(network) => network.clone(),
+        None => {
+            let child = chain.last().cloned().unwrap_or_default();
+            return Err(format!("network '{}' extends unknown network '{}'", child, name));
+        }
+    };
+    let base_name = match &network.extends {
+        Some(base_name) => base_name.clone(),
+        None => return Ok(network),
+    };
+
+    chain.push(name.to_string());
+    let base = resolve_extends(&base_name, raw, chain)?;
+    chain.pop();
+
+    let env = match (base.env, network.env) {
+        (Some(mut env), Some(own)) => {
+            env.extend(&own);
+            Some(env)
+        }
+        (base_env, own) => own.or(base_env),
+    };
+    let inherits_inventory = network.inventory.is_none();
+    let inherits_inventory_file = network.inventory_file.is_none();
+
+    Ok(Network {
+        env,
+        user: network.user.or(base.user),
+        bastion: network.bastion.or(base.bastion),
+        id_file: network.id_file.or(base.id_file),
+        inventory: network.inventory.or(base.inventory),
+        inventory_ttl: network.inventory_ttl.or(if inherits_inventory { base.inventory_ttl } else { None }),
+        inventory_timeout: network.inventory_timeout.or(if inherits_inventory { base.inventory_timeout } else { None }),
+        inventory_file: network.inventory_file.or(base.inventory_file),
+        inventory_format: network.inventory_format.or(if inherits_inventory_file { base.inventory_format } else { None }),
+        protected: network.protected.or(base.protected),
+        ..network
+    })
+}
+
+impl Network {
+    /// Whether runs against it need a confirmation, see `protected`.
+    pub fn is_protected(&self) -> bool {
+        self.protected.unwrap_or(false)
+    }
+}
+
 impl Networks {
     pub fn get(&self, name: &str) -> Option<&Network> {
         self.nets.get(name)
@@ -118,9 +248,15 @@ impl Networks {
     pub fn new( name: &str, network: &Network) -> Networks {
         let hosts = network.hosts.clone();
         let network = Network {
-            hosts: hosts,
+            hosts,
             env: None,
             inventory: None,
+            inventory_ttl: None,
+            inventory_timeout: None,
+            inventory_file: None,
+            inventory_format: None,
+            extends: None,
+            protected: None,
             bastion: None,
             user: None,
             pass: None,
@@ -129,7 +265,7 @@ impl Networks {
         };
         let mut networks = Networks {
             names: Vec::new(),
-            nets: HashMap::new(),
+            nets: IndexMap::new(),
         };
         networks.add_network(name.to_string(), network);
         networks
@@ -149,4 +285,103 @@ impl Networks {
 }
 
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env_of(network: &Network) -> EnvList {
+        network.env.clone().unwrap_or_default()
+    }
+
+    #[test]
+    fn test_extends_inherits_and_overrides() {
+        let yaml = r#"
+base:
+  user: deploy
+  bastion: jump.example.com
+  inventory: cat hosts.txt
+  inventory_ttl: 60
+  env:
+    REGION: eu
+    LOG: info
+staging:
+  extends: base
+  hosts: [staging1]
+  env:
+    LOG: debug
+prod:
+  extends: staging
+  user: root
+"#;
+        let networks: Networks = serde_yaml::from_str(yaml).unwrap();
+        let staging = networks.get("staging").unwrap();
+        assert_eq!(staging.user.as_deref(), Some("deploy"));
+        assert_eq!(staging.bastion.as_deref(), Some("jump.example.com"));
+        assert_eq!(staging.inventory.as_deref(), Some("cat hosts.txt"));
+        assert_eq!(staging.inventory_ttl, Some(60));
+        assert_eq!(staging.hosts.len(), 1);
+        assert_eq!(env_of(staging).get("REGION"), Some("eu"));
+        assert_eq!(env_of(staging).get("LOG"), Some("debug"));
+
+        let prod = networks.get("prod").unwrap();
+        assert_eq!(prod.name, "prod");
+        assert_eq!(prod.user.as_deref(), Some("root"));
+        assert_eq!(prod.bastion.as_deref(), Some("jump.example.com"));
+        assert_eq!(env_of(prod).get("LOG"), Some("debug"));
+        assert!(prod.hosts.is_empty());
+    }
+
+    #[test]
+    fn test_extends_inherits_inventory_file_and_protected() {
+        let yaml = r#"
+prod:
+  protected: true
+  inventory_file: hosts.ini
+  inventory_format: ansible
+prod-eu:
+  extends: prod
+prod-canary:
+  extends: prod
+  protected: false
+  inventory_file: canary.txt
+"#;
+        let networks: Networks = serde_yaml::from_str(yaml).unwrap();
+        let eu = networks.get("prod-eu").unwrap();
+        assert_eq!(eu.inventory_file.as_deref(), Some("hosts.ini"));
+        assert_eq!(eu.inventory_format.as_deref(), Some("ansible"));
+        assert!(eu.is_protected());
+
+        let canary = networks.get("prod-canary").unwrap();
+        assert_eq!(canary.inventory_file.as_deref(), Some("canary.txt"));
+        assert_eq!(canary.inventory_format, None, "the format goes with the file it describes");
+        assert!(!canary.is_protected(), "a network can opt out of the protection it inherits");
+        assert!(!serde_yaml::from_str::<Network>("hosts: []").unwrap().is_protected());
+    }
+
+    #[test]
+    fn test_extends_cycle_is_rejected() {
+        let yaml = "a:\n  extends: b\nb:\n  extends: c\nc:\n  extends: a\n";
+        let err = serde_yaml::from_str::<Networks>(yaml).unwrap_err().to_string();
+        assert!(err.contains("network inheritance cycle"), "{}", err);
+    }
+
+    #[test]
+    fn test_extends_unknown_base_is_rejected() {
+        let err = serde_yaml::from_str::<Networks>("a:\n  extends: nope\n").unwrap_err().to_string();
+        assert!(err.contains("network 'a' extends unknown network 'nope'"), "{}", err);
+    }
+
+    #[test]
+    fn test_passwords_are_redacted() {
+        assert_eq!(redact_host_string("root@10.0.0.1 | hunter2 << tube [role=web]"), "root@10.0.0.1 | **** << tube [role=web]");
+        assert_eq!(redact_host_string("root@10.0.0.1 | hunter2"), "root@10.0.0.1 | ****");
+        assert_eq!(redact_host_string("root@10.0.0.1 << tube"), "root@10.0.0.1 << tube");
 
+        let yaml = "hosts:\n- root@10.0.0.1 | hunter2\n- host: root@10.0.0.2\n  pass: hunter3\npass: hunter4\n";
+        let network: Network = serde_yaml::from_str(yaml).unwrap();
+        let dump = format!("{:?}", network);
+        assert!(!dump.contains("hunter"), "{}", dump);
+        assert!(!network.hosts[1].to_string().contains("hunter"));
+        assert_eq!(network.pass.as_ref().map(|pass| pass.expose()), Some("hunter4"));
+    }
+}
@@ -1,9 +1,9 @@
+use serde::de::{self, DeserializeSeed, MapAccess, SeqAccess, Visitor};
 use serde::{Deserialize, Deserializer};
-use serde_yaml::{self, Mapping, Value};
-use std::collections::HashMap;
+use indexmap::IndexMap;
 use std::fmt;
-
-use super::networks::Network;
+use thiserror::Error;
+use crate::entity::env::{is_env_name, split_words, EnvList};
 
 // #[derive(Debug, Deserialize, Clone)]
 // #[derive(serde::Serialize)]
@@ -13,18 +13,80 @@ use super::networks::Network;
 //     pub command_name: String,
 // }
 
-#[derive(Debug, Deserialize, Clone)]
+/// One step of a target line: `COMMAND [NETWORK] [KEY=VAL...] [--serial N] [--once]`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
 #[derive(serde::Serialize)]
 pub struct Target {
     pub command: String,
     pub affixed_network: String,
+    /// exported on top of the command's env for this step only
+    pub env: EnvList,
+    /// run on this many hosts at a time
+    pub serial: Option<usize>,
+    /// run on the first host only
+    pub once: bool,
+}
+
+const SERIAL_FLAG: &str = "--serial";
+const ONCE_FLAG: &str = "--once";
+
+impl Target {
+    /// Parses a target line, rejecting anything but the options above.
+    /// Words are split like the shell would, so `MSG="hello world"` is one pair.
+    pub fn parse(line: &str) -> Result<Target, String> {
+        let words = split_words(line).map_err(|quote| format!("unclosed {} quote", quote))?;
+        let mut words = words.iter().map(String::as_str).peekable();
+        let mut target = Target {
+            command: words.next().ok_or("no command given")?.to_string(),
+            ..Target::default()
+        };
+        if let Some(network) = words.next_if(|word| !word.starts_with("--") && !word.contains('=')) {
+            target.affixed_network = network.to_string();
+        }
+
+        while let Some(word) = words.next() {
+            match word {
+                SERIAL_FLAG => {
+                    let count = words.next().ok_or("--serial needs a host count")?;
+                    match count.parse::<usize>() {
+                        Ok(count) if count > 0 => target.serial = Some(count),
+                        _ => return Err(format!("--serial needs a positive host count, got `{}`", count)),
+                    }
+                }
+                ONCE_FLAG => target.once = true,
+                flag if flag.starts_with("--") => return Err(format!("unknown option `{}`", flag)),
+                pair => {
+                    let (key, value) = pair.split_once('=').ok_or_else(|| format!("unexpected `{}`", pair))?;
+                    if !is_env_name(key) {
+                        return Err(format!("`{}` is not a valid variable name", key));
+                    }
+                    target.env.set(key.to_string(), value.to_string());
+                }
+            }
+        }
+        Ok(target)
+    }
+
+    /// This step nested in `outer`: the outer network, env and options fill
+    /// in what the step doesn't set itself.
+    fn within(&self, outer: &Target) -> Target {
+        let mut env = outer.env.clone();
+        env.extend(&self.env);
+        Target {
+            command: self.command.clone(),
+            affixed_network: if self.affixed_network.is_empty() { outer.affixed_network.clone() } else { self.affixed_network.clone() },
+            env,
+            serial: self.serial.or(outer.serial),
+            once: self.once || outer.once,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 #[derive(serde::Serialize)]
 pub struct Targets {
     pub names: Vec<String>,
-    pub targets: HashMap<String, Vec<Target>>,
+    pub targets: IndexMap<String, Vec<Target>>,
 }
 
 impl<'de> Deserialize<'de> for Targets {
@@ -32,78 +94,140 @@ impl<'de> Deserialize<'de> for Targets {
     where
         D: Deserializer<'de>,
     {
-        let value: Value = Deserialize::deserialize(deserializer)?;
-        // dbg!(value.clone());
-
-        let targets_tpl: HashMap<String, Vec<String>> =
-            serde_yaml::from_value(value.clone()).map_err(serde::de::Error::custom)?;
-
-        let mut names = Vec::with_capacity(targets_tpl.len());
-        let mut targets_final = HashMap::<String, Vec<Target>>::new();
-        
-        // targets
-        for (key, lines) in targets_tpl.clone() {
-            names.push(key.clone());
-            let mut targets_generated: Vec<Target> = Vec::new();
-            let mut command_name = String::new();
-            // lines 
-            for line in lines.iter() {
-                let parts: Vec<&str> = line.split_whitespace().collect();
-                let mut mapping: Target = Target {
-                    command: "".to_string(),
-                    affixed_network: "".to_string(),
-                };
-
-                if parts.len() == 2 {
-                    command_name = parts[0].to_string();
-                    let affixed_network = parts[1].to_string();
-
-                    mapping = Target {
-                        affixed_network: affixed_network.clone(),
-                        command: command_name,
-                    };
-
-                } else if parts.len() == 1 {
-                    command_name = parts[0].to_string(); // incorrect
-                    mapping = Target {
-                        affixed_network: "".to_string(),
-                        command: command_name,
-                    };
-                } else {
-                    println!("incorrect number of fields when splitting target mapping");
-                    std::process::exit(2);
+        struct TargetsVisitor;
+
+        impl<'de> Visitor<'de> for TargetsVisitor {
+            type Value = Targets;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a map of target names to lists of steps")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Targets, A::Error> {
+                let mut names = Vec::new();
+                let mut targets = IndexMap::new();
+                while let Some(key) = map.next_key::<String>()? {
+                    let steps = map.next_value_seed(Steps { target: &key })?;
+                    if targets.insert(key.clone(), steps).is_none() {
+                        names.push(key);
+                    }
                 }
+                Ok(Targets { names, targets })
+            }
+        }
+
+        deserializer.deserialize_map(TargetsVisitor)
+    }
+}
 
-                targets_generated.insert(0, mapping);
-            } // dns lines
-            targets_final.insert(key, targets_generated);
-        } // end targets
+/// The lines of one target. Each is parsed while it is read, so an error
+/// carries the location of that line.
+struct Steps<'a> {
+    target: &'a str,
+}
 
+impl<'de> DeserializeSeed<'de> for Steps<'_> {
+    type Value = Vec<Target>;
 
-        Ok(Targets {
-            names,
-            targets: targets_final,
-        })
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Vec<Target>, D::Error> {
+        deserializer.deserialize_seq(self)
     }
 }
 
+impl<'de> Visitor<'de> for Steps<'_> {
+    type Value = Vec<Target>;
 
-impl Targets {
-    pub fn get(&self, name: &str) -> Vec<Target> {
-        let result = match self.targets.get(name) {
-            None => {
-                println!("no target found");
-                std::process::exit(1);
-            },
-            Some(target) => target.clone(),
-        };
-        return result;
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a list of target lines")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<Target>, A::Error> {
+        let mut steps = Vec::new();
+        while let Some(step) = seq.next_element_seed(Step { target: self.target })? {
+            steps.push(step);
+        }
+        Ok(steps)
+    }
+}
+
+struct Step<'a> {
+    target: &'a str,
+}
+
+impl<'de> DeserializeSeed<'de> for Step<'_> {
+    type Value = Target;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Target, D::Error> {
+        deserializer.deserialize_str(self)
+    }
+}
+
+impl Visitor<'_> for Step<'_> {
+    type Value = Target;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a target line")
+    }
+
+    fn visit_str<E: de::Error>(self, line: &str) -> Result<Target, E> {
+        Target::parse(line).map_err(|problem| {
+            E::custom(format!(
+                "target {}: '{}' must be COMMAND [NETWORK] [KEY=VAL...] [--serial N] [--once]: {}",
+                self.target, line, problem
+            ))
+        })
     }
+}
 
+
+impl Targets {
     pub fn has(&self, name: &str) -> bool {
         self.targets.contains_key(name)
     }
 
+    /// The steps of target `name`, where a step naming another target (and
+    /// no command, see `is_command`) is replaced by that target's steps,
+    /// recursively. The network, env and options of such a step are the
+    /// defaults for the nested steps. With `dedup`, a step identical to one
+    /// that already ran is dropped. Unknown targets have no steps.
+    pub fn expand(&self, name: &str, is_command: impl Fn(&str) -> bool, dedup: bool) -> Result<Vec<Target>, TargetCycle> {
+        let mut steps = Vec::new();
+        self.expand_into(name, &Target::default(), &is_command, &mut vec![name.to_string()], &mut steps)?;
+        if dedup {
+            let mut seen = Vec::new();
+            steps.retain(|step: &Target| {
+                let first = !seen.contains(step);
+                seen.push(step.clone());
+                first
+            });
+        }
+        Ok(steps)
+    }
+
+    fn expand_into(
+        &self,
+        name: &str,
+        outer: &Target,
+        is_command: &impl Fn(&str) -> bool,
+        chain: &mut Vec<String>,
+        steps: &mut Vec<Target>,
+    ) -> Result<(), TargetCycle> {
+        for step in self.targets.get(name).into_iter().flatten() {
+            let step = step.within(outer);
+            if is_command(&step.command) || !self.has(&step.command) {
+                steps.push(step);
+                continue;
+            }
+            if chain.contains(&step.command) {
+                chain.push(step.command.clone());
+                return Err(TargetCycle { chain: chain.clone() });
+            }
+            chain.push(step.command.clone());
+            self.expand_into(&step.command, &step, is_command, chain, steps)?;
+            chain.pop();
+        }
+        Ok(())
+    }
 
     // pub fn has_affixes(&self) -> bool {
     //     !self.affixes.is_empty()
@@ -114,6 +238,13 @@ impl Targets {
     }
 }
 
+/// A target that, through its nested targets, includes itself.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("target cycle: {}", chain.join(" -> "))]
+pub struct TargetCycle {
+    pub chain: Vec<String>,
+}
+
 impl fmt::Display for Targets {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
@@ -122,4 +253,114 @@ impl fmt::Display for Targets {
             self.names, self.targets,
         )
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_steps_keep_the_written_order() {
+        let targets: Targets = serde_yaml::from_str("release:\n- build\n- deploy prod\n- notify\nall:\n- release\n").unwrap();
+        assert_eq!(targets.names, vec!["release", "all"]);
+        let steps: Vec<(String, String)> = targets.targets["release"].iter().cloned().map(|t| (t.command, t.affixed_network)).collect();
+        assert_eq!(
+            steps,
+            vec![
+                ("build".to_string(), String::new()),
+                ("deploy".to_string(), "prod".to_string()),
+                ("notify".to_string(), String::new()),
+            ]
+        );
+    }
+
+    fn expanded(targets: &Targets, name: &str, dedup: bool) -> Vec<String> {
+        let commands = ["build", "test", "migrate", "restart", "notify"];
+        targets
+            .expand(name, |step| commands.contains(&step), dedup)
+            .unwrap()
+            .into_iter()
+            .map(|step| format!("{} {}", step.command, step.affixed_network).trim_end().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_nested_targets_expand_recursively() {
+        let yaml = "ci: [build, test]\nship:\n- ci\n- migrate db\n- restart\nrelease:\n- ship web\n- ci\n- notify\nbuild: [notify]\n";
+        let targets: Targets = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(expanded(&targets, "release", false), vec!["build web", "test web", "migrate db", "restart web", "build", "test", "notify"]);
+        assert_eq!(expanded(&targets, "ship", false), vec!["build", "test", "migrate db", "restart"]);
+        assert_eq!(expanded(&targets, "build", false), vec!["notify"], "a command of the same name wins inside targets");
+        assert!(expanded(&targets, "nope", false).is_empty());
+
+        let targets: Targets = serde_yaml::from_str("qa: [test]\nrelease: [qa, build, qa, test, notify]\n").unwrap();
+        assert_eq!(expanded(&targets, "release", false), vec!["test", "build", "test", "test", "notify"]);
+        assert_eq!(expanded(&targets, "release", true), vec!["test", "build", "notify"]);
+    }
+
+    #[test]
+    fn test_step_options() {
+        let step = Target::parse("restart web SERVICE=api PORT=8080 --serial 2 --once").unwrap();
+        assert_eq!((step.command.as_str(), step.affixed_network.as_str()), ("restart", "web"));
+        assert_eq!(step.env.get("SERVICE"), Some("api"));
+        assert_eq!(step.env.get("PORT"), Some("8080"));
+        assert_eq!((step.serial, step.once), (Some(2), true));
+
+        let step = Target::parse("restart SERVICE=worker").unwrap();
+        assert_eq!(step.affixed_network, "");
+        assert_eq!(step.env.get("SERVICE"), Some("worker"));
+
+        assert_eq!(Target::parse("restart web --serial 0").unwrap_err(), "--serial needs a positive host count, got `0`");
+        assert_eq!(Target::parse("restart web --serial").unwrap_err(), "--serial needs a host count");
+        assert_eq!(Target::parse("restart web --parallel").unwrap_err(), "unknown option `--parallel`");
+        assert_eq!(Target::parse("restart web worker").unwrap_err(), "unexpected `worker`");
+        assert_eq!(Target::parse("restart 1X=a").unwrap_err(), "`1X` is not a valid variable name");
+
+        let err = serde_yaml::from_str::<Targets>("release:\n- restart web --once --fast\n").unwrap_err();
+        assert!(err.to_string().contains("target release: 'restart web --once --fast' must be COMMAND [NETWORK] [KEY=VAL...] [--serial N] [--once]: unknown option `--fast`"), "{}", err);
+    }
+
+    #[test]
+    fn test_quoted_step_values() {
+        let step = Target::parse(r#"restart prod MSG="hello world" NOTE='it is "done"' EMPTY="" KEY="x""#).unwrap();
+        assert_eq!(step.affixed_network, "prod");
+        assert_eq!(step.env.get("MSG"), Some("hello world"));
+        assert_eq!(step.env.get("NOTE"), Some(r#"it is "done""#));
+        assert_eq!(step.env.get("EMPTY"), Some(""));
+        assert_eq!(step.env.get("KEY"), Some("x"));
+        assert_eq!(Target::parse(r#"restart MSG="open"#).unwrap_err(), "unclosed \" quote");
+
+        let targets: Targets = serde_yaml::from_str("notify:\n- 'restart prod MSG=\"hello world\" --once'\n").unwrap();
+        assert_eq!(targets.targets["notify"][0].env.get("MSG"), Some("hello world"));
+    }
+
+    #[test]
+    fn test_step_errors_point_at_their_line() {
+        let yaml = "version: 0.5\ntargets:\n  release:\n  - build\n  - restart web --fast\n";
+        let err = serde_yaml::from_str::<crate::entity::supfile::Supfile>(yaml).expect_err("must fail");
+        let location = err.location().expect("the error has a location");
+        assert_eq!((location.line(), location.column()), (5, 5));
+    }
+
+    #[test]
+    fn test_nested_steps_inherit_options() {
+        let yaml = "restarts:\n- restart SERVICE=api\n- restart web SERVICE=worker --serial 1\nrelease:\n- restarts prod REGION=eu --serial 3 --once\n";
+        let targets: Targets = serde_yaml::from_str(yaml).unwrap();
+        let steps = targets.expand("release", |step| step == "restart", true).unwrap();
+        assert_eq!(steps.len(), 2, "steps differing in env are not duplicates");
+        assert_eq!((steps[0].affixed_network.as_str(), steps[0].serial, steps[0].once), ("prod", Some(3), true));
+        assert_eq!((steps[1].affixed_network.as_str(), steps[1].serial, steps[1].once), ("web", Some(1), true));
+        assert_eq!(steps[0].env.get("SERVICE"), Some("api"));
+        assert_eq!(steps[1].env.get("SERVICE"), Some("worker"));
+        assert_eq!(steps[1].env.get("REGION"), Some("eu"));
+    }
+
+    #[test]
+    fn test_target_cycles_are_rejected() {
+        let targets: Targets = serde_yaml::from_str("a: [b]\nb: [notify, c]\nc: [a]\n").unwrap();
+        let err = targets.expand("a", |step| step == "notify", false).unwrap_err();
+        assert_eq!(err.to_string(), "target cycle: a -> b -> c -> a");
+        let targets: Targets = serde_yaml::from_str("loop: [notify, loop]\n").unwrap();
+        assert!(targets.expand("loop", |step| step == "notify", false).is_err());
+    }
+}
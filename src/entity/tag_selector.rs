@@ -0,0 +1,230 @@
+use std::fmt;
+use thiserror::Error;
+
+/// Boolean expression over host tags, as given to `--tags`.
+///
+/// Grammar, loosest binding first:
+///
+/// ```text
+/// or   := and ( ('|' | "or") and )*
+/// and  := not ( (',' | '&' | "and") not )*
+/// not  := ('!' | "not") not | atom
+/// atom := '(' or ')' | TAG
+/// ```
+///
+/// A `key=value` atom matches that exact tag, a bare `key` matches the tag
+/// `key` as well as any `key=...` tag.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TagSelector {
+    Tag(String),
+    Not(Box<TagSelector>),
+    And(Box<TagSelector>, Box<TagSelector>),
+    Or(Box<TagSelector>, Box<TagSelector>),
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum TagSelectorError {
+    #[error("empty tag selector")]
+    Empty,
+    #[error("unexpected '{found}' at position {pos} in tag selector")]
+    Unexpected { found: String, pos: usize },
+    #[error("unclosed '(' in tag selector")]
+    Unclosed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Tag(String),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Tag(tag) => write!(f, "{}", tag),
+            Token::And => write!(f, "and"),
+            Token::Or => write!(f, "or"),
+            Token::Not => write!(f, "not"),
+            Token::Open => write!(f, "("),
+            Token::Close => write!(f, ")"),
+        }
+    }
+}
+
+impl TagSelector {
+    pub fn parse(input: &str) -> Result<TagSelector, TagSelectorError> {
+        let tokens = tokenize(input);
+        if tokens.is_empty() {
+            return Err(TagSelectorError::Empty);
+        }
+        let mut parser = Parser { tokens, pos: 0 };
+        let selector = parser.parse_or()?;
+        if let Some(token) = parser.peek() {
+            return Err(TagSelectorError::Unexpected { found: token.to_string(), pos: parser.pos });
+        }
+        Ok(selector)
+    }
+
+    pub fn matches(&self, tags: &[String]) -> bool {
+        match self {
+            TagSelector::Tag(wanted) => tags.iter().any(|tag| {
+                tag == wanted || (!wanted.contains('=') && tag.split_once('=').map(|(key, _)| key) == Some(wanted.as_str()))
+            }),
+            TagSelector::Not(inner) => !inner.matches(tags),
+            TagSelector::And(left, right) => left.matches(tags) && right.matches(tags),
+            TagSelector::Or(left, right) => left.matches(tags) || right.matches(tags),
+        }
+    }
+}
+
+fn tokenize(input: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut word = String::new();
+
+    fn flush(word: &mut String, tokens: &mut Vec<Token>) {
+        if word.is_empty() {
+            return;
+        }
+        let token = match word.as_str() {
+            "and" | "AND" => Token::And,
+            "or" | "OR" => Token::Or,
+            "not" | "NOT" => Token::Not,
+            _ => Token::Tag(word.clone()),
+        };
+        tokens.push(token);
+        word.clear();
+    }
+
+    for c in input.chars() {
+        let symbol = match c {
+            ',' | '&' => Some(Token::And),
+            '|' => Some(Token::Or),
+            '!' => Some(Token::Not),
+            '(' => Some(Token::Open),
+            ')' => Some(Token::Close),
+            _ => None,
+        };
+        if let Some(symbol) = symbol {
+            flush(&mut word, &mut tokens);
+            tokens.push(symbol);
+        } else if c.is_whitespace() {
+            flush(&mut word, &mut tokens);
+        } else {
+            word.push(c);
+        }
+    }
+    flush(&mut word, &mut tokens);
+    tokens
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    fn parse_or(&mut self) -> Result<TagSelector, TagSelectorError> {
+        let mut left = self.parse_and()?;
+        while self.eat(&Token::Or) {
+            let right = self.parse_and()?;
+            left = TagSelector::Or(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> Result<TagSelector, TagSelectorError> {
+        let mut left = self.parse_not()?;
+        while self.eat(&Token::And) {
+            let right = self.parse_not()?;
+            left = TagSelector::And(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_not(&mut self) -> Result<TagSelector, TagSelectorError> {
+        if self.eat(&Token::Not) {
+            return Ok(TagSelector::Not(Box::new(self.parse_not()?)));
+        }
+        self.parse_atom()
+    }
+
+    fn parse_atom(&mut self) -> Result<TagSelector, TagSelectorError> {
+        let pos = self.pos;
+        match self.tokens.get(pos).cloned() {
+            Some(Token::Tag(tag)) => {
+                self.pos += 1;
+                Ok(TagSelector::Tag(tag))
+            }
+            Some(Token::Open) => {
+                self.pos += 1;
+                let inner = self.parse_or()?;
+                if !self.eat(&Token::Close) {
+                    return Err(TagSelectorError::Unclosed);
+                }
+                Ok(inner)
+            }
+            Some(token) => Err(TagSelectorError::Unexpected { found: token.to_string(), pos }),
+            None => Err(TagSelectorError::Unexpected { found: "end of input".to_string(), pos }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(list: &[&str]) -> Vec<String> {
+        list.iter().map(|t| t.to_string()).collect()
+    }
+
+    #[test]
+    fn test_comma_is_and() {
+        let selector = TagSelector::parse("role=web,az=b").unwrap();
+        assert!(selector.matches(&tags(&["role=web", "az=b"])));
+        assert!(!selector.matches(&tags(&["role=web", "az=a"])));
+    }
+
+    #[test]
+    fn test_or_not_and_precedence() {
+        let selector = TagSelector::parse("role=web | role=api, !canary").unwrap();
+        assert!(selector.matches(&tags(&["role=web", "canary"])));
+        assert!(selector.matches(&tags(&["role=api"])));
+        assert!(!selector.matches(&tags(&["role=api", "canary"])));
+
+        let grouped = TagSelector::parse("(role=web or role=api) and not canary").unwrap();
+        assert!(!grouped.matches(&tags(&["role=web", "canary"])));
+        assert!(grouped.matches(&tags(&["role=web"])));
+    }
+
+    #[test]
+    fn test_bare_key_matches_any_value() {
+        let selector = TagSelector::parse("az").unwrap();
+        assert!(selector.matches(&tags(&["az=b"])));
+        assert!(selector.matches(&tags(&["az"])));
+        assert!(!selector.matches(&tags(&["azure=yes"])));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(TagSelector::parse("  "), Err(TagSelectorError::Empty));
+        assert_eq!(TagSelector::parse("(role=web"), Err(TagSelectorError::Unclosed));
+        assert!(matches!(TagSelector::parse("role=web,"), Err(TagSelectorError::Unexpected { .. })));
+        assert!(matches!(TagSelector::parse("a b"), Err(TagSelectorError::Unexpected { .. })));
+    }
+}
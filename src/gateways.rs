@@ -1 +1,7 @@
-pub mod logger;
\ No newline at end of file
+pub mod logger;
+pub mod shell;
+pub mod inventory_cache;
+pub mod masked_writer;
+pub mod secrets_file;
+pub mod shellcheck;
+pub mod terminal;
@@ -0,0 +1,141 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+use crate::gateways::logger::logger_func as l;
+
+/// On-disk cache for the output of `inventory:` scripts.
+///
+/// Entries are keyed by network name and a hash of the script together with
+/// the env it runs with, so editing either invalidates the cached hosts.
+pub struct InventoryCache {
+    dir: PathBuf,
+}
+
+impl InventoryCache {
+    pub fn new(dir: PathBuf) -> Self {
+        InventoryCache { dir }
+    }
+
+    /// `$XDG_CACHE_HOME/ssup/inventory`, falling back to `~/.cache/ssup/inventory`.
+    pub fn default_location() -> Self {
+        let base = match std::env::var("XDG_CACHE_HOME") {
+            Ok(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => {
+                let home = std::env::var("HOME").unwrap_or_else(|_| ".".to_string());
+                PathBuf::from(home).join(".cache")
+            }
+        };
+        InventoryCache::new(base.join("ssup").join("inventory"))
+    }
+
+    /// The hash is FNV-1a, so keys stay the same across Rust releases and a
+    /// cache written by one build of ssup is still found by the next one.
+    pub fn key(network_name: &str, script: &str, env: &BTreeMap<&String, &String>) -> String {
+        let mut hasher = Fnv1a::new();
+        hasher.write_field(script);
+        for (name, value) in env {
+            hasher.write_field(name);
+            hasher.write_field(value);
+        }
+        format!("{}-{:016x}", sanitize(network_name), hasher.0)
+    }
+
+    /// Returns the cached output when the entry is younger than `ttl`.
+    pub fn load(&self, key: &str, ttl: Duration) -> Option<Vec<u8>> {
+        let path = self.dir.join(key);
+        let modified = fs::metadata(&path).and_then(|m| m.modified()).ok()?;
+        let age = SystemTime::now().duration_since(modified).unwrap_or_default();
+        if age > ttl {
+            l(&format!("inventory cache: {} is stale ({}s old)", key, age.as_secs()));
+            return None;
+        }
+        l(&format!("inventory cache: hit for {}", key));
+        fs::read(path).ok()
+    }
+
+    pub fn store(&self, key: &str, data: &[u8]) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        // write to a temp file first so a concurrent run never reads half an entry
+        let tmp = self.dir.join(format!(".{}.tmp", key));
+        fs::write(&tmp, data)?;
+        fs::rename(tmp, self.dir.join(key))
+    }
+}
+
+/// 64-bit FNV-1a.
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    fn new() -> Self {
+        Fnv1a(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= u64::from(byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    /// Length-prefixed, so `("ab", "c")` and `("a", "bc")` hash differently.
+    fn write_field(&mut self, field: &str) {
+        self.write(&(field.len() as u64).to_le_bytes());
+        self.write(field.as_bytes());
+    }
+}
+
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_cache(name: &str) -> InventoryCache {
+        let dir = std::env::temp_dir().join(format!("ssup-inventory-cache-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        InventoryCache::new(dir)
+    }
+
+    #[test]
+    fn test_key_depends_on_script_and_env() {
+        let empty = BTreeMap::new();
+        let (k, v) = ("REGION".to_string(), "eu".to_string());
+        let env = BTreeMap::from([(&k, &v)]);
+        let a = InventoryCache::key("prod", "echo a", &empty);
+        assert_eq!(a, InventoryCache::key("prod", "echo a", &empty));
+        assert_ne!(a, InventoryCache::key("prod", "echo b", &empty));
+        assert_ne!(a, InventoryCache::key("prod", "echo a", &env));
+        assert!(InventoryCache::key("a/b", "x", &empty).starts_with("a_b-"));
+        // fixed across builds, an existing cache must stay valid
+        assert_eq!(InventoryCache::key("prod", "echo a", &env), "prod-6fae6edaf37e004b");
+    }
+
+    #[test]
+    fn test_fnv1a_reference_values() {
+        let mut hasher = Fnv1a::new();
+        hasher.write(b"a");
+        assert_eq!(hasher.0, 0xaf63dc4c8601ec8c);
+    }
+
+    #[test]
+    fn test_store_then_load_within_ttl() {
+        let cache = temp_cache("hit");
+        cache.store("net-1", b"host1\nhost2\n").unwrap();
+        assert_eq!(cache.load("net-1", Duration::from_secs(60)), Some(b"host1\nhost2\n".to_vec()));
+        assert_eq!(cache.load("missing", Duration::from_secs(60)), None);
+    }
+
+    #[test]
+    fn test_expired_entry_is_ignored() {
+        let cache = temp_cache("stale");
+        cache.store("net-1", b"host1\n").unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(cache.load("net-1", Duration::from_millis(1)), None);
+    }
+}
@@ -1,11 +1,12 @@
 use std::collections::hash_map;
+use crate::entity::secret::mask_secrets;
 
 pub fn logger_func(input: &str) {
     // add Log function
     let env: hash_map::HashMap<String, String> = std::env::vars().collect();
     let contains_debug = env.contains_key("DEBUG");
     if contains_debug {
-        println!("{}",input);
+        println!("{}", mask_secrets(input));
     }
 }
 
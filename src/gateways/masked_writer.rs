@@ -0,0 +1,60 @@
+use crate::entity::secret::mask_secrets;
+use std::io::{self, Write};
+
+/// Writes everything through `mask_secrets`, a line at a time, so a secret
+/// split across two writes is still caught. Incomplete lines are held back
+/// until a newline arrives or the writer is flushed.
+pub struct MaskedWriter<W: Write> {
+    inner: W,
+    pending: Vec<u8>,
+}
+
+impl<W: Write> MaskedWriter<W> {
+    pub fn new(inner: W) -> Self {
+        MaskedWriter { inner, pending: Vec::new() }
+    }
+
+    fn write_masked(&mut self, bytes: &[u8]) -> io::Result<()> {
+        let text = String::from_utf8_lossy(bytes);
+        self.inner.write_all(mask_secrets(&text).as_bytes())
+    }
+}
+
+impl<W: Write> Write for MaskedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        if let Some(last_newline) = self.pending.iter().rposition(|&b| b == b'\n') {
+            let complete: Vec<u8> = self.pending.drain(..=last_newline).collect();
+            self.write_masked(&complete)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.pending.is_empty() {
+            let rest = std::mem::take(&mut self.pending);
+            self.write_masked(&rest)?;
+        }
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::secret::register_secret;
+
+    #[test]
+    fn test_secrets_split_across_writes_are_masked() {
+        register_secret("p4ssw0rd-split");
+        let mut out = MaskedWriter::new(Vec::new());
+        out.write_all(b"connecting with p4ss").unwrap();
+        out.write_all(b"w0rd-split\nsecond line p4ssw0rd-").unwrap();
+        out.write_all(b"split").unwrap();
+        out.flush().unwrap();
+        assert_eq!(
+            String::from_utf8(out.inner).unwrap(),
+            "connecting with ****\nsecond line ****"
+        );
+    }
+}
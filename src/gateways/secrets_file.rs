@@ -0,0 +1,213 @@
+use argon2::Argon2;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
+use thiserror::Error;
+
+const FORMAT_VERSION: u32 = 1;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+/// Binds the ciphertext to this file format.
+const ASSOCIATED_DATA: &[u8] = b"ssup-secrets-v1";
+const HEADER: &str = "# ssup encrypted secrets, change with `ssup secrets edit|set`\n";
+
+#[derive(Error, Debug)]
+pub enum SecretsFileError {
+    #[error("failed to read secrets file {path}: {source}")]
+    Read {
+        path: String,
+        #[source]
+        source: io::Error,
+    },
+    #[error("failed to write secrets file {path}: {source}")]
+    Write {
+        path: String,
+        #[source]
+        source: io::Error,
+    },
+    #[error("secrets file {path} is malformed: {message}")]
+    Malformed {
+        path: String,
+        message: String,
+        trace_id: &'static str,
+    },
+    #[error("could not decrypt secrets file {path}: wrong passphrase or the file was modified")]
+    Decrypt {
+        path: String,
+        trace_id: &'static str,
+    },
+    #[error("failed to derive the key or encrypt: {message}")]
+    Crypto {
+        message: String,
+        trace_id: &'static str,
+    },
+}
+
+impl SecretsFileError {
+    pub fn trace_id(&self) -> &'static str {
+        match self {
+            SecretsFileError::Read { .. } => "FF3C21FE-39E9-430B-B905-0A773193B8F4",
+            SecretsFileError::Write { .. } => "82CC27A3-0CE9-44BE-84B9-44F10605FBA7",
+            SecretsFileError::Malformed { trace_id, .. }
+            | SecretsFileError::Decrypt { trace_id, .. }
+            | SecretsFileError::Crypto { trace_id, .. } => trace_id,
+        }
+    }
+}
+
+/// On-disk form: the secrets as a YAML map, encrypted with XChaCha20-Poly1305
+/// under a key derived from the passphrase with Argon2id.
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+    version: u32,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<[u8; 32], SecretsFileError> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| SecretsFileError::Crypto {
+            message: e.to_string(),
+            trace_id: "F2B7C91E-3D05-4A68-9E4C-18A6D0B5C273",
+        })?;
+    Ok(key)
+}
+
+fn random_bytes<const N: usize>() -> Result<[u8; N], SecretsFileError> {
+    let mut bytes = [0u8; N];
+    getrandom::getrandom(&mut bytes).map_err(|e| SecretsFileError::Crypto {
+        message: e.to_string(),
+        trace_id: "5A90E3D1-C7B2-4F84-A16E-2D3B9C08F547",
+    })?;
+    Ok(bytes)
+}
+
+/// Decrypts the secrets file at `path`.
+pub fn load_secrets(path: &Path, passphrase: &str) -> Result<IndexMap<String, String>, SecretsFileError> {
+    let shown = path.display().to_string();
+    let malformed = |message: String| SecretsFileError::Malformed {
+        path: shown.clone(),
+        message,
+        trace_id: "C41E8B06-92D7-4A3F-B5E0-7F6A13D29C84",
+    };
+
+    let content = fs::read_to_string(path).map_err(|source| SecretsFileError::Read { path: shown.clone(), source })?;
+    let envelope: Envelope = serde_yaml::from_str(&content).map_err(|e| malformed(e.to_string()))?;
+    if envelope.version != FORMAT_VERSION {
+        return Err(malformed(format!("unsupported version {}", envelope.version)));
+    }
+    let decode = |field: &str, value: &str| BASE64.decode(value).map_err(|e| malformed(format!("{}: {}", field, e)));
+    let salt = decode("salt", &envelope.salt)?;
+    let nonce = decode("nonce", &envelope.nonce)?;
+    let ciphertext = decode("ciphertext", &envelope.ciphertext)?;
+    if nonce.len() != NONCE_LEN {
+        return Err(malformed(format!("nonce must be {} bytes", NONCE_LEN)));
+    }
+
+    let key = derive_key(passphrase, &salt)?;
+    let cipher = XChaCha20Poly1305::new(&key.into());
+    let plaintext = cipher
+        .decrypt(XNonce::from_slice(&nonce), Payload { msg: &ciphertext, aad: ASSOCIATED_DATA })
+        .map_err(|_| SecretsFileError::Decrypt {
+            path: shown.clone(),
+            trace_id: "0B6D2F8A-41C3-4E97-8D5B-E3A7190C6F2D",
+        })?;
+    let plaintext = String::from_utf8(plaintext).map_err(|e| malformed(e.to_string()))?;
+    parse_plaintext(&plaintext).map_err(malformed)
+}
+
+/// Parses the decrypted YAML map; an empty document is an empty map.
+pub fn parse_plaintext(plaintext: &str) -> Result<IndexMap<String, String>, String> {
+    if plaintext.trim().is_empty() {
+        return Ok(IndexMap::new());
+    }
+    serde_yaml::from_str::<Option<IndexMap<String, String>>>(plaintext)
+        .map(Option::unwrap_or_default)
+        .map_err(|e| e.to_string())
+}
+
+/// Encrypts `secrets` with a fresh salt and nonce and replaces the file at `path`.
+pub fn save_secrets(path: &Path, passphrase: &str, secrets: &IndexMap<String, String>) -> Result<(), SecretsFileError> {
+    let shown = path.display().to_string();
+    let plaintext = if secrets.is_empty() {
+        String::new()
+    } else {
+        serde_yaml::to_string(secrets).expect("a map of strings always serializes")
+    };
+
+    let salt = random_bytes::<SALT_LEN>()?;
+    let nonce = random_bytes::<NONCE_LEN>()?;
+    let key = derive_key(passphrase, &salt)?;
+    let cipher = XChaCha20Poly1305::new(&key.into());
+    let ciphertext = cipher
+        .encrypt(XNonce::from_slice(&nonce), Payload { msg: plaintext.as_bytes(), aad: ASSOCIATED_DATA })
+        .map_err(|e| SecretsFileError::Crypto {
+            message: e.to_string(),
+            trace_id: "7E3C05A9-B814-4D26-92F1-6A0D8E4B3C57",
+        })?;
+
+    let envelope = Envelope {
+        version: FORMAT_VERSION,
+        salt: BASE64.encode(salt),
+        nonce: BASE64.encode(nonce),
+        ciphertext: BASE64.encode(ciphertext),
+    };
+    let content = format!("{}{}", HEADER, serde_yaml::to_string(&envelope).expect("envelope always serializes"));
+
+    // write next to the target and rename, so a failed write never loses the old file
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, content)
+        .and_then(|_| fs::rename(&tmp, path))
+        .map_err(|source| SecretsFileError::Write { path: shown, source })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn temp_file(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("ssup-secrets-{}-{}.yml", name, std::process::id()))
+    }
+
+    #[test]
+    fn test_round_trip_and_wrong_passphrase() {
+        let path = temp_file("round-trip");
+        let mut secrets = IndexMap::new();
+        secrets.insert("DB_PASS".to_string(), "пароль: with 'quotes'".to_string());
+        secrets.insert("API_TOKEN".to_string(), "t0ken".to_string());
+        save_secrets(&path, "correct horse", &secrets).unwrap();
+
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(!content.contains("t0ken") && !content.contains("DB_PASS"));
+        assert_eq!(load_secrets(&path, "correct horse").unwrap(), secrets);
+        assert!(matches!(load_secrets(&path, "wrong"), Err(SecretsFileError::Decrypt { .. })));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_tampering_is_detected() {
+        let path = temp_file("tamper");
+        save_secrets(&path, "pw", &IndexMap::from([("A".to_string(), "b".to_string())])).unwrap();
+        let content = fs::read_to_string(&path).unwrap();
+        let mut envelope: Envelope = serde_yaml::from_str(&content).unwrap();
+        let mut ciphertext = BASE64.decode(&envelope.ciphertext).unwrap();
+        ciphertext[0] ^= 1;
+        envelope.ciphertext = BASE64.encode(ciphertext);
+        fs::write(&path, serde_yaml::to_string(&envelope).unwrap()).unwrap();
+
+        assert!(matches!(load_secrets(&path, "pw"), Err(SecretsFileError::Decrypt { .. })));
+        fs::write(&path, "version: 1\nsalt: '%%'\n").unwrap();
+        assert!(matches!(load_secrets(&path, "pw"), Err(SecretsFileError::Malformed { .. })));
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,161 @@
+use std::ffi::OsStr;
+use std::io::{self, Read};
+use std::process::{Command, ExitStatus, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+/// How often a running child is polled for completion.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+#[derive(Error, Debug)]
+pub enum ShellRunError {
+    #[error("failed to spawn /bin/sh: {source}")]
+    Spawn {
+        #[source]
+        source: io::Error,
+        trace_id: &'static str,
+    },
+    #[error("command did not finish within {}s and was killed", timeout.as_secs())]
+    Timeout {
+        timeout: Duration,
+        trace_id: &'static str,
+    },
+    #[error("failed to wait for command: {source}")]
+    Wait {
+        #[source]
+        source: io::Error,
+        trace_id: &'static str,
+    },
+}
+
+impl ShellRunError {
+    pub fn trace_id(&self) -> &'static str {
+        match self {
+            ShellRunError::Spawn { trace_id, .. }
+            | ShellRunError::Timeout { trace_id, .. }
+            | ShellRunError::Wait { trace_id, .. } => trace_id,
+        }
+    }
+}
+
+/// Captured result of a finished `sh -c` invocation; stderr is inherited.
+#[derive(Debug)]
+pub struct ShellOutput {
+    pub status: ExitStatus,
+    pub stdout: Vec<u8>,
+}
+
+/// Runs `script` with `/bin/sh -c`, killing it once `timeout` elapses.
+pub fn run_sh_with_timeout<I, K, V>(
+    script: &str,
+    env: I,
+    timeout: Duration,
+) -> Result<ShellOutput, ShellRunError>
+where
+    I: IntoIterator<Item = (K, V)>,
+    K: AsRef<OsStr>,
+    V: AsRef<OsStr>,
+{
+    let mut child = Command::new("/bin/sh")
+        .arg("-c")
+        .arg(script)
+        .envs(env)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .map_err(|source| ShellRunError::Spawn {
+            source,
+            trace_id: "2E0C4B61-52F8-4E4B-A0C4-7D1F3B7E9A10",
+        })?;
+
+    // drain stdout on a separate thread so a chatty script can't block on a full pipe
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    let reader = thread::spawn(move || {
+        let mut buf = Vec::new();
+        let _ = stdout.read_to_end(&mut buf);
+        buf
+    });
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if Instant::now() >= deadline => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(ShellRunError::Timeout {
+                    timeout,
+                    trace_id: "9B3F1C2A-6D4E-4F7B-8A1C-2E5D7F9B0C34",
+                });
+            }
+            Ok(None) => thread::sleep(POLL_INTERVAL),
+            Err(source) => {
+                return Err(ShellRunError::Wait {
+                    source,
+                    trace_id: "A41D7E90-3B2C-4C5D-9E8F-6A7B1C2D3E4F",
+                })
+            }
+        }
+    };
+
+    let stdout = reader.join().unwrap_or_default();
+    Ok(ShellOutput { status, stdout })
+}
+
+/// Parses `script` with `/bin/sh -n` without running it. `Ok(None)` when the
+/// syntax is valid, otherwise the shell's complaint.
+pub fn check_sh_syntax(script: &str) -> Result<Option<String>, ShellRunError> {
+    let output = Command::new("/bin/sh")
+        .arg("-n")
+        .arg("-c")
+        .arg(script)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()
+        .map_err(|source| ShellRunError::Spawn {
+            source,
+            trace_id: "13C9BFB9-4D2E-4ADE-A50F-64E35D439B1C",
+        })?;
+    if output.status.success() {
+        return Ok(None);
+    }
+    Ok(Some(String::from_utf8_lossy(&output.stderr).trim().to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_checks_syntax_without_running() {
+        assert_eq!(check_sh_syntax("echo a; exit 3").unwrap(), None);
+        let complaint = check_sh_syntax("if true; then echo a").unwrap();
+        assert!(complaint.is_some_and(|message| !message.is_empty()));
+    }
+
+    #[test]
+    fn test_captures_stdout() {
+        let out = run_sh_with_timeout("echo hello", &HashMap::<String, String>::new(), Duration::from_secs(5)).unwrap();
+        assert!(out.status.success());
+        assert_eq!(out.stdout, b"hello\n");
+    }
+
+    #[test]
+    fn test_passes_env() {
+        let env = HashMap::from([("SSUP_TEST_VAR".to_string(), "value".to_string())]);
+        let out = run_sh_with_timeout("printf %s \"$SSUP_TEST_VAR\"", &env, Duration::from_secs(5)).unwrap();
+        assert_eq!(out.stdout, b"value");
+    }
+
+    #[test]
+    fn test_kills_on_timeout() {
+        let started = Instant::now();
+        let err = run_sh_with_timeout("sleep 5", &HashMap::<String, String>::new(), Duration::from_millis(200)).unwrap_err();
+        assert!(matches!(err, ShellRunError::Timeout { .. }));
+        assert!(started.elapsed() < Duration::from_secs(4));
+    }
+}
@@ -0,0 +1,121 @@
+use serde::Deserialize;
+use std::io::{self, ErrorKind, Write};
+use std::process::{Command, Stdio};
+use thiserror::Error;
+
+const SHELLCHECK: &str = "shellcheck";
+
+#[derive(Error, Debug)]
+pub enum ShellcheckError {
+    #[error("shellcheck is not installed, see https://www.shellcheck.net")]
+    NotInstalled { trace_id: &'static str },
+    #[error("failed to run shellcheck: {source}")]
+    Run {
+        #[source]
+        source: io::Error,
+        trace_id: &'static str,
+    },
+    #[error("shellcheck failed: {message}")]
+    Failed {
+        message: String,
+        trace_id: &'static str,
+    },
+}
+
+impl ShellcheckError {
+    pub fn trace_id(&self) -> &'static str {
+        match self {
+            ShellcheckError::NotInstalled { trace_id }
+            | ShellcheckError::Run { trace_id, .. }
+            | ShellcheckError::Failed { trace_id, .. } => trace_id,
+        }
+    }
+}
+
+/// One finding; line and column are 1-based within the checked script.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ShellcheckComment {
+    pub line: usize,
+    pub column: usize,
+    pub level: String,
+    pub code: u32,
+    pub message: String,
+}
+
+#[derive(Deserialize)]
+struct Json1 {
+    comments: Vec<ShellcheckComment>,
+}
+
+/// Whether a `shellcheck` binary can be started.
+pub fn is_installed() -> bool {
+    Command::new(SHELLCHECK).arg("-V").output().is_ok()
+}
+
+/// Runs shellcheck on `script` as POSIX sh, skipping the `exclude` codes.
+pub fn run_shellcheck(script: &str, exclude: &[u32]) -> Result<Vec<ShellcheckComment>, ShellcheckError> {
+    let mut command = Command::new(SHELLCHECK);
+    command.args(["--format=json1", "--shell=sh"]);
+    if !exclude.is_empty() {
+        let codes: Vec<String> = exclude.iter().map(u32::to_string).collect();
+        command.arg(format!("--exclude={}", codes.join(",")));
+    }
+    let mut child = command
+        .arg("-")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|source| match source.kind() {
+            ErrorKind::NotFound => ShellcheckError::NotInstalled { trace_id: "44884DE3-C79D-4CF3-80F1-980F53E108FD" },
+            _ => ShellcheckError::Run { source, trace_id: "068D85B9-24F5-4DBA-897D-CB93B7EB6388" },
+        })?;
+
+    // shellcheck reads the whole script before it prints anything
+    let written = child.stdin.take().expect("stdin is piped").write_all(script.as_bytes());
+    let output = child.wait_with_output();
+    let output = written.and(output).map_err(|source| ShellcheckError::Run {
+        source,
+        trace_id: "068D85B9-24F5-4DBA-897D-CB93B7EB6388",
+    })?;
+
+    // 0 is clean, 1 means there are comments, anything else is shellcheck failing
+    if !matches!(output.status.code(), Some(0) | Some(1)) {
+        return Err(ShellcheckError::Failed {
+            message: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            trace_id: "FBCB3C6E-04A1-4CD9-A4BE-176342C6C10A",
+        });
+    }
+    parse_json1(&output.stdout)
+}
+
+fn parse_json1(stdout: &[u8]) -> Result<Vec<ShellcheckComment>, ShellcheckError> {
+    serde_json::from_slice::<Json1>(stdout)
+        .map(|report| report.comments)
+        .map_err(|e| ShellcheckError::Failed {
+            message: format!("unexpected output: {}", e),
+            trace_id: "A3F948CB-FA0D-411B-8981-707D6F0C6B9A",
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parses_json1() {
+        let stdout = br#"{"comments":[{"file":"-","line":3,"endLine":3,"column":6,"endColumn":10,"level":"info","code":2086,"message":"Double quote to prevent globbing and word splitting.","fix":null}]}"#;
+        let comments = parse_json1(stdout).unwrap();
+        assert_eq!(
+            comments,
+            vec![ShellcheckComment {
+                line: 3,
+                column: 6,
+                level: "info".to_string(),
+                code: 2086,
+                message: "Double quote to prevent globbing and word splitting.".to_string(),
+            }]
+        );
+        assert!(matches!(parse_json1(b"not json"), Err(ShellcheckError::Failed { .. })));
+    }
+}
@@ -0,0 +1,22 @@
+use std::io::{self, BufRead, IsTerminal, Write};
+
+/// Whether questions can be asked: stdin and stderr are both a terminal.
+pub fn is_interactive() -> bool {
+    io::stdin().is_terminal() && io::stderr().is_terminal()
+}
+
+/// Asks `question` on stderr and reads one line from stdin, without the
+/// line break. `hidden` answers are not echoed.
+pub fn ask(question: &str, hidden: bool) -> io::Result<String> {
+    if hidden {
+        return rpassword::prompt_password(question);
+    }
+    let mut stderr = io::stderr();
+    write!(stderr, "{}", question)?;
+    stderr.flush()?;
+    let mut line = String::new();
+    if io::stdin().lock().read_line(&mut line)? == 0 {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "no answer, stdin is closed"));
+    }
+    Ok(line.trim_end_matches(['\n', '\r']).to_string())
+}
@@ -1,37 +1,76 @@
-use std::env::args;
-
+use crate::gateways::masked_writer::MaskedWriter;
+use crate::usecase::error::SsupError;
 use crate::usecase::program_init;
-use crate::entity::help_displayer::HelpDisplayer;
 use clap::Parser;
+use std::io::{self, Write};
+use std::process;
 mod entity;
 mod usecase;
 mod gateways;
 
+/// Parses the command line. `-e @file` is anchored to the directory ssup was
+/// started in before `parse_supfile` changes into the Supfile's directory.
 fn process_flags() -> entity::CommandLineArgs {
-    let args = entity::CommandLineArgs::parse();
-    return args;
+    let mut flags = entity::CommandLineArgs::parse();
+    if let Ok(cwd) = std::env::current_dir() {
+        flags.env = usecase::env_parser::anchor_env_files(&flags.env, &cwd);
+    }
+    flags
 }
 
 
 fn main() {
-    // collect arguments into a vector
-    let args: Vec<String> = args().collect();
+    if let Err(e) = run() {
+        match e {
+            SsupError::Usage { .. } => eprintln!("{}", e),
+            _ => {
+                eprintln!("ERR: {}", e);
+                if let Some(hint) = usecase::explain::explain_hint(&e) {
+                    eprintln!("     {}", hint);
+                }
+            }
+        }
+        process::exit(e.exit_code());
+    }
+}
+
+fn run() -> Result<(), SsupError> {
     let flags = process_flags();
-    // println!("args are {:?}", &args);
-    let supfile = program_init::parse_supfile(flags.clone());
-    dbg!(supfile.clone());
-
-    // find full path to supfile
-    let start_state = entity::InitState{
-        args: args,
-        supfile: supfile,
-        flags: flags,
+    if flags.args.first().map(String::as_str) == Some(entity::const_values::EXPLAIN_SUBCOMMAND) {
+        return usecase::explain::explain_command(&flags.args);
+    }
+    if flags.args.first().map(String::as_str) == Some(entity::const_values::CHECK_SUBCOMMAND) {
+        return usecase::check::check_command(&flags);
+    }
+    if flags.args.first().map(String::as_str) == Some(entity::const_values::LINT_SUBCOMMAND) {
+        return usecase::lint::lint_command(&flags);
+    }
+    let supfile = program_init::parse_supfile(flags.clone())?;
+    gateways::logger::logger_func(&format!("{:?}", supfile));
+
+    let mut start_state = entity::InitState{
+        args: flags.args.clone(),
+        supfile,
+        flags,
         make_mode: false,
     };
-    let mut help_displayer = HelpDisplayer::new(start_state.clone());
-    help_displayer.show_cmd = true;
-    help_displayer.show_networks = true;
-    help_displayer.show_targets = true;
-    help_displayer.show(&start_state.clone());
-    
+    program_init::usage_on_no_args(start_state.clone())?;
+
+    if start_state.args[0] == entity::const_values::SECRETS_SUBCOMMAND {
+        return usecase::secrets::secrets_command(&start_state);
+    }
+    usecase::secrets::load_supfile_secrets(&mut start_state.supfile)?;
+
+    if start_state.args[0] == entity::const_values::ENV_SUBCOMMAND {
+        return usecase::env_resolver::env_command(&start_state);
+    }
+
+    let mut playbook = program_init::parse_initial_args(&mut start_state)?;
+    usecase::prompts::ask_for_env(&mut playbook, &start_state.flags)?;
+    usecase::ensure_secrets_resolved(&mut playbook, start_state.supfile.shell_output)?;
+    usecase::env_resolver::evaluate_playbook_env(&mut playbook)?;
+    usecase::preflight::ensure_required_env(&playbook)?;
+    usecase::prompts::confirm_run(&playbook, &start_state.flags)?;
+    let mut out = MaskedWriter::new(io::stdout());
+    write!(out, "{}", playbook).and_then(|_| out.flush()).map_err(SsupError::Output)
 }
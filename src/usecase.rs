@@ -3,87 +3,194 @@ pub mod modes_of_operation;
 pub mod env_parser;
 pub mod network_host_utils;
 pub mod parse_network;
-use std::collections::HashMap;
+pub mod inventory_file;
+pub mod env_resolver;
+pub mod env_interpolation;
+pub mod secret_resolver;
+pub mod secrets;
+pub mod preflight;
+pub mod error;
+pub mod explain;
+pub mod diagnostics;
+pub mod check;
+pub mod lint;
+pub mod params;
+pub mod prompts;
 use std::{env, vec};
 use chrono::Utc;
 use crate::entity::help_displayer::HelpDisplayer;
+use crate::entity::const_values::{
+    SUP_COMMAND_ENV, SUP_ENV_ENV, SUP_NETWORK_ENV, SUP_RUN_ID_ENV, SUP_TARGET_ENV, SUP_TIME_ENV, SUP_USER_ENV,
+};
+use crate::entity::supfile::commands::Command;
+use crate::entity::supfile::targets::Target;
+use crate::entity::supfile::OutputPolicy;
 use crate::entity::supfile::networks::{HostDetails, HostEntry, Network, Networks};
 use crate::entity::InitState;
+use crate::entity::playbook::{Play, PlayBook};
+use crate::entity::env::EnvList;
+use crate::entity::tag_selector::TagSelector;
+use crate::gateways::logger::logger_func as l;
+use crate::usecase::network_host_utils::split_tags;
+use crate::usecase::parse_network::check_hosts_form;
+use crate::usecase::error::SsupError;
+use crate::usecase::secret_resolver::{resolve_env_values, resolve_network_secrets};
 use std::process;
+use std::sync::OnceLock;
 
 #[allow(unused_imports)]
 #[allow(dead_code)]
-pub fn ensure_network_exists(network_name: &str, conf: &InitState, help_menu: &HelpDisplayer) {
-    let networks = conf.supfile.networks.clone();
-    if !networks.names.contains(&network_name.to_string()) {
+pub fn ensure_network_exists(network_name: &str, conf: &InitState, help_menu: &HelpDisplayer) -> Result<(), SsupError> {
+    if !conf.supfile.networks.names.iter().any(|name| name == network_name) {
         help_menu.show(conf);
-        eprintln!("Error: Network '{}' does not exist", network_name);
-        process::exit(1);
+        return Err(SsupError::NetworkNotFound {
+            name: network_name.to_string(),
+            trace_id: "A2CDC038-8606-4808-9513-61447F899E4D",
+        });
     }
+    Ok(())
 }
 
-#[allow(unused_imports)]
-#[allow(dead_code)]
-/// Overrides environment variables in the network with values from the given map.
-pub fn override_env_from_args(env_from_args: &HashMap<String, String>, network: &mut Network) {
-    match network.env {
-        Some(ref mut env) => {
-            for (key, value) in env_from_args {
-                env.insert(key.clone(), value.clone());
-            }
-        },
-        None => {
-            let mut env = HashMap::new();
-            for (key, value) in env_from_args {
-                env.insert(key.clone(), value.clone());
-            }
-            network.env = Some(env);
+/// Resolves `$(cmd)`, `env:` and `file:` references in every network and
+/// host of the playbook, and in the env each command gets, before anything
+/// connects; stops at the first failure.
+pub fn ensure_secrets_resolved(playbook: &mut PlayBook, policy: OutputPolicy) -> Result<(), SsupError> {
+    let resolve_play = |play: &mut Play| -> Result<(), SsupError> {
+        if let Some(network) = play.nets.as_mut() {
+            resolve_network_secrets(network, policy)?;
         }
-    }
+        for command_env in play.envs.iter_mut() {
+            let owner = format!("command {} on {}", play.commands[command_env.command].name, command_env.target);
+            resolve_env_values(&owner, &mut command_env.env, policy)?;
+        }
+        Ok(())
+    };
+
+    playbook.plays.iter_mut().try_for_each(resolve_play)
 }
 
+/// Tags of a host entry, `Simple` entries carry them as a trailing `[a=b, c]`.
+pub fn host_tags(host: &HostEntry) -> Vec<String> {
+    match host {
+        HostEntry::Simple(s) => split_tags(s).1,
+        HostEntry::Detailed(details) => details.tags.clone(),
+    }
+}
 
-#[allow(unused_imports)]
-#[allow(dead_code)]
-pub fn add_ssup_default_envs(network: &mut Network, init_state: &InitState) {
-    let mut env = HashMap::<String, String>::new();
-    if network.name == "localhost" {
-        let network_key = "SUP_NETWORK".to_string();
-        let network_value = "localhost".to_string();
-        env.insert(network_key, network_value);
-    } else {
-        let network_key = "SUP_NETWORK".to_string();
-        let network_value = init_state.args.to_vec()[0].clone();
-        env.insert(network_key, network_value);
+/// Applies `--tags` to the hosts of a network, static and inventory ones
+/// alike; fails when no host is left.
+pub fn select_hosts_by_tags(network: &mut Network, init_state: &InitState) -> Result<(), SsupError> {
+    let tags = &init_state.flags.tags;
+    if tags.is_empty() {
+        return Ok(());
     }
-    
-    let now = Utc::now().to_rfc3339();
-    let time_key = "SUP_TIME".to_string();
-    let time_value = now;
-    env.insert(time_key, time_value);
-
-    if env.contains_key("SUP_USER") {
-        let user_key = "SUP_USER".to_string();
-        let user_value = env::var("SUP_USER").unwrap();
-        env.insert(user_key, user_value);
-    } else {
-        let user_key = "SUP_USER".to_string();
-        let user_value = env::var("USER").unwrap();
-        env.insert(user_key, user_value);
+    l("apply --tags selector to the expanded host list");
+    let selector = TagSelector::parse(tags).map_err(|e| SsupError::InvalidTags {
+        tags: tags.clone(),
+        message: e.to_string(),
+        trace_id: "42DE50C7-0C67-4B49-8675-2619B6CFE8C0",
+    })?;
+    filter_hosts_by_tags(network, &selector);
+    if network.hosts.is_empty() {
+        return Err(SsupError::NoMatchingHosts {
+            network: network.name.clone(),
+            tags: tags.clone(),
+            trace_id: "A5F38A37-4E0C-4F6C-9464-9AFE878B73E7",
+        });
+    }
+    Ok(())
+}
+
+/// A host entry as `HostDetails`, `Simple` strings parsed.
+pub fn host_details(host: &HostEntry) -> HostDetails {
+    match host {
+        HostEntry::Simple(s) => match check_hosts_form(s) {
+            HostEntry::Detailed(details) => *details,
+            HostEntry::Simple(_) => unreachable!("check_hosts_form always returns Detailed"),
+        },
+        HostEntry::Detailed(details) => (**details).clone(),
     }
+}
 
-    // if network env does not exists set it to env
-    if let None = network.env {
-        network.env = Some(env);
-    } else {
-        // if network env exists, append to it
-        let mut network_env = network.env.clone().unwrap();
-        for (key, value) in env.iter() {
-            network_env.insert(key.clone(), value.clone());
+/// Drops every host of the network whose tags don't satisfy the selector.
+pub fn filter_hosts_by_tags(network: &mut Network, selector: &TagSelector) {
+    network.hosts.retain(|host| {
+        let keep = selector.matches(&host_tags(host));
+        if !keep {
+            l(&format!("--tags: skipping host {}", host));
+        }
+        keep
+    });
+}
+
+
+/// Identifies this invocation, shared by every host and command it touches.
+pub fn run_id() -> &'static str {
+    static RUN_ID: OnceLock<String> = OnceLock::new();
+    RUN_ID.get_or_init(|| {
+        let now = Utc::now();
+        format!("{}-{:08x}", now.format("%Y%m%dT%H%M%S%.6fZ"), process::id())
+    })
+}
+
+/// Start time of this invocation, shared like `run_id`.
+pub fn run_started_at() -> &'static str {
+    static STARTED_AT: OnceLock<String> = OnceLock::new();
+    STARTED_AT.get_or_init(|| Utc::now().to_rfc3339())
+}
+
+/// The local user running ssup, `$SUP_USER` wins over `$USER` and `$LOGNAME`.
+fn local_user() -> String {
+    ["SUP_USER", "USER", "LOGNAME"]
+        .iter()
+        .find_map(|key| env::var(key).ok().filter(|user| !user.is_empty()))
+        .unwrap_or_default()
+}
+
+/// The host-independent `SUP_*` variables for a network.
+pub fn ssup_default_envs(network: &Network, init_state: &InitState) -> EnvList {
+    let mut env = EnvList::new();
+    env.set(SUP_NETWORK_ENV.to_string(), network.name.clone());
+    env.set(SUP_USER_ENV.to_string(), local_user());
+    env.set(SUP_TIME_ENV.to_string(), run_started_at().to_string());
+    env.set(SUP_ENV_ENV.to_string(), init_state.flags.env.join(" "));
+    env.set(SUP_RUN_ID_ENV.to_string(), run_id().to_string());
+    env
+}
+
+/// `user@host:port` -> `host:port`
+pub fn host_without_user(host: &str) -> &str {
+    host.rsplit_once('@').map_or(host, |(_, host)| host)
+}
+
+/// Parses `Simple` host strings into `Detailed` entries, so every host can
+/// carry its own env. What a command gets on a host, `SUP_*` variables
+/// included, is layered by `env_resolver::resolve_env`.
+pub fn expand_hosts(network: &mut Network) {
+    for host in network.hosts.iter_mut() {
+        if let HostEntry::Simple(_) = host {
+            *host = HostEntry::Detailed(Box::new(host_details(host)));
         }
-        network.env = Some(network_env);
     }
+}
+
+/// Applies the per-step env and options of a target line to its command.
+pub fn apply_target_step(command: &mut Command, step: &Target) {
+    if !step.env.is_empty() {
+        command.env.get_or_insert_with(EnvList::new).extend(&step.env);
+    }
+    if step.serial.is_some() {
+        command.serial = step.serial;
+    }
+    command.once |= step.once;
+}
 
+/// Sets `SUP_COMMAND` and `SUP_TARGET`, the latter empty for commands given
+/// directly on the command line.
+pub fn add_ssup_command_envs(command: &mut Command, target: &str) {
+    let env = command.env.get_or_insert_with(EnvList::new);
+    env.set(SUP_COMMAND_ENV.to_string(), command.name.clone());
+    env.set(SUP_TARGET_ENV.to_string(), target.to_string());
 }
 
 
@@ -106,11 +213,17 @@ pub fn ensure_localhost(init_state: &mut InitState) {
         println!("adding localhost");
         if networks.is_empty() {
             let host_details = HostDetails::new("localhost".to_string());
-            let host_entry = HostEntry::Detailed(host_details);
+            let host_entry = HostEntry::Detailed(Box::new(host_details));
             let localhost_network = Network {
                 hosts: vec![host_entry],
                 env: None,
                 inventory: None,
+                inventory_ttl: None,
+                inventory_timeout: None,
+                inventory_file: None,
+                inventory_format: None,
+                extends: None,
+                protected: None,
                 bastion: None,
                 user: None,
                 pass: None,
@@ -121,11 +234,17 @@ pub fn ensure_localhost(init_state: &mut InitState) {
             init_state.supfile.networks = networks;
         } else {
             let host_details = HostDetails::new("localhost".to_string());
-            let host_entry = HostEntry::Detailed(host_details);
+            let host_entry = HostEntry::Detailed(Box::new(host_details));
             let localhost_network = Network {
                 hosts: vec![host_entry],
                 env: None,
                 inventory: None,
+                inventory_ttl: None,
+                inventory_timeout: None,
+                inventory_file: None,
+                inventory_format: None,
+                extends: None,
+                protected: None,
                 bastion: None,
                 user: None,
                 pass: None,
@@ -0,0 +1,327 @@
+use crate::entity::const_values::SOURCE_DIRECTIVE;
+use crate::entity::supfile::networks::HostEntry;
+use crate::entity::supfile::Supfile;
+use crate::entity::CommandLineArgs;
+use crate::gateways::logger::logger_func as l;
+use crate::gateways::shell::check_sh_syntax;
+use crate::usecase::diagnostics::{find_key, find_text, render, unknown_references, yaml_error};
+use crate::usecase::error::SsupError;
+use crate::usecase::inventory_file::load_inventory_files;
+use crate::usecase::network_host_utils::split_tags;
+use crate::usecase::parse_network::check_hosts_form;
+use serde::Serialize;
+use std::path::Path;
+
+const JSON_FLAG: &str = "--json";
+
+/// The part of the Supfile a problem was found in.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckKind {
+    Syntax,
+    Targets,
+    Upload,
+    Source,
+    Hosts,
+    Inventory,
+    InventoryFile,
+}
+
+/// One problem, with its 1-based location in the Supfile when known.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    pub check: CheckKind,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub column: Option<usize>,
+}
+
+impl Problem {
+    fn new(check: CheckKind, location: Option<(usize, usize)>, message: String) -> Problem {
+        Problem { check, message, line: location.map(|(line, _)| line), column: location.map(|(_, column)| column) }
+    }
+
+    fn location(&self) -> Option<(usize, usize)> {
+        self.line.zip(self.column)
+    }
+}
+
+/// The result of `ssup check`, also printed as JSON with `--json`.
+#[derive(Serialize, Debug)]
+pub struct Report {
+    pub supfile: String,
+    pub ok: bool,
+    pub problems: Vec<Problem>,
+}
+
+/// `ssup check [--json]`: validates the whole Supfile without contacting
+/// any host and lists every problem, as text or as a JSON report.
+pub fn check_command(flags: &CommandLineArgs) -> Result<(), SsupError> {
+    let json = match flags.args.as_slice() {
+        [_] => false,
+        [_, flag] if flag == JSON_FLAG => true,
+        _ => return Err(SsupError::Usage { message: "Usage: ssup [-f Supfile] check [--json]".to_string() }),
+    };
+
+    let (path, content) = crate::usecase::program_init::read_supfile(flags)?;
+    let report = check_supfile(&path, &content);
+
+    if json {
+        let out = serde_json::to_string_pretty(&report).expect("the report serializes");
+        println!("{}", out);
+    } else if report.ok {
+        println!("{}: ok", path);
+    } else {
+        for problem in &report.problems {
+            println!("{}\n", render(&path, &content, problem.location(), &problem.message));
+        }
+    }
+
+    if report.ok {
+        return Ok(());
+    }
+    Err(SsupError::CheckFailed {
+        path,
+        count: report.problems.len(),
+        trace_id: "E59C10ED-87A3-4998-85FE-05206359E97F",
+    })
+}
+
+/// Runs every check on the Supfile at `path`. Relative paths are resolved
+/// from the current directory, which is the Supfile's own when run from
+/// [`check_command`].
+pub fn check_supfile(path: &str, content: &str) -> Report {
+    l(&format!("check: validating {}", path));
+    let mut problems = match serde_yaml::from_str::<Supfile>(content) {
+        Ok(supfile) => check_parsed(content, &supfile),
+        Err(error) => {
+            let (location, message) = yaml_error(&error);
+            vec![Problem::new(CheckKind::Syntax, location, message)]
+        }
+    };
+    problems.sort_by_key(|problem| (problem.line.is_none(), problem.location()));
+    Report { supfile: path.to_string(), ok: problems.is_empty(), problems }
+}
+
+fn check_parsed(content: &str, supfile: &Supfile) -> Vec<Problem> {
+    let mut problems: Vec<Problem> = unknown_references(content, supfile)
+        .into_iter()
+        .map(|(location, message)| Problem::new(CheckKind::Targets, location, message))
+        .collect();
+    for name in &supfile.targets.names {
+        if let Err(cycle) = supfile.target_steps(name) {
+            let location = find_key(content, &["targets", name]);
+            problems.push(Problem::new(CheckKind::Targets, location, format!("targets.{}: {}", name, cycle)));
+        }
+    }
+    problems.extend(check_commands(content, supfile));
+    problems.extend(check_networks(content, supfile));
+    problems
+}
+
+/// `upload` sources and `#source://` files of `run` and `local` must exist.
+fn check_commands(content: &str, supfile: &Supfile) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    for (name, command) in &supfile.commands {
+        for upload in command.upload.iter().flatten() {
+            if !Path::new(&upload.src).exists() {
+                let location = find_text(content, &format!("src: {}", upload.src)).or_else(|| find_text(content, &upload.src));
+                let message = format!("commands.{}: upload src `{}` does not exist", name, upload.src);
+                problems.push(Problem::new(CheckKind::Upload, location, message));
+            }
+        }
+
+        for (field, script) in [("run", &command.run), ("local", &command.local)] {
+            let sources = script
+                .iter()
+                .flat_map(|script| script.lines())
+                .filter_map(|line| line.trim().strip_prefix(SOURCE_DIRECTIVE))
+                .map(str::trim);
+            for source in sources {
+                if !Path::new(source).exists() {
+                    let location = find_text(content, &format!("{}{}", SOURCE_DIRECTIVE, source));
+                    let message = format!("commands.{}.{}: source file `{}` does not exist", name, field, source);
+                    problems.push(Problem::new(CheckKind::Source, location, message));
+                }
+            }
+        }
+    }
+    problems
+}
+
+/// Inventory files load, inventory scripts are valid shell and every host
+/// string, including those from inventory files, parses.
+fn check_networks(content: &str, supfile: &Supfile) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let mut networks = supfile.networks.clone();
+    if let Err(e) = load_inventory_files(&mut networks) {
+        let location = supfile
+            .networks
+            .names
+            .iter()
+            .filter_map(|name| supfile.networks.get(name)?.inventory_file.as_deref())
+            .find(|file| e.to_string().contains(file))
+            .and_then(|file| find_text(content, file));
+        problems.push(Problem::new(CheckKind::InventoryFile, location, e.to_string()));
+    }
+
+    for name in &networks.names {
+        let Some(network) = networks.get(name) else {
+            continue;
+        };
+
+        if let Some(script) = &network.inventory {
+            let complaint = match check_sh_syntax(script) {
+                Ok(None) => None,
+                Ok(Some(complaint)) => Some(format!("invalid shell syntax: {}", complaint)),
+                Err(e) => Some(format!("could not be checked: {}", e)),
+            };
+            if let Some(complaint) = complaint {
+                let first_line = script.lines().map(str::trim).find(|line| !line.is_empty()).unwrap_or_default();
+                let location = find_text(content, first_line);
+                let message = format!("networks.{}.inventory: {}", name, complaint);
+                problems.push(Problem::new(CheckKind::Inventory, location, message));
+            }
+        }
+
+        for host in &network.hosts {
+            let (shown, problem) = match host {
+                HostEntry::Simple(host) => (host, host_string_problem(host)),
+                HostEntry::Detailed(details) => (&details.host, address_problem(&details.host)),
+            };
+            if let Some(problem) = problem {
+                let message = format!("networks.{}: host `{}` {}", name, shown, problem);
+                problems.push(Problem::new(CheckKind::Hosts, find_text(content, shown), message));
+            }
+        }
+    }
+    problems
+}
+
+/// Why a `user@host:port | pass << tube [tags]` string can't be used.
+fn host_string_problem(host: &str) -> Option<String> {
+    let (rest, _) = split_tags(host);
+    if rest.contains('[') {
+        return Some("has an unterminated tag list".to_string());
+    }
+    match check_hosts_form(host) {
+        HostEntry::Detailed(details) => address_problem(&details.host),
+        HostEntry::Simple(_) => None,
+    }
+}
+
+/// Why a `user@host:port` address can't be used.
+fn address_problem(address: &str) -> Option<String> {
+    let address = address.trim();
+    if address.is_empty() {
+        return Some("has no host name".to_string());
+    }
+    if address.contains(char::is_whitespace) {
+        return Some("contains whitespace".to_string());
+    }
+    let host = match address.rsplit_once('@') {
+        Some(("", _)) => return Some("has an empty user before `@`".to_string()),
+        Some((_, host)) => host,
+        None => address,
+    };
+    if host.is_empty() {
+        return Some("has no host name after `@`".to_string());
+    }
+    // a bare IPv6 address has several colons and no port
+    if let Some((name, port)) = host.rsplit_once(':').filter(|_| host.matches(':').count() == 1 || host.starts_with('[')) {
+        if name.is_empty() {
+            return Some("has no host name before the port".to_string());
+        }
+        if !matches!(port.parse::<u16>(), Ok(port) if port > 0) {
+            return Some(format!("has an invalid port `{}`", port));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    fn kinds(report: &Report) -> Vec<CheckKind> {
+        report.problems.iter().map(|problem| problem.check).collect()
+    }
+
+    #[test]
+    fn test_every_problem_is_reported() {
+        let dir = env::temp_dir().join(format!("ssup-check-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let present = dir.join("present.sh");
+        fs::write(&present, "echo hi\n").unwrap();
+        let present = present.display().to_string();
+
+        let yaml = format!(
+            "version: 0.5\nnetworks:\n  prod:\n    hosts:\n    - root@10.0.0.1:22\n    - root@10.0.0.2:ssh\n    inventory: if true; then echo a\n  stage:\n    inventory_file: ./no-such-inventory.ini\ncommands:\n  deploy:\n    run: \"#source://{}\"\n    upload:\n    - src: {}\n      dst: /tmp\n    - src: ./no-such-dist\n      dst: /tmp\n  migrate:\n    local: |\n      #source://./no-such-script.sh\ntargets:\n  release:\n  - deploy prod\n  - migrat\n",
+            present, present
+        );
+        let report = check_supfile("Supfile.yml", &yaml);
+        assert!(!report.ok);
+        assert_eq!(
+            kinds(&report),
+            vec![CheckKind::Hosts, CheckKind::Inventory, CheckKind::InventoryFile, CheckKind::Upload, CheckKind::Source, CheckKind::Targets],
+            "{:#?}",
+            report.problems
+        );
+        let messages: Vec<&str> = report.problems.iter().map(|problem| problem.message.as_str()).collect();
+        assert_eq!(messages[0], "networks.prod: host `root@10.0.0.2:ssh` has an invalid port `ssh`");
+        assert_eq!(report.problems[0].location(), Some((6, 7)));
+        assert!(messages[1].starts_with("networks.prod.inventory: invalid shell syntax"), "{}", messages[1]);
+        assert!(messages[2].contains("no-such-inventory.ini"), "{}", messages[2]);
+        assert_eq!(messages[3], "commands.deploy: upload src `./no-such-dist` does not exist");
+        assert_eq!(report.problems[3].location(), Some((16, 7)));
+        assert_eq!(messages[4], "commands.migrate.local: source file `./no-such-script.sh` does not exist");
+        assert!(messages[5].starts_with("targets.release: unknown command or target `migrat`"));
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["ok"], false);
+        assert_eq!(json["problems"][0]["check"], "hosts");
+        assert_eq!(json["problems"][0]["line"], 6);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_syntax_errors_stop_the_check() {
+        let report = check_supfile("Supfile.yml", "version: 0.5\ncommands:\n  deploy:\n    rnu: x\n");
+        assert_eq!(kinds(&report), vec![CheckKind::Syntax]);
+        assert_eq!(report.problems[0].location(), Some((4, 5)));
+        assert!(report.problems[0].message.contains("did you mean `run`?"));
+    }
+
+    #[test]
+    fn test_host_strings() {
+        assert_eq!(host_string_problem("deploy@10.0.0.1:2222 | $(pass) << tube [role=web]"), None);
+        assert_eq!(host_string_problem("10.0.0.1 [role=web"), Some("has an unterminated tag list".to_string()));
+        assert_eq!(address_problem("@10.0.0.1"), Some("has an empty user before `@`".to_string()));
+        assert_eq!(address_problem("root@"), Some("has no host name after `@`".to_string()));
+        assert_eq!(address_problem("web 1"), Some("contains whitespace".to_string()));
+        assert_eq!(address_problem("10.0.0.1:70000"), Some("has an invalid port `70000`".to_string()));
+        assert_eq!(address_problem("fe80::1"), None);
+        assert_eq!(address_problem("[fe80::1]:22"), None);
+    }
+
+    #[test]
+    fn test_target_cycles() {
+        let yaml = "version: 0.5\ncommands:\n  build:\n    run: make\ntargets:\n  ci: [build, release]\n  release: [ci]\n";
+        let report = check_supfile("Supfile.yml", yaml);
+        let messages: Vec<&str> = report.problems.iter().map(|problem| problem.message.as_str()).collect();
+        assert_eq!(messages, vec!["targets.ci: target cycle: ci -> release -> ci", "targets.release: target cycle: release -> ci -> release"]);
+        assert_eq!(report.problems[0].location(), Some((6, 3)));
+    }
+
+    #[test]
+    fn test_a_clean_supfile_is_ok() {
+        let yaml = "version: 0.5\nnetworks:\n  prod:\n    hosts: [root@10.0.0.1]\n    inventory: echo root@10.0.0.2\ncommands:\n  deploy:\n    run: echo deploy\ntargets:\n  release:\n  - deploy prod\n";
+        let report = check_supfile("Supfile.yml", yaml);
+        assert!(report.ok, "{:#?}", report.problems);
+    }
+}
@@ -0,0 +1,274 @@
+use crate::entity::supfile::Supfile;
+use regex::Regex;
+use std::sync::OnceLock;
+
+/// Renders a Supfile parse error as `path:line:column: message`, with a
+/// "did you mean" hint for misspelled keys and the offending source line.
+pub fn render_yaml_error(path: &str, content: &str, error: &serde_yaml::Error) -> String {
+    let (location, message) = yaml_error(error);
+    render(path, content, location, &message)
+}
+
+/// Where a parse error points and its message, without serde's trailing location.
+pub fn yaml_error(error: &serde_yaml::Error) -> (Option<(usize, usize)>, String) {
+    let message = error.to_string();
+    let Some(location) = error.location() else {
+        return (None, with_suggestion(&message));
+    };
+    // the message ends with the location that is printed up front
+    let suffix = format!(" at line {} column {}", location.line(), location.column());
+    let message = message.strip_suffix(&suffix).unwrap_or(&message);
+    (Some((location.line(), location.column())), with_suggestion(message))
+}
+
+/// `path:line:column: message` and the source line, or `path: message`
+/// when the location is unknown.
+pub fn render(path: &str, content: &str, location: Option<(usize, usize)>, message: &str) -> String {
+    match location {
+        Some((line, column)) => format!("{}:{}:{}: {}\n{}", path, line, column, message, snippet(content, line, column)),
+        None => format!("{}: {}", path, message),
+    }
+}
+
+/// Appends "did you mean" to serde's `unknown field`/`unknown variant`
+/// messages when one of the expected names is close enough.
+fn with_suggestion(message: &str) -> String {
+    static UNKNOWN: OnceLock<Regex> = OnceLock::new();
+    static NAME: OnceLock<Regex> = OnceLock::new();
+    let unknown = UNKNOWN.get_or_init(|| Regex::new(r"unknown (?:field|variant) `([^`]*)`, expected (.*)$").unwrap());
+    let name = NAME.get_or_init(|| Regex::new(r"`([^`]*)`").unwrap());
+
+    let Some(captures) = unknown.captures(message) else {
+        return message.to_string();
+    };
+    let expected: Vec<&str> = name.captures_iter(&captures[2]).map(|c| c.get(1).unwrap().as_str()).collect();
+    match did_you_mean(&captures[1], expected) {
+        Some(suggestion) => format!("{}; did you mean `{}`?", message, suggestion),
+        None => message.to_string(),
+    }
+}
+
+/// The candidate closest to `word`, if it is a plausible typo of it.
+pub fn did_you_mean<'a>(word: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let limit = (word.chars().count() / 3).max(1);
+    candidates
+        .into_iter()
+        .map(|candidate| (edit_distance(word, candidate), candidate))
+        .filter(|(distance, _)| *distance <= limit)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Levenshtein distance, counting a swap of two neighbours as one edit.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut rows = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    rows[0] = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut best = (rows[i - 1][j] + 1).min(rows[i][j - 1] + 1).min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                best = best.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = best;
+        }
+    }
+    rows[a.len()][b.len()]
+}
+
+/// The source line with a caret under `column`, both 1-based.
+fn snippet(content: &str, line: usize, column: usize) -> String {
+    let Some(text) = content.lines().nth(line.saturating_sub(1)) else {
+        return String::new();
+    };
+    let number = line.to_string();
+    let gutter = " ".repeat(number.len());
+    format!(
+        "{} |\n{} | {}\n{} | {}^",
+        gutter,
+        number,
+        text,
+        gutter,
+        " ".repeat(column.saturating_sub(1))
+    )
+}
+
+/// Targets naming a command, target or network the Supfile doesn't define, one
+/// rendered diagnostic each, in the order they appear in the file.
+pub fn check_references(path: &str, content: &str, supfile: &Supfile) -> Vec<String> {
+    unknown_references(content, supfile)
+        .into_iter()
+        .map(|(location, message)| render(path, content, location, &message))
+        .collect()
+}
+
+/// The location and message of every unknown target reference, in file order.
+pub fn unknown_references(content: &str, supfile: &Supfile) -> Vec<(Option<(usize, usize)>, String)> {
+    let mut problems = Vec::new();
+    for name in &supfile.targets.names {
+        for target in supfile.targets.targets.get(name).into_iter().flatten() {
+            let location = find_line(content, name, Some((&target.command, &target.affixed_network)))
+                .or_else(|| find_line(content, name, None));
+            let mut report = |what: &str, unknown: &str, known: Vec<&str>| {
+                let mut message = format!("targets.{}: unknown {} `{}`", name, what, unknown);
+                if let Some(suggestion) = did_you_mean(unknown, known) {
+                    message.push_str(&format!("; did you mean `{}`?", suggestion));
+                }
+                problems.push((location, message));
+            };
+
+            if !supfile.commands.contains_key(&target.command) && !supfile.targets.has(&target.command) {
+                let known = supfile.commands.keys().chain(&supfile.targets.names).map(String::as_str).collect();
+                report("command or target", &target.command, known);
+            }
+            if !target.affixed_network.is_empty() && supfile.networks.get(&target.affixed_network).is_none() {
+                report("network", &target.affixed_network, supfile.networks.names.iter().map(String::as_str).collect());
+            }
+        }
+    }
+    problems
+}
+
+/// Line and column of the first occurrence of `text` outside comments.
+pub fn find_text(content: &str, text: &str) -> Option<(usize, usize)> {
+    if text.is_empty() {
+        return None;
+    }
+    content.lines().enumerate().find_map(|(index, line)| {
+        if line.trim_start().starts_with("# ") {
+            return None;
+        }
+        line.find(text).map(|byte| (index + 1, line[..byte].chars().count() + 1))
+    })
+}
+
+/// Line and column of the key at `path`, e.g. `["commands", "deploy", "run"]`,
+/// following block mappings down by indentation.
+pub fn find_key(content: &str, path: &[&str]) -> Option<(usize, usize)> {
+    let mut depth = 0;
+    let mut parent_indent: Option<usize> = None;
+    let mut child_indent: Option<usize> = None;
+    for (index, line) in content.lines().enumerate() {
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let indent = line.len() - trimmed.len();
+        if parent_indent.is_some_and(|parent| indent <= parent) {
+            return None;
+        }
+        let indent_of_children = *child_indent.get_or_insert(indent);
+        let Some((key, _)) = trimmed.split_once(':') else {
+            continue;
+        };
+        if indent != indent_of_children || key.trim().trim_matches(|c| c == '"' || c == '\'') != *path.get(depth)? {
+            continue;
+        }
+        depth += 1;
+        if depth == path.len() {
+            return Some((index + 1, indent + 1));
+        }
+        parent_indent = Some(indent);
+        child_indent = None;
+    }
+    None
+}
+
+/// Line and column of target `name` under the top-level `targets:` key or,
+/// given `(command, network)`, of that list item within it.
+fn find_line(content: &str, name: &str, item: Option<(&str, &str)>) -> Option<(usize, usize)> {
+    let mut lines = content.lines().enumerate().skip_while(|(_, line)| line.trim_end() != "targets:");
+    lines.next()?;
+    let mut in_target = false;
+    for (index, line) in lines {
+        let trimmed = line.trim_start();
+        let column = line.len() - trimmed.len() + 1;
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        if column == 1 {
+            return None;
+        }
+        if let Some(key) = trimmed.strip_suffix(':').or_else(|| trimmed.split_once(':').map(|(key, _)| key)) {
+            if !trimmed.starts_with('-') {
+                in_target = key.trim_matches(|c| c == '"' || c == '\'') == name;
+                if in_target && item.is_none() {
+                    return Some((index + 1, column));
+                }
+                continue;
+            }
+        }
+        if let (true, Some((command, network)), Some(entry)) = (in_target, item, trimmed.strip_prefix('-')) {
+            let words: Vec<&str> = entry.trim().trim_matches(|c| c == '"' || c == '\'').split_whitespace().collect();
+            let expected: Vec<&str> = [command, network].into_iter().filter(|word| !word.is_empty()).collect();
+            if words.starts_with(&expected) {
+                return Some((index + 1, column + entry.len() - entry.trim_start().len() + 1));
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_error(yaml: &str) -> String {
+        let error = serde_yaml::from_str::<Supfile>(yaml).unwrap_err();
+        render_yaml_error("Supfile.yml", yaml, &error)
+    }
+
+    #[test]
+    fn test_typos_point_at_the_key() {
+        let yaml = "version: 0.5\ncommands:\n  deploy:\n    run: ./deploy\n    uplaod:\n    - src: a\n      dst: b\n";
+        let rendered = parse_error(yaml);
+        assert!(rendered.starts_with("Supfile.yml:5:5: commands.deploy: unknown field `uplaod`"), "{}", rendered);
+        assert!(rendered.contains("did you mean `upload`?"), "{}", rendered);
+        assert!(rendered.ends_with("  |\n5 |     uplaod:\n  |     ^"), "{}", rendered);
+
+        let yaml = "version: 0.5\nnetworks:\n  prod:\n    inventroy: ./hosts.sh\ncommands: {}\n";
+        assert!(parse_error(yaml).contains("unknown field `inventroy`, expected one of"));
+        assert!(parse_error(yaml).contains("did you mean `inventory`?"));
+
+        let yaml = "version: 0.5\nnetworks:\n  prod:\n    hosts:\n    - host: a\n      tgas: [web]\ncommands: {}\n";
+        assert!(parse_error(yaml).contains("did you mean `tags`?"), "{}", parse_error(yaml));
+
+        let yaml = "version: 0.5\nshell_output: strp\ncommands: {}\n";
+        assert!(parse_error(yaml).contains("did you mean `strip`?"));
+    }
+
+    #[test]
+    fn test_no_suggestion_for_unrelated_keys() {
+        let yaml = "version: 0.5\ncommands: {}\nfrobnicate: true\n";
+        let rendered = parse_error(yaml);
+        assert!(rendered.contains("unknown field `frobnicate`"), "{}", rendered);
+        assert!(!rendered.contains("did you mean"));
+        assert_eq!(did_you_mean("inventroy", ["inventory", "inventory_ttl"]), Some("inventory"));
+        assert_eq!(did_you_mean("env", ["desc", "run"]), None);
+    }
+
+    #[test]
+    fn test_find_key() {
+        let yaml = "version: 0.5\nenv:\n  run: x\ncommands:\n  build:\n    local: make\n  deploy:\n    desc: ship it\n    run: |\n      echo run: now\n";
+        assert_eq!(find_key(yaml, &["commands", "deploy", "run"]), Some((9, 5)));
+        assert_eq!(find_key(yaml, &["commands", "build"]), Some((5, 3)));
+        assert_eq!(find_key(yaml, &["commands", "build", "run"]), None);
+        assert_eq!(find_key(yaml, &["targets"]), None);
+    }
+
+    #[test]
+    fn test_unknown_target_references() {
+        let yaml = "version: 0.5\nnetworks:\n  prod:\n    hosts: [a]\ncommands:\n  deploy:\n    run: x\n  migrate:\n    run: y\ntargets:\n  release:\n  - deploy prod\n  - migrat prod\n  - deploy prdo\n";
+        let supfile: Supfile = serde_yaml::from_str(yaml).unwrap();
+        let problems = check_references("Supfile.yml", yaml, &supfile);
+        assert_eq!(problems.len(), 2, "{:?}", problems);
+        assert!(problems[0].starts_with("Supfile.yml:13:5: targets.release: unknown command or target `migrat`; did you mean `migrate`?\n"), "{}", problems[0]);
+        assert!(problems[0].ends_with("13 |   - migrat prod\n   |     ^"), "{}", problems[0]);
+        assert!(problems[1].starts_with("Supfile.yml:14:5: targets.release: unknown network `prdo`; did you mean `prod`?"), "{}", problems[1]);
+    }
+}
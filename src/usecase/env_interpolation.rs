@@ -0,0 +1,319 @@
+use crate::entity::env::EnvList;
+use crate::entity::supfile::OutputPolicy;
+use crate::gateways::logger::logger_func as l;
+use crate::usecase::parse_network::{resolve_shell_with_env, ShellResolveError};
+use indexmap::IndexMap;
+use std::env;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum InterpolationError {
+    #[error("{key}: reference to undefined variable ${{{reference}}}")]
+    Undefined {
+        key: String,
+        reference: String,
+        trace_id: &'static str,
+    },
+    #[error("env variables reference each other in a cycle: {}", chain.join(" -> "))]
+    Cycle {
+        chain: Vec<String>,
+        trace_id: &'static str,
+    },
+    #[error("{key}: unterminated {what}")]
+    Unterminated {
+        key: String,
+        what: &'static str,
+        trace_id: &'static str,
+    },
+    #[error("{key}: failed to evaluate $(...): {source}")]
+    Shell {
+        key: String,
+        #[source]
+        source: ShellResolveError,
+    },
+}
+
+impl InterpolationError {
+    pub fn trace_id(&self) -> &'static str {
+        match self {
+            InterpolationError::Undefined { trace_id, .. }
+            | InterpolationError::Cycle { trace_id, .. }
+            | InterpolationError::Unterminated { trace_id, .. } => trace_id,
+            InterpolationError::Shell { source, .. } => source.trace_id(),
+        }
+    }
+}
+
+/// One piece of a raw env value.
+#[derive(Debug, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    /// `${NAME}`, or `$NAME` inside a shell snippet
+    Reference(String),
+    /// `$(...)`, including the `$(` and `)`
+    Shell(String),
+}
+
+/// Evaluates `${VAR}` and `$(cmd)` in every value, in insertion order.
+///
+/// A reference resolves to the evaluated value of that key; keys defined later
+/// in the list are evaluated first, on demand. A key referencing itself, or a
+/// name the list doesn't define, falls back to the local process environment,
+/// so `PATH: ${PATH}:/opt/bin` works. Anything still undefined, and any cycle
+/// between keys, is an error. `$(cmd)` runs locally with the variables
+/// evaluated so far exported, and like in a shell its trailing newlines are
+/// dropped. A bare `$VAR` and `\$` are left for the remote shell, the latter
+/// as a literal `$`. Raw values are meant for the remote shell and are kept as
+/// they are.
+pub fn evaluate_env(list: &EnvList) -> Result<EnvList, InterpolationError> {
+    let mut evaluator = Evaluator { list, done: IndexMap::new(), stack: Vec::new() };
+    for key in list.keys() {
+        evaluator.evaluate(key)?;
+    }
+    // `done` is filled in dependency order, report in the list's order instead
+    let mut evaluated = EnvList::new();
+    for key in list.keys() {
+        let value = evaluator.done[key.as_str()].clone();
+        if list.is_raw(key) {
+            evaluated.set_raw(key.clone(), value);
+        } else {
+            evaluated.set(key.clone(), value);
+        }
+    }
+    Ok(evaluated)
+}
+
+struct Evaluator<'a> {
+    list: &'a EnvList,
+    done: IndexMap<String, String>,
+    stack: Vec<String>,
+}
+
+impl Evaluator<'_> {
+    fn evaluate(&mut self, key: &str) -> Result<String, InterpolationError> {
+        if let Some(value) = self.done.get(key) {
+            return Ok(value.clone());
+        }
+        if let Some(start) = self.stack.iter().position(|k| k == key) {
+            let mut chain = self.stack[start..].to_vec();
+            chain.push(key.to_string());
+            return Err(InterpolationError::Cycle { chain, trace_id: "B85E0C3D-1F7A-4E92-A6D4-5C3B8E9F0A21" });
+        }
+
+        let raw = self.list.get(key).unwrap_or_default().to_string();
+        if self.list.is_raw(key) {
+            self.done.insert(key.to_string(), raw.clone());
+            return Ok(raw);
+        }
+        self.stack.push(key.to_string());
+        let result = self.expand(key, &raw);
+        self.stack.pop();
+
+        let value = result?;
+        l(&format!("env: evaluated {}", key));
+        self.done.insert(key.to_string(), value.clone());
+        Ok(value)
+    }
+
+    fn lookup(&mut self, key: &str, reference: &str) -> Result<String, InterpolationError> {
+        if reference != key && self.list.contains_key(reference) {
+            return self.evaluate(reference);
+        }
+        env::var(reference).map_err(|_| InterpolationError::Undefined {
+            key: key.to_string(),
+            reference: reference.to_string(),
+            trace_id: "4F19D6A2-7C30-4B85-9E1A-D2C6F8B3057E",
+        })
+    }
+
+    fn expand(&mut self, key: &str, raw: &str) -> Result<String, InterpolationError> {
+        let mut value = String::new();
+        for part in split_parts(key, raw, false)? {
+            match part {
+                Part::Text(text) => value.push_str(&text),
+                Part::Reference(reference) => value.push_str(&self.lookup(key, &reference)?),
+                Part::Shell(cmd) => {
+                    // make sure everything the command mentions is evaluated before it runs
+                    for reference in references_in(&cmd) {
+                        if reference != key && self.list.contains_key(&reference) {
+                            self.evaluate(&reference)?;
+                        }
+                    }
+                    let exported: EnvList = self.done.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+                    let output = resolve_shell_with_env(&cmd, &exported, OutputPolicy::Raw)
+                        .map_err(|source| InterpolationError::Shell { key: key.to_string(), source })?;
+                    value.push_str(output.trim_end_matches(['\n', '\r']));
+                }
+            }
+        }
+        Ok(value)
+    }
+}
+
+fn is_name_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_'
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// Splits `raw` into text, references and shell snippets. A bare `$NAME` is a
+/// reference only with `bare_names`, otherwise it stays text.
+fn split_parts(key: &str, raw: &str, bare_names: bool) -> Result<Vec<Part>, InterpolationError> {
+    let chars: Vec<char> = raw.chars().collect();
+    let mut parts = Vec::new();
+    let mut text = String::new();
+    let mut i = 0;
+
+    let unterminated = |what| InterpolationError::Unterminated {
+        key: key.to_string(),
+        what,
+        trace_id: "E07A4C19-58B2-4D3F-8C6E-91A0B7D2F4C5",
+    };
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '\\' && chars.get(i + 1) == Some(&'$') {
+            text.push('$');
+            i += 2;
+            continue;
+        }
+        if c != '$' {
+            text.push(c);
+            i += 1;
+            continue;
+        }
+
+        match chars.get(i + 1) {
+            Some('{') => {
+                let end = chars[i + 2..].iter().position(|&c| c == '}').ok_or_else(|| unterminated("${"))?;
+                let name: String = chars[i + 2..i + 2 + end].iter().collect();
+                flush(&mut text, &mut parts);
+                parts.push(Part::Reference(name));
+                i += end + 3;
+            }
+            Some('(') => {
+                let mut depth = 0;
+                let mut end = None;
+                for (j, &c) in chars.iter().enumerate().skip(i + 1) {
+                    match c {
+                        '(' => depth += 1,
+                        ')' => {
+                            depth -= 1;
+                            if depth == 0 {
+                                end = Some(j);
+                                break;
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                let end = end.ok_or_else(|| unterminated("$("))?;
+                flush(&mut text, &mut parts);
+                parts.push(Part::Shell(chars[i..=end].iter().collect()));
+                i = end + 1;
+            }
+            Some(&c) if bare_names && is_name_start(c) => {
+                let len = chars[i + 1..].iter().take_while(|&&c| is_name_char(c)).count();
+                flush(&mut text, &mut parts);
+                parts.push(Part::Reference(chars[i + 1..i + 1 + len].iter().collect()));
+                i += len + 1;
+            }
+            _ => {
+                text.push('$');
+                i += 1;
+            }
+        }
+    }
+    flush(&mut text, &mut parts);
+    Ok(parts)
+}
+
+fn flush(text: &mut String, parts: &mut Vec<Part>) {
+    if !text.is_empty() {
+        parts.push(Part::Text(std::mem::take(text)));
+    }
+}
+
+/// Names a shell snippet mentions as `$NAME` or `${NAME}`.
+fn references_in(cmd: &str) -> Vec<String> {
+    let inner = cmd.strip_prefix("$(").and_then(|c| c.strip_suffix(')')).unwrap_or(cmd);
+    split_parts("", inner, true)
+        .map(|parts| {
+            parts
+                .into_iter()
+                .filter_map(|part| match part {
+                    Part::Reference(name) => Some(name),
+                    _ => None,
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list(yaml: &str) -> EnvList {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn test_references_earlier_and_later_values() {
+        let evaluated = evaluate_env(&list("FIRST: hello\nREF: ${FIRST}_${SECOND}\nSECOND: world\nBARE: $FIRST-x")).unwrap();
+        assert_eq!(evaluated.get("REF"), Some("hello_world"));
+        assert_eq!(evaluated.get("BARE"), Some("$FIRST-x"));
+        // order of the input is kept
+        assert_eq!(evaluated.keys_owned(), vec!["FIRST", "REF", "SECOND", "BARE"]);
+    }
+
+    #[test]
+    fn test_shell_evaluation_sees_earlier_values() {
+        let evaluated = evaluate_env(&list("NAME: ssup\nGREETING: $(echo hi $NAME)\nUPPER: $(printf %s \"$GREETING\" | tr a-z A-Z)")).unwrap();
+        assert_eq!(evaluated.get("GREETING"), Some("hi ssup"));
+        assert_eq!(evaluated.get("UPPER"), Some("HI SSUP"));
+    }
+
+    #[test]
+    fn test_self_reference_uses_process_env() {
+        std::env::set_var("SSUP_INTERPOLATION_TEST", "/usr/bin");
+        let evaluated = evaluate_env(&list("SSUP_INTERPOLATION_TEST: ${SSUP_INTERPOLATION_TEST}:/opt/bin")).unwrap();
+        assert_eq!(evaluated.get("SSUP_INTERPOLATION_TEST"), Some("/usr/bin:/opt/bin"));
+    }
+
+    #[test]
+    fn test_escaped_and_lone_dollars_are_literal() {
+        let evaluated = evaluate_env(&list("PRICE: \\$5 or $ 6 or $$")).unwrap();
+        assert_eq!(evaluated.get("PRICE"), Some("$5 or $ 6 or $$"));
+    }
+
+    #[test]
+    fn test_bare_names_are_left_for_the_remote_shell() {
+        let evaluated = evaluate_env(&list("DIR: $HOME/x")).unwrap();
+        assert_eq!(evaluated.get("DIR"), Some("$HOME/x"));
+    }
+
+    #[test]
+    fn test_raw_values_are_left_for_the_remote_shell() {
+        let evaluated = evaluate_env(&list("DIR: /opt\nREMOTE:\n  raw: $HOME$(id -u)\nLOCAL: ${DIR}")).unwrap();
+        assert_eq!(evaluated.get("REMOTE"), Some("$HOME$(id -u)"));
+        assert!(evaluated.is_raw("REMOTE"));
+        assert_eq!(evaluated.get("LOCAL"), Some("/opt"));
+    }
+
+    #[test]
+    fn test_cycles_and_undefined_are_rejected() {
+        match evaluate_env(&list("A: ${B}\nB: x${C}\nC: ${A}")) {
+            Err(InterpolationError::Cycle { chain, .. }) => assert_eq!(chain, vec!["A", "B", "C", "A"]),
+            other => panic!("expected a cycle, got {:?}", other),
+        }
+        assert!(matches!(
+            evaluate_env(&list("A: ${SSUP_SURELY_NOT_DEFINED_ANYWHERE}")),
+            Err(InterpolationError::Undefined { .. })
+        ));
+        assert!(matches!(evaluate_env(&list("A: ${B")), Err(InterpolationError::Unterminated { .. })));
+        assert!(matches!(evaluate_env(&list("A: $(echo")), Err(InterpolationError::Unterminated { .. })));
+    }
+}
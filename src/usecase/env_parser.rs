@@ -1,15 +1,227 @@
-use std::collections::HashMap;
-
-pub fn parse_env(s: &str) -> HashMap<String, String> {
-    let mut result: HashMap<String, String> = HashMap::new();
-    // split on spaces
-    for elem in s.split(" ") {
-        // get the key and value from the env variable
-        let mut key_val = elem.split("=");
-        let key = key_val.next().unwrap().to_string();
-        let val = key_val.next().unwrap().to_string();
-        // add the key and value to the HashMap
-        result.insert(key, val);
-    }
-    return result;
+use crate::entity::env::{self, EnvList};
+use crate::entity::InitState;
+use crate::gateways::logger::logger_func as l;
+use std::path::Path;
+use std::{fs, io};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum EnvParseError {
+    #[error("'{token}' is not KEY=VALUE")]
+    MissingEquals {
+        token: String,
+        trace_id: &'static str,
+    },
+    #[error("'{key}' is not a valid variable name")]
+    InvalidKey {
+        key: String,
+        trace_id: &'static str,
+    },
+    #[error("unclosed {quote} quote in '{input}'")]
+    UnclosedQuote {
+        quote: char,
+        input: String,
+        trace_id: &'static str,
+    },
+    #[error("failed to read env file {path}: {source}")]
+    ReadFile {
+        path: String,
+        #[source]
+        source: io::Error,
+    },
+    #[error("{path}:{line}: {source}")]
+    InFile {
+        path: String,
+        line: usize,
+        #[source]
+        source: Box<EnvParseError>,
+    },
+}
+
+impl EnvParseError {
+    pub fn trace_id(&self) -> &'static str {
+        match self {
+            EnvParseError::MissingEquals { trace_id, .. }
+            | EnvParseError::InvalidKey { trace_id, .. }
+            | EnvParseError::UnclosedQuote { trace_id, .. } => trace_id,
+            EnvParseError::ReadFile { .. } => "327EFFE0-4EFA-4A30-A312-49970BF9074F",
+            EnvParseError::InFile { source, .. } => source.trace_id(),
+        }
+    }
+}
+
+/// Parses the values of every `-e`/`--env` flag, in order; later values win.
+///
+/// Each value is either a list of `KEY=VALUE` pairs, split like a shell would
+/// (`-e 'A="x y" B=c=d'`), or `@path` to load a dotenv file.
+pub fn parse_env_args(values: &[String]) -> Result<EnvList, EnvParseError> {
+    let mut result = EnvList::new();
+    for value in values {
+        match value.strip_prefix('@') {
+            Some(path) => result.extend(&parse_env_file(path)?),
+            None => result.extend(&parse_env(value)?),
+        }
+    }
+    Ok(result)
+}
+
+/// Makes relative `@path` values absolute against `cwd`, the directory ssup
+/// was started in, so they still name the same file once ssup has changed
+/// into the Supfile's directory.
+pub fn anchor_env_files(values: &[String], cwd: &Path) -> Vec<String> {
+    values
+        .iter()
+        .map(|value| match value.strip_prefix('@') {
+            Some(path) if Path::new(path).is_relative() => format!("@{}", cwd.join(path).display()),
+            _ => value.clone(),
+        })
+        .collect()
+}
+
+/// The `-e` variables of this run.
+pub fn env_from_args(init_state: &InitState) -> Result<EnvList, EnvParseError> {
+    parse_env_args(&init_state.flags.env)
+}
+
+/// Parses `KEY=VALUE` pairs separated by whitespace. Single quotes keep
+/// everything literally, double quotes allow `\"`, `\\`, `\$` and `` \` ``,
+/// a backslash outside quotes escapes the next character.
+pub fn parse_env(s: &str) -> Result<EnvList, EnvParseError> {
+    let mut result = EnvList::new();
+    for token in split_words(s)? {
+        let (key, val) = split_pair(&token)?;
+        // add the key and value to the list, keeping CLI order
+        result.set(key, val);
+    }
+    Ok(result)
+}
+
+/// Loads a dotenv file: one `KEY=VALUE` per line, optionally prefixed with
+/// `export`, values quoted the same way as on the command line. Blank lines
+/// and lines starting with `#` are skipped.
+pub fn parse_env_file(path: &str) -> Result<EnvList, EnvParseError> {
+    l(&format!("loading env file {}", path));
+    let content = fs::read_to_string(path).map_err(|source| EnvParseError::ReadFile { path: path.to_string(), source })?;
+    let in_file = |line: usize, source: EnvParseError| EnvParseError::InFile {
+        path: path.to_string(),
+        line,
+        source: Box::new(source),
+    };
+
+    let mut result = EnvList::new();
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").map(str::trim_start).unwrap_or(line);
+        let words = split_words(line).map_err(|e| in_file(index + 1, e))?;
+        // a trailing comment is the only thing allowed after the pair
+        let pair = match words.as_slice() {
+            [pair] => pair,
+            [pair, comment, ..] if comment.starts_with('#') => pair,
+            _ => {
+                let e = EnvParseError::MissingEquals {
+                    token: line.to_string(),
+                    trace_id: "9C2E71B4-3A5D-4F08-B6E9-0D17A4C85F32",
+                };
+                return Err(in_file(index + 1, e));
+            }
+        };
+        let (key, val) = split_pair(pair).map_err(|e| in_file(index + 1, e))?;
+        result.set(key, val);
+    }
+    Ok(result)
+}
+
+fn split_pair(token: &str) -> Result<(String, String), EnvParseError> {
+    let (key, val) = token.split_once('=').ok_or_else(|| EnvParseError::MissingEquals {
+        token: token.to_string(),
+        trace_id: "5B8D0E3F-6A21-4C7B-9F4E-E2A36C1D8B70",
+    })?;
+    let mut chars = key.chars();
+    let valid = chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid {
+        return Err(EnvParseError::InvalidKey {
+            key: key.to_string(),
+            trace_id: "D41F6A08-2E9C-4B53-8A7D-3C5E0B96F1A4",
+        });
+    }
+    Ok((key.to_string(), val.to_string()))
+}
+
+/// `env::split_words`, failing with the input on an unclosed quote.
+fn split_words(s: &str) -> Result<Vec<String>, EnvParseError> {
+    env::split_words(s).map_err(|quote| EnvParseError::UnclosedQuote {
+        quote,
+        input: s.to_string(),
+        trace_id: "71A3C5E9-0B4D-4E26-9F8A-B6D2E4071C3F",
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pairs(env: &EnvList) -> Vec<String> {
+        env.to_string_vec()
+    }
+
+    #[test]
+    fn test_parse_env_quoting() {
+        assert!(parse_env("").unwrap().is_empty());
+        assert!(parse_env("   ").unwrap().is_empty());
+        assert_eq!(pairs(&parse_env(r#"A="x y" B=c=d"#).unwrap()), vec!["A=x y", "B=c=d"]);
+        assert_eq!(pairs(&parse_env(r#"A='$HOME "q"' B="a \"b\" \$c" C=x\ y D="#).unwrap()), vec![
+            r#"A=$HOME "q""#,
+            r#"B=a "b" $c"#,
+            "C=x y",
+            "D=",
+        ]);
+        assert_eq!(pairs(&parse_env("A=1 A=2").unwrap()), vec!["A=2"]);
+    }
+
+    #[test]
+    fn test_parse_env_errors() {
+        assert!(matches!(parse_env("A=1 oops"), Err(EnvParseError::MissingEquals { .. })));
+        assert!(matches!(parse_env("=1"), Err(EnvParseError::InvalidKey { .. })));
+        assert!(matches!(parse_env("1A=1"), Err(EnvParseError::InvalidKey { .. })));
+        assert!(matches!(parse_env("A='x"), Err(EnvParseError::UnclosedQuote { quote: '\'', .. })));
+        assert!(matches!(parse_env("A=\"x"), Err(EnvParseError::UnclosedQuote { quote: '"', .. })));
+    }
+
+    #[test]
+    fn test_repeated_flags_and_env_files() {
+        let dir = std::env::temp_dir().join(format!("ssup-env-parser-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("deploy.env");
+        fs::write(&file, "# deploy settings\n\nexport A=file\nB=\"x y\" # trailing\nC='c=d'\n").unwrap();
+
+        let values = vec!["A=cli Z=1".to_string(), format!("@{}", file.display()), "C=last".to_string()];
+        let env = parse_env_args(&values).unwrap();
+        assert_eq!(pairs(&env), vec!["A=file", "Z=1", "B=x y", "C=last"]);
+
+        fs::write(&file, "A=1\nnot a pair\n").unwrap();
+        let err = parse_env_args(&[format!("@{}", file.display())]).unwrap_err();
+        assert!(matches!(err, EnvParseError::InFile { line: 2, .. }), "{:?}", err);
+
+        let missing = parse_env_args(&[format!("@{}", dir.join("missing.env").display())]);
+        assert!(matches!(missing, Err(EnvParseError::ReadFile { .. })));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_env_files_are_relative_to_the_start_dir() {
+        let dir = std::env::temp_dir().join(format!("ssup-env-anchor-{}", std::process::id()));
+        fs::create_dir_all(dir.join("conf")).unwrap();
+        fs::write(dir.join("conf/prod.env"), "A=from-start-dir\n").unwrap();
+
+        let values = vec!["@conf/prod.env".to_string(), "@/etc/ssup.env".to_string(), "B=@x".to_string()];
+        let anchored = anchor_env_files(&values, &dir);
+        assert_eq!(anchored, vec![format!("@{}", dir.join("conf/prod.env").display()), "@/etc/ssup.env".to_string(), "B=@x".to_string()]);
+        // found whatever the current directory is by now
+        assert_eq!(parse_env_args(&anchored[..1]).unwrap().get("A"), Some("from-start-dir"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -0,0 +1,291 @@
+use crate::entity::const_values::SUP_HOST_ENV;
+use crate::entity::env::{EnvList, EnvSource, ResolvedEnv, ResolvedVar};
+use crate::entity::playbook::{CommandEnv, PlayBook};
+use crate::entity::secret::REDACTED;
+use crate::entity::help_displayer::HelpDisplayer;
+use crate::entity::supfile::commands::Command;
+use crate::entity::supfile::networks::{HostDetails, HostEntry, Network};
+use crate::entity::InitState;
+use crate::gateways::logger::logger_func as l;
+use crate::usecase::env_interpolation::{evaluate_env, InterpolationError};
+use crate::usecase::env_parser::env_from_args;
+use crate::usecase::error::SsupError;
+use crate::usecase::parse_network::parse_inventory;
+use crate::usecase::{ensure_network_exists, host_details, host_without_user, ssup_default_envs};
+
+/// Builds the environment a command sees on a host.
+///
+/// Layers are applied in this order, each one overriding the previous:
+///
+/// 1. `SUP_*` variables set by ssup
+/// 2. Supfile `env`
+/// 3. network `env`
+/// 4. host `env`
+/// 5. command `env`
+/// 6. `-e`/`--env` from the command line
+///
+/// Keys stay in the order they were first defined, which is YAML order
+/// within each layer.
+pub fn resolve_env(
+    init_state: &InitState,
+    network: &Network,
+    host: Option<&HostDetails>,
+    command: Option<&Command>,
+    cli: &EnvList,
+) -> ResolvedEnv {
+    let mut builtin = ssup_default_envs(network, init_state);
+    if let Some(host) = host {
+        builtin.set(SUP_HOST_ENV.to_string(), host_without_user(&host.host).to_string());
+    }
+
+    let mut resolved = ResolvedEnv::new();
+    resolved.layer(EnvSource::Ssup, &builtin);
+    resolved.layer(EnvSource::Supfile, &init_state.supfile.env);
+    if let Some(env) = &network.env {
+        resolved.layer(EnvSource::Network, env);
+    }
+    if let Some(env) = host.and_then(|host| host.env.as_ref()) {
+        resolved.layer(EnvSource::Host, env);
+    }
+    if let Some(env) = command.and_then(|command| command.env.as_ref()) {
+        resolved.layer(EnvSource::Command, env);
+    }
+    resolved.layer(EnvSource::Cli, cli);
+    resolved
+}
+
+/// Fills `Play::envs` with what every command gets on every host of its
+/// play, see [`resolve_env`]. Values are not evaluated here, prompts and
+/// secret references still change them; see [`evaluate_playbook_env`].
+pub fn resolve_playbook_env(init_state: &InitState, playbook: &mut PlayBook, cli: &EnvList) {
+    for play in playbook.plays.iter_mut() {
+        let Some(network) = play.nets.as_ref() else {
+            continue;
+        };
+        let mut hosts: Vec<(String, Option<HostDetails>)> = network
+            .hosts
+            .iter()
+            .map(host_details)
+            .map(|details| (format!("{}/{}", network.name, host_without_user(&details.host)), Some(details)))
+            .collect();
+        if hosts.is_empty() {
+            hosts.push((network.name.clone(), None));
+        }
+
+        let mut envs = Vec::new();
+        for (index, command) in play.commands.iter().enumerate() {
+            for (target, host) in &hosts {
+                let env = resolve_env(init_state, network, host.as_ref(), Some(command), cli).to_env_list();
+                envs.push(CommandEnv { target: target.clone(), command: index, env });
+            }
+        }
+        play.envs = envs;
+    }
+}
+
+/// Expands `${VAR}` references and `$(cmd)` in the env of every command on
+/// every host, once prompts are answered and secret references resolved.
+/// Raw, secret and the other markings of each value are kept.
+pub fn evaluate_playbook_env(playbook: &mut PlayBook) -> Result<(), SsupError> {
+    for command_env in playbook.plays.iter_mut().flat_map(|play| play.envs.iter_mut()) {
+        let evaluated = evaluate_env(&command_env.env)?;
+        for (key, value) in evaluated.iter() {
+            if command_env.env.get(key) != Some(value.as_str()) {
+                command_env.env.set(key.clone(), value.clone());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Runs the network's `inventory:` with its env resolved like a command's,
+/// without the `SUP_*` variables: they change on every run and would defeat
+/// the inventory cache.
+pub fn inventory_hosts(init_state: &InitState, network: &Network, cli: &EnvList) -> Result<Vec<HostEntry>, SsupError> {
+    let resolved = resolve_env(init_state, network, None, None, cli);
+    let mut env = resolved.to_env_list();
+    for (key, var) in resolved.iter() {
+        if var.source == EnvSource::Ssup {
+            env.remove(key);
+        }
+    }
+    let mut inventory_network = network.clone();
+    inventory_network.env = Some(env);
+    Ok(parse_inventory(&inventory_network, init_state.flags.refresh_inventory)?)
+}
+
+/// Expands `${VAR}` references and `$(cmd)` in the final values, in the order
+/// the keys were first defined, see [`evaluate_env`].
+pub fn evaluate_resolved(mut resolved: ResolvedEnv) -> Result<ResolvedEnv, InterpolationError> {
+    let evaluated = evaluate_env(&resolved.to_env_list())?;
+    resolved.set_values(&evaluated);
+    Ok(resolved)
+}
+
+/// `ssup env NETWORK [HOST]`: prints the final environment of the network,
+/// or of one of its hosts, with the layer each variable came from.
+pub fn env_command(init_state: &InitState) -> Result<(), SsupError> {
+    let help_menu = HelpDisplayer::new(init_state.clone());
+    let (network_name, host_name) = match init_state.args.as_slice() {
+        [_, network] => (network, None),
+        [_, network, host] => (network, Some(host)),
+        _ => {
+            return Err(SsupError::Usage { message: "Usage: ssup env NETWORK [HOST]".to_string() });
+        }
+    };
+
+    ensure_network_exists(network_name, init_state, &help_menu)?;
+    let network = init_state.supfile.networks.get(network_name).cloned().ok_or_else(|| SsupError::NetworkNotFound {
+        name: network_name.clone(),
+        trace_id: "18FECFC4-968C-489B-8108-924453B337B9",
+    })?;
+    let cli = env_from_args(init_state)?;
+
+    let host = match host_name {
+        Some(host_name) => Some(find_host(&network, &cli, init_state, host_name)?.ok_or_else(|| SsupError::HostNotFound {
+            host: host_name.clone(),
+            network: network_name.clone(),
+            trace_id: "99478F08-E3ED-4A95-BAB5-DEC9383D2663",
+        })?),
+        None => None,
+    };
+
+    let resolved = evaluate_resolved(resolve_env(init_state, &network, host.as_ref(), None, &cli))?;
+    print!("{}", format_resolved(&resolved));
+    Ok(())
+}
+
+/// Looks the host up among the static hosts first, then the inventory.
+fn find_host(network: &Network, cli: &EnvList, init_state: &InitState, wanted: &str) -> Result<Option<HostDetails>, SsupError> {
+    let matches = |details: &HostDetails| details.host == wanted || host_without_user(&details.host) == wanted;
+
+    if let Some(found) = network.hosts.iter().map(host_details).find(|details| matches(details)) {
+        return Ok(Some(found));
+    }
+
+    l(&format!("host {} is not static, checking the inventory of {}", wanted, network.name));
+    let hosts = inventory_hosts(init_state, network, cli)?;
+    Ok(hosts.iter().map(host_details).find(|details| matches(details)))
+}
+
+/// Secret values are shown as `****`.
+fn format_resolved(resolved: &ResolvedEnv) -> String {
+    let shown = |var: &ResolvedVar| if var.secret { REDACTED.to_string() } else { var.value.clone() };
+    let width = resolved.iter().map(|(key, var)| key.len() + shown(var).len() + 1).max().unwrap_or(0);
+    let mut out = String::new();
+    for (key, var) in resolved.iter() {
+        let pair = format!("{}={}", key, shown(var));
+        let mut origin = var.source.to_string();
+        if !var.overrides.is_empty() {
+            let replaced: Vec<String> = var.overrides.iter().map(|source| source.to_string()).collect();
+            origin = format!("{}, overrides {}", origin, replaced.join(", "));
+        }
+        out.push_str(&format!("{:width$}  # {}\n", pair, origin, width = width));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::const_values::SUP_NETWORK_ENV;
+    use crate::usecase::modes_of_operation::test_support::init_state;
+
+    const SUPFILE: &str = r#"
+version: 0.5
+env:
+  APP: shop
+  LOG: warn
+  REGION: global
+networks:
+  prod:
+    env:
+      REGION: eu
+      LOG: info
+    hosts:
+    - host: root@10.0.0.1
+      env:
+        LOG: debug
+        SHARD: "1"
+commands:
+  deploy:
+    run: echo deploy
+    env:
+      SHARD: "9"
+      STEP: deploy
+"#;
+
+    #[test]
+    fn test_layers_in_documented_order() {
+        let state = init_state(SUPFILE, &[]);
+        let network = state.supfile.networks.get("prod").unwrap().clone();
+        let host = match &network.hosts[0] {
+            HostEntry::Detailed(details) => details.clone(),
+            HostEntry::Simple(_) => unreachable!(),
+        };
+        let command = state.supfile.commands.get("deploy").unwrap().clone();
+        let cli: EnvList = serde_yaml::from_str("STEP: cli").unwrap();
+
+        let resolved = resolve_env(&state, &network, Some(&host), Some(&command), &cli);
+        let value = |key: &str| resolved.get(key).unwrap().value.clone();
+        let source = |key: &str| resolved.get(key).unwrap().source;
+
+        assert_eq!((value("APP"), source("APP")), ("shop".to_string(), EnvSource::Supfile));
+        assert_eq!((value("REGION"), source("REGION")), ("eu".to_string(), EnvSource::Network));
+        assert_eq!((value("LOG"), source("LOG")), ("debug".to_string(), EnvSource::Host));
+        assert_eq!((value("SHARD"), source("SHARD")), ("9".to_string(), EnvSource::Command));
+        assert_eq!((value("STEP"), source("STEP")), ("cli".to_string(), EnvSource::Cli));
+        assert_eq!((value(SUP_HOST_ENV), source(SUP_HOST_ENV)), ("10.0.0.1".to_string(), EnvSource::Ssup));
+        assert_eq!(value(SUP_NETWORK_ENV), "prod");
+        assert_eq!(resolved.get("LOG").unwrap().overrides, vec![EnvSource::Supfile, EnvSource::Network]);
+    }
+
+    #[test]
+    fn test_order_follows_first_definition() {
+        let state = init_state(SUPFILE, &[]);
+        let network = state.supfile.networks.get("prod").unwrap().clone();
+        let resolved = resolve_env(&state, &network, None, None, &EnvList::new());
+        let keys: Vec<&String> = resolved.iter().map(|(key, _)| key).skip_while(|key| key.starts_with("SUP_")).collect();
+        assert_eq!(keys, vec!["APP", "LOG", "REGION"]);
+        assert!(resolved.get(SUP_HOST_ENV).is_none());
+    }
+
+    #[test]
+    fn test_references_see_all_layers() {
+        let state = init_state(SUPFILE, &[]);
+        let network = state.supfile.networks.get("prod").unwrap().clone();
+        let cli: EnvList = serde_yaml::from_str("TAG: ${APP}-${REGION}-$(echo ${SUP_NETWORK})").unwrap();
+        let resolved = evaluate_resolved(resolve_env(&state, &network, None, None, &cli)).unwrap();
+        let tag = resolved.get("TAG").unwrap();
+        assert_eq!(tag.value, "shop-eu-prod");
+        assert_eq!(tag.source, EnvSource::Cli);
+    }
+
+    #[test]
+    fn test_playbook_env_is_resolved_and_evaluated() {
+        let yaml = "version: 0.5\nenv:\n  FIRST: hello\n  REF: ${FIRST}_${SECOND}\n  SECOND: world\nnetworks:\n  prod:\n    hosts: [root@10.0.0.1]\ncommands:\n  deploy:\n    run: ./deploy\n    env:\n      SECOND: there\n      TOKEN:\n        value: $(echo t0ken)\n        secret: true\n      LATER:\n        raw: ${HOME}\n";
+        let mut state = init_state(yaml, &["-e", "FIRST=hi", "prod", "deploy"]);
+        let mut playbook = crate::usecase::program_init::parse_initial_args(&mut state).unwrap();
+        assert_eq!(playbook.plays[0].envs[0].env["REF"], "${FIRST}_${SECOND}");
+
+        evaluate_playbook_env(&mut playbook).unwrap();
+        let env = &playbook.plays[0].envs[0].env;
+        assert_eq!(env["REF"], "hi_there");
+        assert_eq!(env["TOKEN"], "t0ken");
+        assert!(env.is_secret("TOKEN"));
+        assert_eq!(env["LATER"], "${HOME}");
+        assert!(env.is_raw("LATER"));
+    }
+
+    #[test]
+    fn test_format_shows_sources() {
+        let mut resolved = ResolvedEnv::new();
+        resolved.layer(EnvSource::Network, &serde_yaml::from_str("A: one\nLONGER: x").unwrap());
+        resolved.layer(EnvSource::Cli, &serde_yaml::from_str("A: two").unwrap());
+        resolved.layer(EnvSource::Host, &serde_yaml::from_str("PW:\n  value: hunter2\n  secret: true").unwrap());
+        assert_eq!(
+            format_resolved(&resolved),
+            "A=two     # cli, overrides network\nLONGER=x  # network\nPW=****   # host\n"
+        );
+    }
+}
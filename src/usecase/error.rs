@@ -0,0 +1,223 @@
+use crate::gateways::secrets_file::SecretsFileError;
+use crate::entity::supfile::targets::TargetCycle;
+use crate::gateways::shellcheck::ShellcheckError;
+use crate::usecase::env_interpolation::InterpolationError;
+use crate::usecase::env_parser::EnvParseError;
+use crate::usecase::inventory_file::InventoryFileError;
+use crate::usecase::parse_network::InventoryError;
+use crate::usecase::preflight::EnvViolation;
+use crate::usecase::secret_resolver::SecretError;
+use std::io;
+use thiserror::Error;
+
+/// Exit status for anything that went wrong while preparing or running.
+pub const EXIT_FAILURE: i32 = 1;
+/// Exit status for a command line ssup cannot make sense of.
+pub const EXIT_USAGE: i32 = 2;
+
+/// Everything a usecase can fail with. Usecases return it instead of
+/// exiting, `main` prints it and picks the exit status with [`SsupError::exit_code`].
+#[derive(Error, Debug)]
+pub enum SsupError {
+    /// The usage text is the whole message, it is printed as it is.
+    #[error("{message}")]
+    Usage { message: String },
+    #[error("could not open Supfile {path}: {source}")]
+    ReadSupfile {
+        path: String,
+        #[source]
+        source: io::Error,
+    },
+    /// `diagnostic` is the message with the location and source line.
+    #[error("invalid Supfile {diagnostic}")]
+    ParseSupfile {
+        path: String,
+        diagnostic: String,
+        #[source]
+        source: serde_yaml::Error,
+    },
+    #[error("invalid Supfile references:{}", problems.iter().map(|p| format!("\n{}", p)).collect::<String>())]
+    UnknownReferences {
+        path: String,
+        problems: Vec<String>,
+        trace_id: &'static str,
+    },
+    #[error("could not change to the Supfile directory {path}: {source}")]
+    WorkingDir {
+        path: String,
+        #[source]
+        source: io::Error,
+    },
+    #[error("network '{name}' does not exist")]
+    NetworkNotFound {
+        name: String,
+        trace_id: &'static str,
+    },
+    #[error("target '{name}' does not exist")]
+    TargetNotFound {
+        name: String,
+        trace_id: &'static str,
+    },
+    #[error(transparent)]
+    TargetCycle(#[from] TargetCycle),
+    #[error("command not found: {name}, while traversing target '{target}'")]
+    CommandNotFound {
+        name: String,
+        target: String,
+        trace_id: &'static str,
+    },
+    #[error("invalid parameters for command '{command}':{}", problems.iter().map(|p| format!("\n  {}", p)).collect::<String>())]
+    InvalidParams {
+        command: String,
+        problems: Vec<String>,
+        trace_id: &'static str,
+    },
+    #[error("host '{host}' not found in network '{network}'")]
+    HostNotFound {
+        host: String,
+        network: String,
+        trace_id: &'static str,
+    },
+    #[error("invalid --tags '{tags}': {message}")]
+    InvalidTags {
+        tags: String,
+        message: String,
+        trace_id: &'static str,
+    },
+    #[error("no hosts in network '{network}' match --tags '{tags}'")]
+    NoMatchingHosts {
+        network: String,
+        tags: String,
+        trace_id: &'static str,
+    },
+    #[error("invalid --env: {0}")]
+    Env(#[from] EnvParseError),
+    #[error(transparent)]
+    Interpolation(#[from] InterpolationError),
+    #[error(transparent)]
+    Secret(#[from] SecretError),
+    #[error(transparent)]
+    SecretsFile(#[from] SecretsFileError),
+    #[error(transparent)]
+    Inventory(#[from] InventoryError),
+    #[error(transparent)]
+    InventoryFile(#[from] InventoryFileError),
+    #[error("required environment is not satisfied:{}", violations.iter().map(|v| format!("\n  {}", v)).collect::<String>())]
+    RequiredEnv {
+        violations: Vec<EnvViolation>,
+        trace_id: &'static str,
+    },
+    #[error("the Supfile has no `secrets:` file configured")]
+    NoSecretsFile { trace_id: &'static str },
+    #[error("no secret named '{key}' in {path}")]
+    SecretNotFound {
+        key: String,
+        path: String,
+        trace_id: &'static str,
+    },
+    /// Reading a passphrase or value from the terminal, or running the editor.
+    #[error("{message}")]
+    Interactive {
+        message: String,
+        trace_id: &'static str,
+    },
+    #[error("no terminal to ask for:{}\nset them with -e KEY=VALUE, or give them a `value:` and pass --yes", questions.iter().map(|q| format!("\n  {}", q)).collect::<String>())]
+    UnansweredPrompts {
+        questions: Vec<String>,
+        trace_id: &'static str,
+    },
+    #[error("no terminal to confirm:{}\npass --yes to confirm", questions.iter().map(|q| format!("\n  {}", q)).collect::<String>())]
+    UnconfirmedRun {
+        questions: Vec<String>,
+        trace_id: &'static str,
+    },
+    #[error("not confirmed: {question}")]
+    Declined {
+        question: String,
+        trace_id: &'static str,
+    },
+    #[error("failed to write output: {0}")]
+    Output(#[source] io::Error),
+    #[error("{path}: {count} problem(s) found")]
+    CheckFailed {
+        path: String,
+        count: usize,
+        trace_id: &'static str,
+    },
+    #[error(transparent)]
+    Shellcheck(#[from] ShellcheckError),
+    #[error("{path}: {count} shellcheck finding(s)")]
+    LintFailed {
+        path: String,
+        count: usize,
+        trace_id: &'static str,
+    },
+    #[error("no error with trace id '{id}' in the catalog")]
+    UnknownTraceId {
+        id: String,
+        trace_id: &'static str,
+    },
+}
+
+impl SsupError {
+    /// The process exit status this error maps to.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            SsupError::Usage { .. } => EXIT_USAGE,
+            _ => EXIT_FAILURE,
+        }
+    }
+
+    /// The stable id of this failure in the error catalog, see `ssup explain`.
+    /// Errors wrapping another one report the innermost id; usage errors have none.
+    pub fn trace_id(&self) -> Option<&'static str> {
+        let trace_id = match self {
+            SsupError::Usage { .. } => return None,
+            SsupError::ReadSupfile { .. } => "C02C8E4A-8EA7-4BAA-ADAF-D741CC976BCB",
+            SsupError::ParseSupfile { .. } => "C16D83BF-E7DE-48ED-ADE8-C5AD7CD118C4",
+            SsupError::WorkingDir { .. } => "02C8CAB3-6225-41BE-A2CD-B99F695FA01D",
+            SsupError::Output(_) => "5FBDE38C-63F3-44AA-BCD1-BD4DBDD27E85",
+            SsupError::TargetCycle(_) => "3B1F6F0E-5C47-4D8A-9E21-7A64C0D2B9F3",
+            SsupError::NetworkNotFound { trace_id, .. }
+            | SsupError::TargetNotFound { trace_id, .. }
+            | SsupError::CommandNotFound { trace_id, .. }
+            | SsupError::InvalidParams { trace_id, .. }
+            | SsupError::HostNotFound { trace_id, .. }
+            | SsupError::InvalidTags { trace_id, .. }
+            | SsupError::NoMatchingHosts { trace_id, .. }
+            | SsupError::UnknownReferences { trace_id, .. }
+            | SsupError::RequiredEnv { trace_id, .. }
+            | SsupError::NoSecretsFile { trace_id }
+            | SsupError::SecretNotFound { trace_id, .. }
+            | SsupError::Interactive { trace_id, .. }
+            | SsupError::UnansweredPrompts { trace_id, .. }
+            | SsupError::UnconfirmedRun { trace_id, .. }
+            | SsupError::Declined { trace_id, .. }
+            | SsupError::CheckFailed { trace_id, .. }
+            | SsupError::LintFailed { trace_id, .. }
+            | SsupError::UnknownTraceId { trace_id, .. } => trace_id,
+            SsupError::Env(e) => e.trace_id(),
+            SsupError::Interpolation(e) => e.trace_id(),
+            SsupError::Secret(e) => e.trace_id(),
+            SsupError::SecretsFile(e) => e.trace_id(),
+            SsupError::Inventory(e) => e.trace_id(),
+            SsupError::InventoryFile(e) => e.trace_id(),
+            SsupError::Shellcheck(e) => e.trace_id(),
+        };
+        Some(trace_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_codes() {
+        let usage = SsupError::Usage { message: "Usage: ssup NETWORK COMMAND".to_string() };
+        assert_eq!(usage.exit_code(), EXIT_USAGE);
+        assert_eq!(usage.to_string(), "Usage: ssup NETWORK COMMAND");
+        let missing = SsupError::NetworkNotFound { name: "prod".to_string(), trace_id: "" };
+        assert_eq!(missing.exit_code(), EXIT_FAILURE);
+    }
+}
@@ -0,0 +1,89 @@
+use crate::entity::error_catalog::{find_entries, short_id, CatalogEntry, CATALOG};
+use crate::usecase::error::SsupError;
+
+/// `ssup explain [TRACE-ID]`: the title, likely causes and remedies of an
+/// error, or the list of every known id when none is given.
+pub fn explain_command(args: &[String]) -> Result<(), SsupError> {
+    match args {
+        [_] => {
+            print!("{}", format_catalog());
+            Ok(())
+        }
+        [_, id] => {
+            let entries = find_entries(id);
+            if entries.is_empty() {
+                return Err(SsupError::UnknownTraceId {
+                    id: id.clone(),
+                    trace_id: "32946188-602C-4519-B5A5-92162D97037B",
+                });
+            }
+            let shown: Vec<String> = entries.iter().map(|entry| format_entry(entry)).collect();
+            print!("{}", shown.join("\n"));
+            Ok(())
+        }
+        _ => Err(SsupError::Usage { message: "Usage: ssup explain [TRACE-ID]".to_string() }),
+    }
+}
+
+/// One line per entry, with the short form of each of its ids.
+fn format_catalog() -> String {
+    let mut out = String::new();
+    for entry in CATALOG {
+        let ids: Vec<&str> = entry.ids.iter().map(|id| short_id(id)).collect();
+        out.push_str(&format!("{}  {}\n", ids.join(" "), entry.title));
+    }
+    out
+}
+
+fn format_entry(entry: &CatalogEntry) -> String {
+    let mut out = format!("{}\n{}\n", entry.title, "=".repeat(entry.title.chars().count()));
+    out.push_str(&format!("trace ids: {}\n\nLikely causes:\n", entry.ids.join(", ")));
+    for cause in entry.causes {
+        out.push_str(&format!("  - {}\n", cause));
+    }
+    out.push_str("\nRemedies:\n");
+    for remedy in entry.remedies {
+        out.push_str(&format!("  - {}\n", remedy));
+    }
+    out
+}
+
+/// The line printed under an error, pointing at `ssup explain`.
+pub fn explain_hint(error: &SsupError) -> Option<String> {
+    let id = short_id(error.trace_id()?);
+    Some(format!("trace id {}, run `ssup explain {}` for likely causes and remedies", id, id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entry_format() {
+        let entry = find_entries("64B2D565")[0];
+        let shown = format_entry(entry);
+        assert!(shown.starts_with("A target refers to a command that does not exist\n====="));
+        assert!(shown.contains("64B2D565-8345-4108-B790-25606C2128C0"));
+        assert!(shown.contains("\nLikely causes:\n  - a typo in a target line under `targets:`\n"));
+        assert!(shown.contains("\nRemedies:\n  - "));
+    }
+
+    #[test]
+    fn test_hint_uses_innermost_id() {
+        let error = SsupError::CommandNotFound {
+            name: "migrate".to_string(),
+            target: "release".to_string(),
+            trace_id: "64B2D565-8345-4108-B790-25606C2128C0",
+        };
+        assert_eq!(
+            explain_hint(&error).unwrap(),
+            "trace id 64B2D565, run `ssup explain 64B2D565` for likely causes and remedies"
+        );
+        let usage = SsupError::Usage { message: "Usage: ssup".to_string() };
+        assert_eq!(explain_hint(&usage), None);
+
+        let nested = SsupError::from(crate::usecase::env_parser::parse_env("A").unwrap_err());
+        assert_eq!(nested.trace_id().map(short_id), Some("5B8D0E3F"));
+        assert!(format_catalog().lines().any(|line| line.starts_with("5B8D0E3F 9C2E71B4")));
+    }
+}
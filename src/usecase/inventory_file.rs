@@ -0,0 +1,519 @@
+use crate::entity::supfile::networks::{HostDetails, HostEntry, Network, Networks};
+use crate::gateways::logger::logger_func as l;
+use crate::usecase::parse_network::check_hosts_form;
+use indexmap::IndexMap;
+use crate::entity::env::EnvList;
+use crate::entity::secret::Secret;
+use std::io;
+use thiserror::Error;
+
+const ANSIBLE_FORMAT: &str = "ansible";
+const SSUP_FORMAT: &str = "ssup";
+
+#[derive(Error, Debug)]
+pub enum InventoryFileError {
+    #[error("failed to read inventory file {path}: {source}")]
+    Read {
+        path: String,
+        #[source]
+        source: io::Error,
+        trace_id: &'static str,
+    },
+    #[error("{path}:{line}: {message}")]
+    Parse {
+        path: String,
+        line: usize,
+        message: String,
+        trace_id: &'static str,
+    },
+    #[error("unknown inventory_format '{format}' for network '{network}', expected 'ssup' or 'ansible'")]
+    UnknownFormat {
+        network: String,
+        format: String,
+        trace_id: &'static str,
+    },
+}
+
+impl InventoryFileError {
+    pub fn trace_id(&self) -> &'static str {
+        match self {
+            InventoryFileError::Read { trace_id, .. }
+            | InventoryFileError::Parse { trace_id, .. }
+            | InventoryFileError::UnknownFormat { trace_id, .. } => trace_id,
+        }
+    }
+}
+
+/// Loads every network's `inventory_file` and appends the hosts it lists.
+///
+/// For Ansible inventories each group additionally becomes a network of its
+/// own, inheriting the settings of the network that referenced the file.
+/// Networks already defined in the Supfile are never replaced by a group.
+pub fn load_inventory_files(networks: &mut Networks) -> Result<(), InventoryFileError> {
+    for name in networks.names.clone() {
+        let network = match networks.get(&name) {
+            Some(network) if network.inventory_file.is_some() => network.clone(),
+            _ => continue,
+        };
+        let path = network.inventory_file.clone().unwrap_or_default();
+        let content = std::fs::read_to_string(&path).map_err(|source| InventoryFileError::Read {
+            path: path.clone(),
+            source,
+            trace_id: "0D4B9E27-3C61-4F8A-B5D2-7E1A9C3F6B80",
+        })?;
+
+        let format = match network.inventory_format.as_deref() {
+            Some(format) => format.to_string(),
+            None => guess_format(&path, &content).to_string(),
+        };
+        l(&format!("loading {} inventory file {} for network {}", format, path, name));
+
+        match format.as_str() {
+            SSUP_FORMAT => {
+                let hosts = parse_ssup_inventory(&content);
+                if let Some(target) = networks.get_mut(&name) {
+                    target.hosts.extend(hosts);
+                }
+            }
+            ANSIBLE_FORMAT => {
+                let groups = parse_ansible_inventory(&content).map_err(|(line, message)| InventoryFileError::Parse {
+                    path: path.clone(),
+                    line,
+                    message,
+                    trace_id: "5A8C2F13-9D47-4B6E-A1F0-3C7E8B2D4A95",
+                })?;
+                add_ansible_groups(networks, &network, groups);
+            }
+            other => {
+                return Err(InventoryFileError::UnknownFormat {
+                    network: name.clone(),
+                    format: other.to_string(),
+                    trace_id: "E3B7A041-6F2D-4C98-8E15-B9D0C4A7F362",
+                })
+            }
+        }
+    }
+    Ok(())
+}
+
+fn guess_format(path: &str, content: &str) -> &'static str {
+    let has_sections = content.lines().any(|line| line.trim_start().starts_with('['));
+    if path.ends_with(".ini") || has_sections {
+        ANSIBLE_FORMAT
+    } else {
+        SSUP_FORMAT
+    }
+}
+
+fn add_ansible_groups(networks: &mut Networks, parent: &Network, groups: IndexMap<String, Vec<HostDetails>>) {
+    let mut all_hosts: Vec<HostDetails> = Vec::new();
+    for (group, hosts) in groups {
+        for host in &hosts {
+            if !all_hosts.iter().any(|known| known.host == host.host) {
+                all_hosts.push(host.clone());
+            }
+        }
+        if group == "all" || group == "ungrouped" || group == parent.name {
+            continue;
+        }
+        if networks.get(&group).is_some() {
+            l(&format!("ansible group {} shadowed by a Supfile network, skipping", group));
+            continue;
+        }
+        let mut group_network = parent.clone();
+        group_network.name = group.clone();
+        group_network.inventory = None;
+        group_network.inventory_file = None;
+        group_network.hosts = hosts.into_iter().map(|details| HostEntry::Detailed(Box::new(details))).collect();
+        networks.add_network(group, group_network);
+    }
+
+    if let Some(target) = networks.get_mut(&parent.name) {
+        target.hosts.extend(all_hosts.into_iter().map(|details| HostEntry::Detailed(Box::new(details))));
+    }
+}
+
+/// ssup's own format: one host string per line, `#` starts a comment.
+pub fn parse_ssup_inventory(content: &str) -> Vec<HostEntry> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(check_hosts_form)
+        .collect()
+}
+
+#[derive(Default)]
+struct AnsibleGroup {
+    hosts: Vec<String>,
+    /// child group names with the line listing them
+    children: Vec<(String, usize)>,
+    vars: Vec<(String, String)>,
+}
+
+/// Parses an Ansible INI inventory into hosts per group, in file order.
+///
+/// Every group lists the hosts of its `:children` too, and `all` lists every
+/// host. Host ranges like `web[01:50].example.com` are expanded. A host's env
+/// is built from the vars of each group that contains it, parents before
+/// children so the most specific group wins, followed by its own inline vars. The connection
+/// vars `ansible_host`, `ansible_port`, `ansible_user`, `ansible_password`
+/// (or `ansible_ssh_pass`) and `ansible_become` map onto `HostDetails`, and a
+/// comma separated `tags` var becomes the host's tags.
+pub fn parse_ansible_inventory(content: &str) -> Result<IndexMap<String, Vec<HostDetails>>, (usize, String)> {
+    let mut groups: IndexMap<String, AnsibleGroup> = IndexMap::new();
+    let mut host_vars: IndexMap<String, Vec<(String, String)>> = IndexMap::new();
+    groups.insert("all".to_string(), AnsibleGroup::default());
+    groups.insert("ungrouped".to_string(), AnsibleGroup::default());
+
+    #[derive(PartialEq)]
+    enum Section { Hosts, Vars, Children }
+    let mut current = ("ungrouped".to_string(), Section::Hosts);
+
+    for (index, raw) in content.lines().enumerate() {
+        let line_no = index + 1;
+        let line = raw.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+
+        if line.starts_with('[') {
+            let header = line
+                .strip_prefix('[')
+                .and_then(|h| h.strip_suffix(']'))
+                .ok_or((line_no, format!("malformed section header: {}", line)))?;
+            let (name, section) = match header.split_once(':') {
+                None => (header, Section::Hosts),
+                Some((name, "vars")) => (name, Section::Vars),
+                Some((name, "children")) => (name, Section::Children),
+                Some((_, kind)) => return Err((line_no, format!("unknown section type ':{}'", kind))),
+            };
+            if name.is_empty() {
+                return Err((line_no, "empty group name".to_string()));
+            }
+            groups.entry(name.to_string()).or_default();
+            current = (name.to_string(), section);
+            continue;
+        }
+
+        let tokens = split_ansible_line(line).map_err(|message| (line_no, message))?;
+        let group = groups.get_mut(&current.0).expect("current group is always registered");
+        match current.1 {
+            Section::Hosts => {
+                let mut tokens = tokens.into_iter();
+                let pattern = tokens.next().unwrap_or_default();
+                let mut vars = Vec::new();
+                for token in tokens {
+                    vars.push(split_var(&token).ok_or((line_no, format!("expected key=value, got '{}'", token)))?);
+                }
+                for host in expand_host_range(&pattern).map_err(|message| (line_no, message))? {
+                    if !group.hosts.contains(&host) {
+                        group.hosts.push(host.clone());
+                    }
+                    host_vars.entry(host).or_default().extend(vars.iter().cloned());
+                }
+            }
+            Section::Vars => {
+                let joined = tokens.join(" ");
+                let var = split_var(&joined).ok_or((line_no, format!("expected key=value, got '{}'", line)))?;
+                group.vars.push(var);
+            }
+            Section::Children => {
+                group.children.extend(tokens.into_iter().map(|child| (child, line_no)));
+            }
+        }
+    }
+
+    for (name, group) in groups.iter() {
+        for (child, line_no) in &group.children {
+            if !groups.contains_key(child) {
+                return Err((*line_no, format!("group '{}' lists unknown child group '{}'", name, child)));
+            }
+        }
+    }
+
+    let mut result = IndexMap::new();
+    for name in groups.keys() {
+        let members = if name == "all" {
+            host_vars.keys().cloned().collect()
+        } else {
+            group_members(&groups, name, &mut Vec::new())
+        };
+        if members.is_empty() {
+            continue;
+        }
+        let hosts = members
+            .iter()
+            .map(|host| build_host(host, &groups, &host_vars))
+            .collect();
+        result.insert(name.clone(), hosts);
+    }
+    Ok(result)
+}
+
+fn group_members(groups: &IndexMap<String, AnsibleGroup>, name: &str, visiting: &mut Vec<String>) -> Vec<String> {
+    if visiting.iter().any(|v| v == name) {
+        return Vec::new();
+    }
+    visiting.push(name.to_string());
+    let mut members = Vec::new();
+    if let Some(group) = groups.get(name) {
+        members.extend(group.hosts.iter().cloned());
+        for (child, _) in &group.children {
+            for host in group_members(groups, child, visiting) {
+                if !members.contains(&host) {
+                    members.push(host);
+                }
+            }
+        }
+    }
+    visiting.pop();
+    members
+}
+
+/// How deep `name` sits below `all`: 0 for `all`, 1 for groups no other
+/// group lists as a child, one more than the deepest parent otherwise.
+fn group_depth(groups: &IndexMap<String, AnsibleGroup>, name: &str, visiting: &mut Vec<String>) -> usize {
+    if name == "all" {
+        return 0;
+    }
+    if visiting.iter().any(|v| v == name) {
+        return 1;
+    }
+    visiting.push(name.to_string());
+    let depth = groups
+        .iter()
+        .filter(|(_, group)| group.children.iter().any(|(child, _)| child == name))
+        .map(|(parent, _)| group_depth(groups, parent, visiting) + 1)
+        .max()
+        .unwrap_or(1);
+    visiting.pop();
+    depth
+}
+
+/// Expands Ansible host ranges: `web[01:03]` is `web01`, `web02`, `web03`,
+/// `db-[a:c]` is `db-a`, `db-b`, `db-c`, and `[1:9:4]` steps by 4. A pattern
+/// may hold several ranges. Numeric bounds with a leading zero are padded.
+fn expand_host_range(pattern: &str) -> Result<Vec<String>, String> {
+    let Some(open) = pattern.find('[') else {
+        return Ok(vec![pattern.to_string()]);
+    };
+    let close = pattern[open..]
+        .find(']')
+        .map(|close| open + close)
+        .ok_or_else(|| format!("unterminated host range in '{}'", pattern))?;
+    let (prefix, range, rest) = (&pattern[..open], &pattern[open + 1..close], &pattern[close + 1..]);
+
+    let bad_range = || format!("invalid host range '[{}]' in '{}'", range, pattern);
+    let parts: Vec<&str> = range.split(':').collect();
+    let (start, end, step) = match parts.as_slice() {
+        [start, end] => (*start, *end, 1),
+        [start, end, step] => (*start, *end, step.parse::<usize>().ok().filter(|step| *step > 0).ok_or_else(bad_range)?),
+        _ => return Err(bad_range()),
+    };
+
+    let items: Vec<String> = match (start.parse::<usize>(), end.parse::<usize>()) {
+        (Ok(first), Ok(last)) if first <= last => {
+            let width = if start.len() > 1 && start.starts_with('0') { start.len() } else { 0 };
+            (first..=last).step_by(step).map(|n| format!("{:0width$}", n, width = width)).collect()
+        }
+        _ => {
+            let letter = |bound: &str| match bound.as_bytes() {
+                [c] if c.is_ascii_alphabetic() => Some(*c),
+                _ => None,
+            };
+            match (letter(start), letter(end)) {
+                (Some(first), Some(last)) if first <= last => {
+                    (first..=last).step_by(step).map(|c| (c as char).to_string()).collect()
+                }
+                _ => return Err(bad_range()),
+            }
+        }
+    };
+
+    let tails = expand_host_range(rest)?;
+    Ok(items
+        .iter()
+        .flat_map(|item| tails.iter().map(move |tail| format!("{}{}{}", prefix, item, tail)))
+        .collect())
+}
+
+fn build_host(
+    host: &str,
+    groups: &IndexMap<String, AnsibleGroup>,
+    host_vars: &IndexMap<String, Vec<(String, String)>>,
+) -> HostDetails {
+    let mut containing: Vec<(usize, &AnsibleGroup)> = groups
+        .iter()
+        .filter(|(name, _)| *name == "all" || group_members(groups, name, &mut Vec::new()).iter().any(|h| h == host))
+        .map(|(name, group)| (group_depth(groups, name, &mut Vec::new()), group))
+        .collect();
+    // stable, so groups of the same depth keep file order
+    containing.sort_by_key(|(depth, _)| *depth);
+
+    let mut vars = EnvList::new();
+    for (_, group) in containing {
+        vars.extend(&group.vars.iter().cloned().collect());
+    }
+    if let Some(own) = host_vars.get(host) {
+        vars.extend(&own.iter().cloned().collect());
+    }
+
+    let mut details = HostDetails::new(vars.remove("ansible_host").unwrap_or_else(|| host.to_string()));
+    if let Some(port) = vars.remove("ansible_port") {
+        details.host = format!("{}:{}", details.host, port);
+    }
+    details.user = vars.remove("ansible_user");
+    details.pass = vars.remove("ansible_password").or_else(|| vars.remove("ansible_ssh_pass")).map(Secret::new);
+    vars.remove("ansible_ssh_pass");
+    if let Some(tags) = vars.remove("tags") {
+        details.tags = tags.split(',').map(str::trim).filter(|t| !t.is_empty()).map(String::from).collect();
+    }
+    if let Some(become_flag) = vars.remove("ansible_become") {
+        details.sudo = matches!(become_flag.to_lowercase().as_str(), "true" | "yes" | "1");
+    }
+    if !vars.is_empty() {
+        details.env = Some(vars);
+    }
+    details
+}
+
+fn split_var(token: &str) -> Option<(String, String)> {
+    let (key, value) = token.split_once('=')?;
+    let key = key.trim();
+    if key.is_empty() {
+        return None;
+    }
+    Some((key.to_string(), value.trim().to_string()))
+}
+
+/// Splits on whitespace while keeping single or double quoted runs intact,
+/// and drops a trailing `# comment`.
+fn split_ansible_line(line: &str) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut quote: Option<char> = None;
+    for c in line.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => current.push(c),
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == '#' && current.is_empty() => break,
+            None if c.is_whitespace() => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+            }
+            None => current.push(c),
+        }
+    }
+    if let Some(q) = quote {
+        return Err(format!("unterminated {} quote", q));
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INVENTORY: &str = r#"
+bastion.example.com
+
+[web]
+web1 ansible_host=10.0.0.1 ansible_user=deploy role="front end" tags=canary,az=b
+web2 ansible_host=10.0.0.2 ansible_port=2222
+
+[db]
+db1 ansible_host=10.0.1.1 ansible_become=yes
+
+[web:vars]
+role=web
+az=b
+
+[prod:children]
+web
+db
+
+[all:vars]
+dc=eu1
+"#;
+
+    fn host<'a>(groups: &'a IndexMap<String, Vec<HostDetails>>, group: &str, index: usize) -> &'a HostDetails {
+        &groups.get(group).unwrap()[index]
+    }
+
+    #[test]
+    fn test_groups_become_host_lists() {
+        let groups = parse_ansible_inventory(INVENTORY).unwrap();
+        let names: Vec<&String> = groups.keys().collect();
+        assert_eq!(names, vec!["all", "ungrouped", "web", "db", "prod"]);
+        assert_eq!(groups["all"].len(), 4);
+        assert_eq!(groups["prod"].iter().map(|h| h.host.as_str()).collect::<Vec<_>>(), vec!["10.0.0.1", "10.0.0.2:2222", "10.0.1.1"]);
+    }
+
+    #[test]
+    fn test_connection_vars_map_to_host_details() {
+        let groups = parse_ansible_inventory(INVENTORY).unwrap();
+        let web1 = host(&groups, "web", 0);
+        assert_eq!(web1.user.as_deref(), Some("deploy"));
+        assert_eq!(web1.tags, vec!["canary", "az=b"]);
+        assert!(!web1.sudo);
+        assert!(host(&groups, "db", 0).sudo);
+    }
+
+    #[test]
+    fn test_host_vars_override_group_vars() {
+        let groups = parse_ansible_inventory(INVENTORY).unwrap();
+        let web1_env = host(&groups, "web", 0).env.clone().unwrap();
+        assert_eq!(web1_env.get("role"), Some("front end"));
+        assert_eq!(web1_env.get("az"), Some("b"));
+        assert_eq!(web1_env.get("dc"), Some("eu1"));
+        assert!(!web1_env.contains_key("ansible_host"));
+
+        let web2_env = host(&groups, "web", 1).env.clone().unwrap();
+        assert_eq!(web2_env.get("role"), Some("web"));
+    }
+
+    #[test]
+    fn test_host_ranges_are_expanded() {
+        let groups = parse_ansible_inventory("[web]\nweb[01:03].example.com role=web\n[db]\ndb-[a:c]\nrack[1:2]-n[0:4:2]\n").unwrap();
+        let hosts = |group: &str| groups[group].iter().map(|h| h.host.clone()).collect::<Vec<_>>();
+        assert_eq!(hosts("web"), vec!["web01.example.com", "web02.example.com", "web03.example.com"]);
+        assert_eq!(groups["web"][2].env.as_ref().unwrap().get("role"), Some("web"));
+        assert_eq!(hosts("db"), vec!["db-a", "db-b", "db-c", "rack1-n0", "rack1-n2", "rack1-n4", "rack2-n0", "rack2-n2", "rack2-n4"]);
+
+        assert_eq!(parse_ansible_inventory("[web]\nweb[3:1]").unwrap_err(), (2, "invalid host range '[3:1]' in 'web[3:1]'".to_string()));
+        assert_eq!(parse_ansible_inventory("[web]\nweb[1:3").unwrap_err().0, 2);
+    }
+
+    #[test]
+    fn test_child_group_vars_override_parent_vars() {
+        // the child's vars come first in the file, they still win
+        let inventory = "[web]\nweb1\n[web:vars]\nrole=web\n[prod:children]\nweb\n[prod:vars]\nrole=generic\nstage=prod\n[all:vars]\nrole=any\n";
+        let groups = parse_ansible_inventory(inventory).unwrap();
+        let env = groups["web"][0].env.clone().unwrap();
+        assert_eq!(env.get("role"), Some("web"));
+        assert_eq!(env.get("stage"), Some("prod"));
+    }
+
+    #[test]
+    fn test_parse_errors_report_line() {
+        assert_eq!(parse_ansible_inventory("[web\nhost").unwrap_err().0, 1);
+        assert_eq!(parse_ansible_inventory("[web]\nhost1 novalue").unwrap_err().0, 2);
+        assert_eq!(parse_ansible_inventory("[web]\nweb1\n[web:children]\nmissing").unwrap_err().0, 4);
+        assert!(parse_ansible_inventory("[web]\nhost1 a=\"open").is_err());
+    }
+
+    #[test]
+    fn test_ssup_format_skips_comments() {
+        let hosts = parse_ssup_inventory("# fleet\nroot@10.0.0.1\n\nroot@10.0.0.2 | secret\n");
+        assert_eq!(hosts.len(), 2);
+        assert_eq!(guess_format("hosts.txt", "root@10.0.0.1\n"), SSUP_FORMAT);
+        assert_eq!(guess_format("hosts", "[web]\nweb1\n"), ANSIBLE_FORMAT);
+        assert_eq!(guess_format("hosts.ini", "web1\n"), ANSIBLE_FORMAT);
+    }
+}
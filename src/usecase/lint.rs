@@ -0,0 +1,200 @@
+use crate::entity::const_values::SOURCE_DIRECTIVE;
+use crate::entity::env::EnvList;
+use crate::entity::supfile::commands::Command;
+use crate::entity::CommandLineArgs;
+use crate::gateways::logger::logger_func as l;
+use crate::gateways::shellcheck::{run_shellcheck, ShellcheckComment};
+use crate::usecase::diagnostics::{find_key, render};
+use crate::usecase::error::SsupError;
+use crate::usecase::program_init::{read_supfile, supfile_from_str};
+use std::fs;
+
+/// Where a line of the generated script comes from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Origin {
+    /// the shebang and env exports ssup adds
+    Preamble,
+    /// 0-based line of the `run:`/`local:` value
+    Script(usize),
+    /// 1-based line of a file pulled in with `#source://`
+    Sourced { path: String, line: usize },
+}
+
+/// A command's script as it runs: env exports, then the script with every
+/// `#source://` line replaced by the file it names.
+#[derive(Debug)]
+pub struct EffectiveScript {
+    pub text: String,
+    origins: Vec<Origin>,
+}
+
+/// Builds the script `command` runs for `script`, its `run:` or `local:`.
+/// `#source://` files that can't be read are left in as the comment they are.
+pub fn effective_script(supfile_env: &EnvList, command: &Command, script: &str) -> EffectiveScript {
+    let mut lines = vec![("#!/bin/sh".to_string(), Origin::Preamble)];
+    let mut env = supfile_env.clone();
+    if let Some(command_env) = &command.env {
+        env.extend(command_env);
+    }
+    if !env.is_empty() {
+        lines.push((env.as_export_string(), Origin::Preamble));
+    }
+
+    for (index, line) in script.lines().enumerate() {
+        if let Some(path) = line.trim().strip_prefix(SOURCE_DIRECTIVE).map(str::trim) {
+            if let Ok(source) = fs::read_to_string(path) {
+                for (number, sourced) in source.lines().enumerate() {
+                    lines.push((sourced.to_string(), Origin::Sourced { path: path.to_string(), line: number + 1 }));
+                }
+                continue;
+            }
+            l(&format!("lint: cannot read {}, left as a comment", path));
+        }
+        lines.push((line.to_string(), Origin::Script(index)));
+    }
+
+    let text = lines.iter().map(|(line, _)| format!("{}\n", line)).collect();
+    EffectiveScript { text, origins: lines.into_iter().map(|(_, origin)| origin).collect() }
+}
+
+/// Renders shellcheck `comments` on the effective script of `commands.NAME.FIELD`
+/// at their Supfile line, or at the line of the `#source://` file they are in.
+fn map_comments(
+    path: &str,
+    content: &str,
+    (name, field): (&str, &str),
+    script: &EffectiveScript,
+    comments: Vec<ShellcheckComment>,
+) -> Vec<String> {
+    comments
+        .into_iter()
+        .map(|comment| {
+            let message = format!("SC{} ({}): {}", comment.code, comment.level, comment.message);
+            match script.origins.get(comment.line.saturating_sub(1)) {
+                Some(Origin::Script(line)) => {
+                    let location = script_location(content, &["commands", name, field], *line, comment.column);
+                    render(path, content, location, &format!("commands.{}.{}: {}", name, field, message))
+                }
+                Some(Origin::Sourced { path: source, line }) => {
+                    format!("{}:{}:{}: commands.{}.{} via {}: {}", source, line, comment.column, name, field, SOURCE_DIRECTIVE, message)
+                }
+                Some(Origin::Preamble) | None => {
+                    let location = find_key(content, &["commands", name]);
+                    render(path, content, location, &format!("commands.{}.env: {}", name, message))
+                }
+            }
+        })
+        .collect()
+}
+
+/// Supfile line and column of `column` on the 0-based `line` of the value
+/// under `key`. Literal blocks (`|`) map line by line, anything else to the key.
+fn script_location(content: &str, key: &[&str], line: usize, column: usize) -> Option<(usize, usize)> {
+    let (key_line, key_column) = find_key(content, key)?;
+    let text = content.lines().nth(key_line - 1)?;
+    let value_start = text.find(':')? + 1;
+    let value = text[value_start..].trim_start();
+    let value_column = text.len() - value.len() + 1;
+
+    if value.starts_with('|') {
+        let indent = content
+            .lines()
+            .skip(key_line)
+            .find(|block_line| !block_line.trim().is_empty())
+            .map(|block_line| block_line.len() - block_line.trim_start().len())
+            .filter(|indent| *indent >= key_column)?;
+        return Some((key_line + 1 + line, indent + column));
+    }
+    if line == 0 && !value.starts_with(['>', '"', '\'']) {
+        return Some((key_line, value_column + column - 1));
+    }
+    Some((key_line, value_column))
+}
+
+/// `ssup lint [COMMAND...]`: runs shellcheck on the effective script of every
+/// command, or of the ones given, and reports findings at their Supfile lines.
+pub fn lint_command(flags: &CommandLineArgs) -> Result<(), SsupError> {
+    let (path, content) = read_supfile(flags)?;
+    let supfile = supfile_from_str(&path, &content)?;
+
+    let mut names: Vec<String> = flags.args[1..].to_vec();
+    if names.is_empty() {
+        names = supfile.commands.keys().cloned().collect();
+    }
+
+    let mut findings = Vec::new();
+    let mut scripts = 0;
+    for name in &names {
+        let command = supfile.commands.get(name).ok_or_else(|| SsupError::Usage {
+            message: format!("unknown command '{}'\nUsage: ssup [-f Supfile] lint [COMMAND...]", name),
+        })?;
+        for (field, script) in [("run", &command.run), ("local", &command.local)] {
+            let Some(script) = script else {
+                continue;
+            };
+            l(&format!("lint: shellcheck commands.{}.{}", name, field));
+            let effective = effective_script(&supfile.env, command, script);
+            let comments = run_shellcheck(&effective.text, &command.shellcheck_ignore)?;
+            findings.extend(map_comments(&path, &content, (name, field), &effective, comments));
+            scripts += 1;
+        }
+    }
+
+    if findings.is_empty() {
+        println!("{}: no findings in {} script(s)", path, scripts);
+        return Ok(());
+    }
+    for finding in &findings {
+        println!("{}\n", finding);
+    }
+    Err(SsupError::LintFailed {
+        path,
+        count: findings.len(),
+        trace_id: "9C082286-CD52-41DE-AFE5-3173B9FE3722",
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::supfile::Supfile;
+    use std::env;
+    use std::process;
+
+    fn comment(line: usize, column: usize, code: u32) -> ShellcheckComment {
+        ShellcheckComment { line, column, level: "info".to_string(), code, message: "Double quote it.".to_string() }
+    }
+
+    #[test]
+    fn test_effective_script_expands_sources() {
+        let sourced = env::temp_dir().join(format!("ssup-lint-{}.sh", process::id()));
+        fs::write(&sourced, "helper() {\n  echo $1\n}\n").unwrap();
+        let yaml = format!(
+            "version: 0.5\nenv:\n  APP: shop\ncommands:\n  deploy:\n    env:\n      TAG: v1\n    run: |\n      #source://{}\n      helper $TAG\n      #source://./missing.sh\n",
+            sourced.display()
+        );
+        let supfile: Supfile = serde_yaml::from_str(&yaml).unwrap();
+        let command = &supfile.commands["deploy"];
+        let script = effective_script(&supfile.env, command, command.run.as_deref().unwrap());
+
+        assert_eq!(
+            script.text,
+            "#!/bin/sh\nexport APP='shop'; export TAG='v1';\nhelper() {\n  echo $1\n}\nhelper $TAG\n#source://./missing.sh\n"
+        );
+        let findings = map_comments("Supfile.yml", &yaml, ("deploy", "run"), &script, vec![comment(4, 8, 2086), comment(6, 8, 2086)]);
+        assert_eq!(findings[0], format!("{}:2:8: commands.deploy.run via #source://: SC2086 (info): Double quote it.", sourced.display()));
+        assert_eq!(
+            findings[1],
+            "Supfile.yml:10:14: commands.deploy.run: SC2086 (info): Double quote it.\n   |\n10 |       helper $TAG\n   |              ^"
+        );
+        fs::remove_file(&sourced).unwrap();
+    }
+
+    #[test]
+    fn test_inline_scripts_map_to_the_key_line() {
+        let yaml = "version: 0.5\ncommands:\n  deploy:\n    run: rm -rf $DIR/\n    local: \"echo $A\"\n";
+        assert_eq!(script_location(yaml, &["commands", "deploy", "run"], 0, 8), Some((4, 17)));
+        assert_eq!(script_location(yaml, &["commands", "deploy", "local"], 0, 6), Some((5, 12)));
+        assert_eq!(script_location(yaml, &["commands", "build", "run"], 0, 1), None);
+    }
+}
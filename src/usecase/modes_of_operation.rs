@@ -1,3 +1,22 @@
 pub mod normal_mode;
 pub mod special_target_mode;
-pub mod makefile_mode;
\ No newline at end of file
+pub mod makefile_mode;
+
+#[cfg(test)]
+pub(crate) mod test_support {
+    use crate::entity::{CommandLineArgs, InitState};
+    use clap::Parser;
+
+    /// Builds the state `main` would, from Supfile YAML and CLI arguments.
+    pub fn init_state(yaml: &str, cli: &[&str]) -> InitState {
+        let mut argv = vec!["ssup"];
+        argv.extend_from_slice(cli);
+        let flags = CommandLineArgs::parse_from(argv);
+        InitState {
+            args: flags.args.clone(),
+            supfile: serde_yaml::from_str(yaml).expect("test Supfile must parse"),
+            flags,
+            make_mode: false,
+        }
+    }
+}
@@ -1,13 +1,15 @@
 use crate::entity::help_displayer::HelpDisplayer;
 use crate::entity::playbook::{Play, PlayBook};
-use crate::usecase::ensure_localhost;
+use crate::usecase::{add_ssup_command_envs, apply_target_step, ensure_localhost, expand_hosts, select_hosts_by_tags};
 use crate::gateways::logger::logger_func as l;
 use crate::entity::InitState;
+use crate::usecase::error::SsupError;
+use crate::usecase::params::{apply_params, split_params, ParamArgs};
 
 
 #[allow(unused_imports)]
 #[allow(dead_code)]
-pub fn makefile_mode(init_data: &mut InitState, help_menu: &mut HelpDisplayer) -> PlayBook {
+pub fn makefile_mode(init_data: &mut InitState, help_menu: &mut HelpDisplayer) -> Result<PlayBook, SsupError> {
     let mut result = PlayBook::new();
     let mut play = Play::new();
     ensure_localhost(init_data);
@@ -16,11 +18,18 @@ pub fn makefile_mode(init_data: &mut InitState, help_menu: &mut HelpDisplayer) -
     let networks = init_data.supfile.networks.clone();
     let targets = init_data.supfile.targets.clone();
     let commands = init_data.supfile.commands.clone();
-    let localhost_network = networks.get("localhost").expect("C5A59F87-CB34-4660-B527-6FD1CCAAA144: localhost network not found, but must be present");
+    let localhost_network = networks.get("localhost").ok_or_else(|| SsupError::NetworkNotFound {
+        name: "localhost".to_string(),
+        trace_id: "C5A59F87-CB34-4660-B527-6FD1CCAAA144",
+    })?;
     result.mark_as_makefile_mode();
 
-    play.add_net(localhost_network.clone());
-    for single_argument in init_data.args.iter() {
+    let mut localhost_network = localhost_network.clone();
+    select_hosts_by_tags(&mut localhost_network, init_data)?;
+    expand_hosts(&mut localhost_network);
+    play.add_net(localhost_network);
+    for (single_argument, params) in split_params(&init_data.args)? {
+        let single_argument = &single_argument;
         let mut is_command = false;
         let mut is_target = false;
 
@@ -43,22 +52,91 @@ pub fn makefile_mode(init_data: &mut InitState, help_menu: &mut HelpDisplayer) -
             help_menu.show(&init_data.clone());
         }
 
-        if is_command {
-            let command = commands.get(&single_argument.clone()).unwrap();
-            play.add_command(command.clone());
+        if let Some(command) = commands.get(single_argument) {
+            let mut command = command.clone();
+            apply_params(&mut command, &params)?;
+            add_ssup_command_envs(&mut command, "");
+            play.add_command(command);
+        }
+
+        if is_target && !is_command && params != ParamArgs::default() {
+            return Err(SsupError::Usage {
+                message: format!("target '{}' takes no parameters, set them on its steps in the Supfile", single_argument),
+            });
         }
 
         if is_target {
-            let supfile_targets = init_data.supfile.targets.clone();
-            let targets = supfile_targets.get(&single_argument.clone());
-            for target in targets {
-                let command_name = target.command;
-                let command = commands.get(&command_name).unwrap();
-                play.add_command(command.clone());
+            for target in init_data.supfile.target_steps(single_argument)? {
+                let command_name = target.command.clone();
+                let mut command = commands
+                    .get(&command_name)
+                    .ok_or_else(|| SsupError::CommandNotFound {
+                        name: command_name.clone(),
+                        target: single_argument.clone(),
+                        trace_id: "BC9FA2A7-73DB-413D-958C-91A2FCE16041",
+                    })?
+                    .clone();
+                apply_target_step(&mut command, &target);
+                apply_params(&mut command, &ParamArgs::default())?;
+                add_ssup_command_envs(&mut command, single_argument);
+                play.add_command(command);
             }
         }
     }
 
     result.add_play(play);
-    result
-}
\ No newline at end of file
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::entity::const_values::*;
+    use crate::usecase::error::SsupError;
+    use crate::usecase::modes_of_operation::test_support::init_state;
+    use crate::usecase::program_init::parse_initial_args;
+
+    #[test]
+    fn test_sup_envs_on_localhost() {
+        let yaml = r#"
+version: 0.5
+commands:
+  build:
+    run: make
+  test:
+    run: make test
+targets:
+  ci:
+  - test
+"#;
+        let mut state = init_state(yaml, &["-e", "CI=1", "build", "ci"]);
+        let playbook = parse_initial_args(&mut state).unwrap();
+        assert!(playbook.is_makefile_mode());
+
+        let play = &playbook.plays[0];
+        let network = play.nets.as_ref().unwrap();
+        assert_eq!(network.name, "localhost");
+        let env = &play.envs[0].env;
+        assert_eq!(env[SUP_NETWORK_ENV], "localhost");
+        assert_eq!(env[SUP_HOST_ENV], "localhost");
+        assert_eq!(env[SUP_ENV_ENV], "CI=1");
+        assert_eq!(env["CI"], "1");
+
+        let targets: Vec<(String, String)> = play
+            .envs
+            .iter()
+            .map(|command_env| (command_env.env[SUP_COMMAND_ENV].clone(), command_env.env[SUP_TARGET_ENV].clone()))
+            .collect();
+        assert_eq!(targets, vec![("build".to_string(), "".to_string()), ("test".to_string(), "ci".to_string())]);
+    }
+
+    #[test]
+    fn test_tags_apply_to_localhost() {
+        let yaml = "version: 0.5\ncommands:\n  build:\n    run: make\n";
+        let mut state = init_state(yaml, &["--tags", "role=web", "build"]);
+        let err = parse_initial_args(&mut state).err().expect("must fail");
+        assert!(matches!(err, SsupError::NoMatchingHosts { ref network, .. } if network == "localhost"), "{}", err);
+
+        let mut state = init_state(yaml, &["--tags", "!role=web", "build"]);
+        assert_eq!(parse_initial_args(&mut state).unwrap().plays[0].envs.len(), 1);
+    }
+}
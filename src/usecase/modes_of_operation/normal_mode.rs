@@ -1,96 +1,319 @@
+use crate::entity::const_values::ALL_NETWORKS;
 use crate::entity::help_displayer::HelpDisplayer;
+use crate::entity::supfile::commands::Command;
+use crate::entity::supfile::networks::Network;
 use crate::entity::InitState;
-use std::process;
+use crate::entity::env::EnvList;
 use crate::gateways::logger::logger_func as l;
 use crate::entity::playbook::{PlayBook,Play};
-use crate::usecase::env_parser::parse_env;
-use crate::usecase::{ensure_network_exists,override_env_from_args,add_ssup_default_envs};
-use crate::usecase::parse_network::parse_inventory;
+use crate::usecase::env_parser::env_from_args;
+use crate::usecase::{ensure_network_exists,expand_hosts,add_ssup_command_envs,apply_target_step,select_hosts_by_tags};
+use crate::usecase::env_resolver::inventory_hosts;
+use crate::usecase::params::{apply_params, split_params, ParamArgs};
+use crate::usecase::error::SsupError;
 
 #[allow(dead_code)]
 /// Parses the initial arguments and constructs a playbook.
 ///
 /// This function processes command-line arguments, retrieves network information,
 /// parses inventory, and builds a playbook containing commands and targets.
+/// The network argument may list several networks separated by commas, or be
+/// `all`; each network gets its own play with the same commands.
 ///
 /// # Errors
 ///
-/// Returns an `AppInitError` if the network or command is not found, or if an invalid
-/// command or target is specified.
-pub fn normal_mode(init_data: &InitState, help_menu: &HelpDisplayer) -> PlayBook {
+/// Returns an `SsupError` if a network or a target's command is not found, the
+/// inventory fails, or `--env`/`--tags` are invalid.
+pub fn normal_mode(init_data: &InitState, help_menu: &HelpDisplayer) -> Result<PlayBook, SsupError> {
     l("usecase::ParseInitialArgs::normalMode");
 
-    let mut result = PlayBook { 
-        plays: Vec::new(),
-        is_makefile: false,
-    };
+    let mut result = PlayBook::new();
+    result.networks_parallel = init_data.flags.networks_parallel;
 
-    let env_from_args = parse_env(&init_data.flags.env);
+    let env_from_args = env_from_args(init_data)?;
     let mut args = init_data.args.clone();
 
-    let network_name = args.remove(0);
+    let network_names = resolve_network_names(&args.remove(0), init_data);
+    let commands = collect_commands(&args, init_data, help_menu)?;
 
-    ensure_network_exists(&network_name, &init_data, &help_menu);
+    for network_name in network_names {
+        ensure_network_exists(&network_name, init_data, help_menu)?;
+        let network = prepare_network(&network_name, &env_from_args, init_data)?;
 
+        let mut play = Play::new();
+        play.add_net(network);
+        for command in &commands {
+            play.add_command(command.clone());
+        }
+        result.add_play(play);
+    }
+
+    l("dump: A0ED3871-1622-4D93-BCBF-1924CE2828A9");
+    l(&format!("{:?}", &init_data));
+
+    Ok(result)
+}
+
+/// Expands `prod-eu,prod-us` into its network names and the reserved
+/// `all` into every network of the Supfile, in declaration order.
+fn resolve_network_names(arg: &str, init_data: &InitState) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for name in arg.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+        let expanded = if name == ALL_NETWORKS {
+            init_data.supfile.networks.names.clone()
+        } else {
+            vec![name.to_string()]
+        };
+        for name in expanded {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+    names
+}
+
+fn prepare_network(network_name: &str, env_from_args: &EnvList, init_data: &InitState) -> Result<Network, SsupError> {
     let mut network = init_data.supfile
         .networks
-        .clone()
-        .nets
-        .get(&network_name)
+        .get(network_name)
         .cloned()
-        .expect(format!("Network '{}' not found", network_name).as_str());
-
-    l("parse CLI --env flag env vars, override values defined in Network env");
-    override_env_from_args(&env_from_args, &mut network);
+        .ok_or_else(|| SsupError::NetworkNotFound {
+            name: network_name.to_string(),
+            trace_id: "CE387BA5-D366-4437-8F8D-6DF97609CB77",
+        })?;
 
     l("check if we have an inventory via script execution");
-    let hosts = parse_inventory(&network).expect("failed to parse inventory from script");
+    let hosts = inventory_hosts(init_data, &network, env_from_args)?;
     network.hosts.extend(hosts);
 
-    add_ssup_default_envs(&mut network, &init_data);
+    select_hosts_by_tags(&mut network, init_data)?;
+    expand_hosts(&mut network);
+    Ok(network)
+}
 
-    let mut play = Play {
-        nets: Some(network),
-        commands: Vec::new(),
-    };
+fn collect_commands(args: &[String], init_data: &InitState, help_menu: &HelpDisplayer) -> Result<Vec<Command>, SsupError> {
+    let conf = &init_data.supfile;
+    let mut commands = Vec::new();
 
-
-    for single_argument in args {
+    for (single_argument, params) in split_params(args)? {
+        let single_argument = &single_argument;
         l(&format!("parse given command: {}", single_argument));
-        l("check if its a target");
-        let conf = init_data.supfile.clone();
 
         // check if its a command
-        if let Some(command) = conf.commands.get(&single_argument) {
+        if let Some(command) = conf.commands.get(single_argument) {
             l(&format!("found command: {}", single_argument));
-            play.commands.push(command.clone());
+            let mut command = command.clone();
+            apply_params(&mut command, &params)?;
+            add_ssup_command_envs(&mut command, "");
+            commands.push(command);
+            continue;
         }
-        
+
         // check if its a target
-        let targets = conf.targets.clone();
-        if !targets.is_empty() {
+        l("check if its a target");
+        if conf.targets.has(single_argument) {
             l(&format!("found target: {}", single_argument));
-            let targets = targets.get(&single_argument);
-            for single_target in targets {
+            if params != ParamArgs::default() {
+                return Err(SsupError::Usage {
+                    message: format!("target '{}' takes no parameters, set them on its steps in the Supfile", single_argument),
+                });
+            }
+            for single_target in conf.target_steps(single_argument)? {
                 let command_name = single_target.command.clone();
                 if let Some(target_command) = conf.commands.get(&command_name) {
-                    play.commands.push(target_command.clone());
+                    let mut target_command = target_command.clone();
+                    apply_target_step(&mut target_command, &single_target);
+                    apply_params(&mut target_command, &ParamArgs::default())?;
+                    add_ssup_command_envs(&mut target_command, single_argument);
+                    commands.push(target_command);
                 } else {
-                    eprintln!(
-                        "ERR: 64B2D565-8345-4108-B790-25606C2128C0, command not found: {}, while traversing Targets:",
-                        command_name
-                    );
-                    process::exit(1);
+                    return Err(SsupError::CommandNotFound {
+                        name: command_name,
+                        target: single_argument.clone(),
+                        trace_id: "64B2D565-8345-4108-B790-25606C2128C0",
+                    });
                 }
             }
         } else {
-            help_menu.show(&init_data);
+            help_menu.show(init_data);
         }
     }
+    Ok(commands)
+}
 
-    result.add_play(play);
-    l("dump: A0ED3871-1622-4D93-BCBF-1924CE2828A9");
-    l(&format!("{:?}", &init_data));
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::const_values::*;
+    use crate::usecase::modes_of_operation::test_support::init_state;
+    use crate::usecase::program_init::parse_initial_args;
+
+    const SUPFILE: &str = r#"
+version: 0.5
+networks:
+  prod-eu:
+    hosts: [root@10.0.0.1, deploy@10.0.0.2:2222]
+  prod-us:
+    hosts: [root@10.1.0.1]
+commands:
+  deploy:
+    run: echo deploy
+  restart:
+    run: echo restart
+targets:
+  release:
+  - deploy
+"#;
+
+    fn command_env(command: &Command) -> EnvList {
+        command.env.clone().unwrap_or_default()
+    }
+
+    #[test]
+    fn test_resolve_network_names() {
+        let state = init_state(SUPFILE, &[]);
+        assert_eq!(resolve_network_names("prod-eu", &state), vec!["prod-eu"]);
+        assert_eq!(resolve_network_names("prod-us,prod-eu,prod-us", &state), vec!["prod-us", "prod-eu"]);
+        assert_eq!(resolve_network_names("all", &state), vec!["prod-eu", "prod-us"]);
+
+        // declaration order, not name order
+        let yaml = "version: 0.5\nnetworks:\n  zeta:\n    hosts: [root@10.0.0.3]\n  alpha:\n    hosts: [root@10.0.0.1]\n  mid:\n    hosts: [root@10.0.0.2]\ncommands: {}\n";
+        let state = init_state(yaml, &[]);
+        assert_eq!(resolve_network_names("all", &state), vec!["zeta", "alpha", "mid"]);
+        assert_eq!(resolve_network_names("mid,all", &state), vec!["mid", "zeta", "alpha"]);
+    }
 
-    result
-}
\ No newline at end of file
+    #[test]
+    fn test_one_play_per_network() {
+        let state = init_state(SUPFILE, &["--networks-parallel", "prod-eu,prod-us", "deploy"]);
+        let playbook = normal_mode(&state, &HelpDisplayer::new(state.clone())).unwrap();
+        assert!(playbook.networks_parallel);
+        let networks: Vec<String> = playbook.plays.iter().map(|play| play.network_name()).collect();
+        assert_eq!(networks, vec!["prod-eu", "prod-us"]);
+        assert!(playbook.plays.iter().all(|play| play.commands.len() == 1));
+        assert_eq!(playbook.plays_by_network().len(), 2);
+        assert!(playbook.to_string().starts_with("Plan: 2 network(s), in parallel\n"));
+
+        let state = init_state(SUPFILE, &["prod-eu,prod-us", "deploy"]);
+        let playbook = normal_mode(&state, &HelpDisplayer::new(state.clone())).unwrap();
+        assert!(playbook.to_string().starts_with("Plan: 2 network(s), sequentially\n"));
+    }
+
+    #[test]
+    fn test_sup_envs_are_set_per_host() {
+        let mut state = init_state(SUPFILE, &["-e", "A=b", "prod-eu,prod-us", "deploy", "release"]);
+        let playbook = parse_initial_args(&mut state).unwrap();
+
+        // per play, every host for the first command, then for the next
+        let env = |play: usize, index: usize| playbook.plays[play].envs[index].env.clone();
+        let (first, second, other) = (env(0, 0), env(0, 1), env(1, 0));
+        assert_eq!(playbook.plays[0].envs[1].target, "prod-eu/10.0.0.2:2222");
+
+        assert_eq!(first[SUP_NETWORK_ENV], "prod-eu");
+        assert_eq!(other[SUP_NETWORK_ENV], "prod-us");
+        assert_eq!(first[SUP_HOST_ENV], "10.0.0.1");
+        assert_eq!(second[SUP_HOST_ENV], "10.0.0.2:2222");
+        assert_eq!(first[SUP_ENV_ENV], "A=b");
+        assert!(first.contains_key(SUP_USER_ENV));
+        assert!(!first[SUP_TIME_ENV].is_empty());
+        assert!(!first[SUP_RUN_ID_ENV].is_empty());
+        assert_eq!(first[SUP_RUN_ID_ENV], other[SUP_RUN_ID_ENV]);
+        assert_eq!(first[SUP_TIME_ENV], second[SUP_TIME_ENV]);
+        assert_eq!(first["A"], "b");
+
+        assert_eq!(first[SUP_COMMAND_ENV], "deploy");
+        assert_eq!(first[SUP_TARGET_ENV], "");
+        assert_eq!(env(0, 2)[SUP_COMMAND_ENV], "deploy");
+        assert_eq!(env(0, 2)[SUP_TARGET_ENV], "release");
+    }
+
+    #[test]
+    fn test_supfile_env_and_secrets_reach_commands() {
+        let yaml = "version: 0.5\nenv:\n  APP: shop\n  REGION: global\nnetworks:\n  prod:\n    env:\n      REGION: eu\n    hosts:\n    - host: root@10.0.0.1\n      env:\n        APP: cart\ncommands:\n  deploy:\n    run: echo deploy\n";
+        let mut state = init_state(yaml, &["-e", "REGION=us", "prod", "deploy"]);
+        let secrets = indexmap::IndexMap::from([("DB_PASS".to_string(), "from-secrets-file".to_string())]);
+        crate::usecase::secrets::merge_secrets(&mut state.supfile.env, &secrets);
+
+        let playbook = parse_initial_args(&mut state).unwrap();
+        // the network keeps its own env, the layers meet in the command's env
+        let network_env = playbook.plays[0].nets.as_ref().unwrap().env.clone().unwrap();
+        assert_eq!(network_env.keys_owned(), vec!["REGION"]);
+        assert_eq!(network_env["REGION"], "eu");
+
+        let env = playbook.plays[0].envs[0].env.clone();
+        assert_eq!(env["APP"], "cart");
+        assert_eq!(env["REGION"], "us");
+        assert_eq!(env["DB_PASS"], "from-secrets-file");
+        assert!(env.is_secret("DB_PASS"));
+    }
+
+    #[test]
+    fn test_nested_targets_run_in_order() {
+        let yaml = "version: 0.5\nnetworks:\n  prod:\n    hosts: [root@10.0.0.1]\ncommands:\n  build: {run: make}\n  migrate: {run: ./migrate}\n  restart: {run: ./restart}\ntargets:\n  prepare: [build, migrate]\n  release: [prepare, build, restart]\n  loop: [build, again]\n  again: [loop]\n";
+        let names = |yaml: &str, args: &[&str]| -> Vec<String> {
+            let state = init_state(yaml, args);
+            let playbook = normal_mode(&state, &HelpDisplayer::new(state.clone())).unwrap();
+            playbook.plays[0].commands.iter().map(|command| command.name.clone()).collect()
+        };
+        assert_eq!(names(yaml, &["prod", "release"]), vec!["build", "migrate", "build", "restart"]);
+        let deduped = format!("dedup_targets: true\n{}", yaml);
+        assert_eq!(names(&deduped, &["prod", "release"]), vec!["build", "migrate", "restart"]);
+
+        let state = init_state(yaml, &["prod", "loop"]);
+        let err = normal_mode(&state, &HelpDisplayer::new(state.clone())).err().expect("must fail");
+        assert_eq!(err.to_string(), "target cycle: loop -> again -> loop");
+    }
+
+    #[test]
+    fn test_target_steps_override_command_env() {
+        let yaml = "version: 0.5\nnetworks:\n  prod:\n    hosts: [root@10.0.0.1]\ncommands:\n  restart:\n    run: systemctl restart $SERVICE\n    env:\n      SERVICE: all\ntargets:\n  bounce:\n  - restart SERVICE=api --once\n  - restart SERVICE=worker --serial 2\n";
+        let state = init_state(yaml, &["prod", "bounce"]);
+        let playbook = normal_mode(&state, &HelpDisplayer::new(state.clone())).unwrap();
+        let commands = &playbook.plays[0].commands;
+        assert_eq!(command_env(&commands[0])["SERVICE"], "api");
+        assert_eq!((commands[0].once, commands[0].serial), (true, None));
+        assert_eq!(command_env(&commands[1])["SERVICE"], "worker");
+        assert_eq!((commands[1].once, commands[1].serial), (false, Some(2)));
+        assert_eq!(command_env(&commands[1])[SUP_TARGET_ENV], "bounce");
+        assert_eq!(state.supfile.commands["restart"].env.as_ref().unwrap()["SERVICE"], "all");
+    }
+
+    #[test]
+    fn test_params_from_the_command_line() {
+        let yaml = "version: 0.5\nnetworks:\n  prod:\n    hosts: [root@10.0.0.1]\ncommands:\n  deploy:\n    run: ./deploy \"$@\"\n    params:\n    - name: version\n      required: true\n    - name: stage\n      default: prod\n      choices: [prod, canary]\ntargets:\n  canary:\n  - deploy version=v1 stage=canary\n";
+        let commands = |args: &[&str]| {
+            let state = init_state(yaml, args);
+            normal_mode(&state, &HelpDisplayer::new(state.clone())).map(|playbook| playbook.plays[0].commands.clone())
+        };
+
+        let deploy = &commands(&["prod", "deploy", "--", "v1.2.3", "canary", "--force"]).unwrap()[0];
+        assert_eq!(command_env(deploy)["version"], "v1.2.3");
+        assert_eq!(deploy.run.as_deref(), Some("set -- 'v1.2.3' 'canary' '--force'\n./deploy \"$@\""));
+
+        let deploy = &commands(&["prod", "deploy", "version=v2", "stage=canary"]).unwrap()[0];
+        assert_eq!((command_env(deploy)["version"].as_str(), command_env(deploy)["stage"].as_str()), ("v2", "canary"));
+
+        let deploy = &commands(&["prod", "canary"]).unwrap()[0];
+        assert_eq!(deploy.run.as_deref(), Some("set -- 'v1' 'canary'\n./deploy \"$@\""));
+
+        let err = commands(&["prod", "deploy", "stage=dev"]).unwrap_err();
+        assert!(matches!(err, SsupError::InvalidParams { ref problems, .. } if problems.len() == 2), "{}", err);
+        assert!(matches!(commands(&["prod", "canary", "version=v3"]), Err(SsupError::Usage { .. })));
+    }
+
+    #[test]
+    fn test_errors_are_returned() {
+        let yaml = "version: 0.5\nnetworks:\n  prod:\n    hosts: [root@10.0.0.1]\ncommands:\n  deploy:\n    run: echo deploy\ntargets:\n  release:\n  - deploy\n  - migrate\n";
+        let state = init_state(yaml, &["prod", "release"]);
+        let err = normal_mode(&state, &HelpDisplayer::new(state.clone())).err().expect("must fail");
+        assert!(matches!(err, SsupError::CommandNotFound { ref name, ref target, .. } if name == "migrate" && target == "release"));
+
+        let state = init_state(yaml, &["-e", "not a pair", "prod", "deploy"]);
+        let err = normal_mode(&state, &HelpDisplayer::new(state.clone())).err().expect("must fail");
+        assert!(matches!(err, SsupError::Env(_)), "{}", err);
+
+        let bad_target = serde_yaml::from_str::<crate::entity::supfile::Supfile>("version: 0.5\ntargets:\n  release:\n  - deploy web extra\n");
+        assert!(bad_target.unwrap_err().to_string().contains("target release: 'deploy web extra' must be COMMAND [NETWORK]"));
+    }
+}
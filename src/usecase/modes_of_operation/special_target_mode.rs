@@ -3,13 +3,15 @@ use crate::entity::playbook::{Play, PlayBook};
 use crate::entity::supfile::networks::Network;
 use crate::gateways::logger::logger_func as l;
 use crate::entity::InitState;
-use crate::usecase::{add_ssup_default_envs, ensure_network_exists};
+use crate::usecase::{add_ssup_command_envs, apply_target_step, ensure_network_exists, expand_hosts, select_hosts_by_tags};
+use crate::usecase::error::SsupError;
+use crate::usecase::params::{apply_params, ParamArgs};
 
 #[allow(dead_code)]
 #[allow(unused_imports)]
 // SpecialTargetMode is a mode where all args are Target names
 // and networks are actually defined inside Supfile
-pub fn special_target_mode(init_state: &InitState, help_displayer: &mut HelpDisplayer) -> PlayBook {
+pub fn special_target_mode(init_state: &InitState, help_displayer: &mut HelpDisplayer) -> Result<PlayBook, SsupError> {
     l("special target mode");
     // targets is really a hash map
     // pub targets: Option<HashMap<String, String>>,
@@ -22,7 +24,13 @@ pub fn special_target_mode(init_state: &InitState, help_displayer: &mut HelpDisp
         // if it fails than we have 2 fail here
         l(format!("argument name: {}", single_arg).as_str());
 
-        let affixed_targets = targets.get(&single_arg);
+        if !targets.has(&single_arg) {
+            return Err(SsupError::TargetNotFound {
+                name: single_arg.clone(),
+                trace_id: "549C54DE-96F7-4108-9FC8-B0CCB6598BDB",
+            });
+        }
+        let affixed_targets = init_state.supfile.target_steps(&single_arg)?;
         
         l(format!("{:?}", affixed_targets).as_str());
         for affixed_target in affixed_targets {
@@ -33,28 +41,95 @@ pub fn special_target_mode(init_state: &InitState, help_displayer: &mut HelpDisp
             let report = format!("affix: {:?}\ncommand: {}", affixed_target, command_name);
             l(format!("{}", report).as_str());
 
-            ensure_network_exists(affixed_network.as_str(), init_state, help_displayer);
-            let affixed_network: &mut Network = networks.get_mut(affixed_network.as_str()).expect(format!(
-                "F0AABC9B-9214-444D-B864-F6AE785EA695: error getting network {}", 
-                affixed_network.as_str()).as_str());
-            // TODO rewrite replacement for OverrideEnvFromArgs
-            // and use it here, skipped for now
-            add_ssup_default_envs(affixed_network, &init_state);
-            let command = commands
-            .get(&command_name.clone())
-            .expect("0325F68E-5D0C-45BA-970E-D97C5D38B07A3: error getting command");
+            ensure_network_exists(affixed_network.as_str(), init_state, help_displayer)?;
+            let affixed_network: &mut Network = networks.get_mut(affixed_network.as_str()).ok_or_else(|| SsupError::NetworkNotFound {
+                name: affixed_network.clone(),
+                trace_id: "F0AABC9B-9214-444D-B864-F6AE785EA695",
+            })?;
+            select_hosts_by_tags(affixed_network, init_state)?;
+            expand_hosts(affixed_network);
+            let mut command = commands
+            .get(&command_name)
+            .ok_or_else(|| SsupError::CommandNotFound {
+                name: command_name.clone(),
+                target: single_arg.clone(),
+                trace_id: "0325F68E-5D0C-45BA-970E-D97C5D38B07A",
+            })?
+            .clone();
+            apply_target_step(&mut command, &affixed_target);
+            apply_params(&mut command, &ParamArgs::default())?;
+            add_ssup_command_envs(&mut command, &single_arg);
 
             l(format!("command: {:?}", command).as_str());
             l(format!("affix: {:?}", affixed_network).as_str());
 
             // fill in the values for play
             let mut play = Play::new();
-            play.add_command(command.clone());
+            play.add_command(command);
             play.add_net(affixed_network.clone());
 
             result.add_play(play);
         }
     }
-    result
-        
-}
\ No newline at end of file
+    Ok(result)
+
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::entity::const_values::*;
+    use crate::usecase::error::SsupError;
+    use crate::usecase::modes_of_operation::test_support::init_state;
+    use crate::usecase::program_init::parse_initial_args;
+
+    #[test]
+    fn test_sup_envs_follow_affixed_networks() {
+        let yaml = r#"
+version: 0.5
+networks:
+  web:
+    hosts: [root@10.0.0.1]
+  db:
+    hosts: [root@10.0.1.1]
+commands:
+  deploy:
+    run: echo deploy
+  migrate:
+    run: echo migrate
+targets:
+  release:
+  - deploy web
+  - migrate db
+"#;
+        let mut state = init_state(yaml, &["release"]);
+        let playbook = parse_initial_args(&mut state).unwrap();
+        assert_eq!(playbook.plays.len(), 2);
+
+        for play in &playbook.plays {
+            let network = play.nets.as_ref().unwrap();
+            let command = &play.commands[0];
+            let env = &play.envs[0].env;
+            assert_eq!(env[SUP_NETWORK_ENV], network.name);
+            assert!(env[SUP_HOST_ENV].starts_with("10.0."));
+            assert_eq!(env[SUP_ENV_ENV], "");
+            assert!(!env[SUP_RUN_ID_ENV].is_empty());
+            assert_eq!(env[SUP_COMMAND_ENV], command.name);
+            assert_eq!(env[SUP_TARGET_ENV], "release");
+            let expected_network = if command.name == "deploy" { "web" } else { "db" };
+            assert_eq!(network.name, expected_network);
+        }
+    }
+
+    #[test]
+    fn test_tags_select_hosts() {
+        let yaml = "version: 0.5\nnetworks:\n  web:\n    hosts: ['root@10.0.0.1 [role=web]', 'root@10.0.0.2 [role=web, canary]']\ncommands:\n  deploy:\n    run: echo deploy\ntargets:\n  release:\n  - deploy web\n";
+        let mut state = init_state(yaml, &["--tags", "canary", "release"]);
+        let playbook = parse_initial_args(&mut state).unwrap();
+        let hosts: Vec<&str> = playbook.plays[0].envs.iter().map(|env| env.target.as_str()).collect();
+        assert_eq!(hosts, vec!["web/10.0.0.2"]);
+
+        let mut state = init_state(yaml, &["--tags", "role=db", "release"]);
+        let err = parse_initial_args(&mut state).err().expect("must fail");
+        assert!(matches!(err, SsupError::NoMatchingHosts { ref network, .. } if network == "web"), "{}", err);
+    }
+}
@@ -1,4 +1,4 @@
-use crate::entity::const_values::{PASS_SEPARATOR, TUBE_NAME_SEPARATOR};
+use crate::entity::const_values::{PASS_SEPARATOR, TAGS_START, TUBE_NAME_SEPARATOR};
 
 fn contains_one(s: &str, subs: &[String]) -> bool {
     for sub in subs {
@@ -62,6 +62,26 @@ pub fn find_tube_name_end(host: &str) -> Option<usize> {
     Some(result)
 }
 
+/// Splits a trailing ` [role=web, az=b]` tag list off a host string.
+pub fn split_tags(host: &str) -> (&str, Vec<String>) {
+    let trimmed = host.trim_end();
+    if !trimmed.ends_with(']') {
+        return (host, Vec::new());
+    }
+    match trimmed.rfind(TAGS_START) {
+        Some(index) => {
+            let list = &trimmed[index + TAGS_START.len()..trimmed.len() - 1];
+            let tags = list
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|tag| !tag.is_empty())
+                .map(|tag| tag.to_string())
+                .collect();
+            (&host[..index], tags)
+        }
+        None => (host, Vec::new()),
+    }
+}
+
 
 
 #[cfg(test)]
@@ -93,6 +113,17 @@ mod tests {
         assert_eq!(result, Some(21));
     }
 
+    #[test]
+    fn test_split_tags() {
+        let (host, tags) = split_tags("root@10.0.0.1 | pass << tube [role=web, az=b canary]");
+        assert_eq!(host, "root@10.0.0.1 | pass << tube");
+        assert_eq!(tags, vec!["role=web", "az=b", "canary"]);
+
+        let (host, tags) = split_tags("root@10.0.0.1 | pa]ss");
+        assert_eq!(host, "root@10.0.0.1 | pa]ss");
+        assert!(tags.is_empty());
+    }
+
     #[test]
     fn test_find_tube_name_end() {
         let host = "user:password@hostname#tube";
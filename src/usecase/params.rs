@@ -0,0 +1,169 @@
+use crate::entity::const_values::POSITIONAL_SEPARATOR;
+use crate::entity::env::{is_env_name, shell_quote, EnvList};
+use crate::entity::supfile::commands::Command;
+use crate::gateways::logger::logger_func as l;
+use crate::usecase::diagnostics::did_you_mean;
+use crate::usecase::error::SsupError;
+
+/// Parameter values given after a command or target name.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ParamArgs {
+    /// `NAME=value` words
+    pub named: Vec<(String, String)>,
+    /// every word after `--`
+    pub positional: Vec<String>,
+}
+
+/// Splits the words after the network into names, each with the `NAME=value`
+/// words following it. Everything after `--` belongs to the last name.
+pub fn split_params(args: &[String]) -> Result<Vec<(String, ParamArgs)>, SsupError> {
+    let mut names: Vec<(String, ParamArgs)> = Vec::new();
+    let mut words = args.iter();
+    while let Some(word) = words.next() {
+        if word == POSITIONAL_SEPARATOR {
+            let (_, params) = names.last_mut().ok_or_else(|| params_usage("`--` must follow a command"))?;
+            params.positional.extend(words.by_ref().cloned());
+            break;
+        }
+        match word.split_once('=') {
+            Some((name, value)) if is_env_name(name) => {
+                let (_, params) = names
+                    .last_mut()
+                    .ok_or_else(|| params_usage(&format!("`{}` must follow a command", word)))?;
+                params.named.push((name.to_string(), value.to_string()));
+            }
+            _ => names.push((word.clone(), ParamArgs::default())),
+        }
+    }
+    Ok(names)
+}
+
+fn params_usage(problem: &str) -> SsupError {
+    SsupError::Usage {
+        message: format!("{}\nUsage: ssup [OPTIONS] NETWORK COMMAND [NAME=value...] [-- VALUE...]", problem),
+    }
+}
+
+/// Validates the values given for `command`'s `params:` and exports them:
+/// each as an env var of its name, and all of them, in declaration order and
+/// followed by any extra positional words, as `$1..$n` of `run` and `local`.
+///
+/// A value comes from `NAME=value`, else the positional word at the param's
+/// place, else the command's env (a target step's `NAME=value`), else the
+/// default. Every problem is reported at once.
+pub fn apply_params(command: &mut Command, given: &ParamArgs) -> Result<(), SsupError> {
+    let mut problems = Vec::new();
+    for (name, _) in &given.named {
+        if !command.params.iter().any(|param| &param.name == name) {
+            let known = command.params.iter().map(|param| param.name.as_str());
+            match did_you_mean(name, known) {
+                Some(suggestion) => problems.push(format!("unknown parameter `{}`; did you mean `{}`?", name, suggestion)),
+                None => problems.push(format!("unknown parameter `{}`", name)),
+            }
+        }
+    }
+
+    let env = command.env.get_or_insert_with(EnvList::new);
+    let mut values = Vec::new();
+    for (index, param) in command.params.iter().enumerate() {
+        let value = given
+            .named
+            .iter()
+            .rev()
+            .find(|(name, _)| name == &param.name)
+            .map(|(_, value)| value.clone())
+            .or_else(|| given.positional.get(index).cloned())
+            .or_else(|| env.get(&param.name).map(str::to_string))
+            .or_else(|| param.default.clone());
+        match value {
+            Some(value) => {
+                if let Some(problem) = param.reject(&value) {
+                    problems.push(problem);
+                }
+                values.push(value);
+            }
+            None if param.required => problems.push(format!("`{}` is required", param.name)),
+            None => values.push(String::new()),
+        }
+    }
+    if !problems.is_empty() {
+        return Err(SsupError::InvalidParams {
+            command: command.name.clone(),
+            problems,
+            trace_id: "36473A4D-0F4C-40A7-B778-FC11C30A47A9",
+        });
+    }
+
+    for (param, value) in command.params.iter().zip(&values) {
+        env.set(param.name.clone(), value.clone());
+    }
+    values.extend(given.positional.iter().skip(command.params.len()).cloned());
+    if values.is_empty() {
+        return Ok(());
+    }
+    l(&format!("params of {}: {} positional value(s)", command.name, values.len()));
+    let set = format!("set -- {}", values.iter().map(|value| shell_quote(value)).collect::<Vec<_>>().join(" "));
+    for script in [&mut command.run, &mut command.local].into_iter().flatten() {
+        *script = format!("{}\n{}", set, script);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    fn deploy() -> Command {
+        let yaml = "run: ./deploy \"$@\"\nparams:\n- name: version\n  required: true\n  regex: 'v[0-9.]+'\n- name: stage\n  default: prod\n  choices: [prod, canary]\n";
+        let mut command: Command = serde_yaml::from_str(yaml).unwrap();
+        command.name = "deploy".to_string();
+        command
+    }
+
+    #[test]
+    fn test_split_params() {
+        let split = split_params(&words(&["build", "deploy", "version=v1.2.3", "stage=canary", "--", "v1", "--force"])).unwrap();
+        assert_eq!(split[0], ("build".to_string(), ParamArgs::default()));
+        assert_eq!(split[1].0, "deploy");
+        assert_eq!(split[1].1.named, vec![("version".to_string(), "v1.2.3".to_string()), ("stage".to_string(), "canary".to_string())]);
+        assert_eq!(split[1].1.positional, words(&["v1", "--force"]));
+
+        assert!(matches!(split_params(&words(&["--", "v1"])), Err(SsupError::Usage { .. })));
+        assert!(matches!(split_params(&words(&["version=v1", "deploy"])), Err(SsupError::Usage { .. })));
+    }
+
+    #[test]
+    fn test_apply_params() {
+        let mut command = deploy();
+        let given = ParamArgs { named: vec![], positional: words(&["v1.2.3", "canary", "--force"]) };
+        apply_params(&mut command, &given).unwrap();
+        let env = command.env.clone().unwrap();
+        assert_eq!((env.get("version"), env.get("stage")), (Some("v1.2.3"), Some("canary")));
+        assert_eq!(command.run.as_deref(), Some("set -- 'v1.2.3' 'canary' '--force'\n./deploy \"$@\""));
+
+        let mut command = deploy();
+        let given = ParamArgs { named: vec![("version".to_string(), "v2".to_string())], positional: vec![] };
+        apply_params(&mut command, &given).unwrap();
+        assert_eq!(command.env.unwrap().get("stage"), Some("prod"));
+        assert_eq!(command.run.as_deref(), Some("set -- 'v2' 'prod'\n./deploy \"$@\""));
+    }
+
+    #[test]
+    fn test_invalid_params_are_all_reported() {
+        let mut command = deploy();
+        let given = ParamArgs { named: vec![("stag".to_string(), "dev".to_string())], positional: vec![] };
+        let err = apply_params(&mut command, &given).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid parameters for command 'deploy':\n  unknown parameter `stag`; did you mean `stage`?\n  `version` is required"
+        );
+
+        let given = ParamArgs { named: vec![], positional: words(&["1.2", "dev"]) };
+        let err = apply_params(&mut deploy(), &given).unwrap_err();
+        assert!(matches!(err, SsupError::InvalidParams { ref problems, .. } if problems.len() == 2), "{}", err);
+    }
+}
@@ -1,19 +1,44 @@
 
 use crate::usecase::network_host_utils::*;
 use crate::entity::supfile::networks::{Network, HostEntry, HostDetails};
-use crate::entity::const_values::TUBE_NAME_SEPARATOR;
-use std::process::{Command, Stdio, Output};
-use std::io::{self};
-use std::collections::HashMap;
+use crate::entity::const_values::{PASS_SEPARATOR, TUBE_NAME_SEPARATOR};
+use crate::entity::secret::Secret;
+use crate::entity::supfile::networks::redact_host_string;
+use crate::entity::const_values::{DEFAULT_INVENTORY_TIMEOUT_SECS, DEFAULT_SHELL_VALUE_TIMEOUT_SECS};
+use crate::entity::env::EnvList;
+use crate::entity::supfile::OutputPolicy;
+use crate::gateways::inventory_cache::InventoryCache;
+use crate::gateways::shell::{run_sh_with_timeout, ShellRunError};
+use std::process::ExitStatus;
+use std::string::FromUtf8Error;
+use std::time::Duration;
 use crate::gateways::logger::logger_func as l;
 use thiserror::Error;
-use std::{fmt,env};
 
 
 #[derive(Error, Debug)]
-enum ParseError {
-    #[error("Missing inventory field in Supfile")]
-    MissingInventory,
+pub enum InventoryError {
+    #[error("inventory script for network '{network}' failed: {source}")]
+    Run {
+        network: String,
+        #[source]
+        source: ShellRunError,
+    },
+    #[error("inventory script for network '{network}' exited with {status}")]
+    Failed {
+        network: String,
+        status: ExitStatus,
+        trace_id: &'static str,
+    },
+}
+
+impl InventoryError {
+    pub fn trace_id(&self) -> &'static str {
+        match self {
+            InventoryError::Run { source, .. } => source.trace_id(),
+            InventoryError::Failed { trace_id, .. } => trace_id,
+        }
+    }
 }
 
 
@@ -23,72 +48,91 @@ pub enum ShellResolveError {
     MissingPrefix,
     #[error("Missing ) suffix")]
     MissingSuffix,
-    #[error("Failed to run command: {cmd}")]
+    #[error("Failed to run command: {cmd}: {source}")]
     CommandExecution {
         cmd: String,
         #[source]
-        source: io::Error,
+        source: ShellRunError,
+    },
+    #[error("Command {cmd} exited with {status}")]
+    CommandFailed {
+        cmd: String,
+        status: ExitStatus,
         trace_id: &'static str,
     },
-    #[error("Failed to filter non-printable characters")]
-    FilterError {
+    #[error("Command {cmd} printed invalid UTF-8: {source}")]
+    InvalidUtf8 {
+        cmd: String,
         #[source]
-        source: io::Error,
+        source: FromUtf8Error,
         trace_id: &'static str,
     },
 }
 
 impl ShellResolveError {
-    fn command_error(cmd: &str, source: io::Error) -> Self {
-        ShellResolveError::CommandExecution {
-            cmd: cmd.to_string(),
-            source,
-            trace_id: "6928F3B4-0D17-45FB-9633-DABA63E163A1",
-        }
-    }
-
-    fn filter_error(source: io::Error) -> Self {
-        ShellResolveError::FilterError {
-            source,
-            trace_id: "CE16720F-D992-4EA3-9E68-3F1A740A66C1",
+    pub fn trace_id(&self) -> &'static str {
+        match self {
+            ShellResolveError::MissingPrefix => "A5E08CB4-B68E-4BBF-B82A-DE18C60B1DA8",
+            ShellResolveError::MissingSuffix => "1BDEE33B-C757-4D6D-852B-8012B665F9D6",
+            ShellResolveError::CommandExecution { source, .. } => source.trace_id(),
+            ShellResolveError::CommandFailed { trace_id, .. } | ShellResolveError::InvalidUtf8 { trace_id, .. } => trace_id,
         }
     }
 }
 
 
-pub fn parse_inventory(network: &Network) -> Result<Vec<HostEntry>,std::io::Error> {
-    let mut inventory_data = "".to_string();
-    let mut network_env: HashMap<String,String> = HashMap::new();
-
-    if let Some(ref network_env_unpacked) = network.env {
-        let mut new_env = env::vars().collect::<HashMap<String, String>>();
-        new_env.extend(network_env_unpacked.iter().map(|(k, v)| (k.clone(), v.clone())));
-        network_env = network_env_unpacked.clone();
-    }
-
-    if let Some(inventory) = &network.inventory {
-        inventory_data = inventory.clone();
-    }
+/// Runs the network's `inventory:` script and parses every non-empty output
+/// line as a host. With `inventory_ttl` set the output is served from the
+/// on-disk cache until it expires, unless `refresh` forces a new run.
+pub fn parse_inventory(network: &Network, refresh: bool) -> Result<Vec<HostEntry>, InventoryError> {
+    parse_inventory_with_cache(network, refresh, &InventoryCache::default_location())
+}
 
-    let output = Command::new("/bin/sh")
-        .arg("-c")
-        .arg(inventory_data)
-        .envs(&network_env)
-        .stderr(Stdio::inherit())
-        .output()?;
+pub fn parse_inventory_with_cache(network: &Network, refresh: bool, cache: &InventoryCache) -> Result<Vec<HostEntry>, InventoryError> {
+    let inventory_data = match &network.inventory {
+        Some(inventory) if !inventory.trim().is_empty() => inventory,
+        _ => return Ok(Vec::new()),
+    };
+    let network_env = network.env.clone().unwrap_or_default();
+
+    let ttl = network.inventory_ttl.filter(|ttl| *ttl > 0).map(Duration::from_secs);
+    let cache_key = InventoryCache::key(&network.name, inventory_data, &network_env.iter().collect());
+
+    let cached = match ttl {
+        Some(ttl) if !refresh => cache.load(&cache_key, ttl),
+        _ => None,
+    };
+
+    let stdout = match cached {
+        Some(stdout) => stdout,
+        None => {
+            let timeout = Duration::from_secs(network.inventory_timeout.unwrap_or(DEFAULT_INVENTORY_TIMEOUT_SECS));
+            l(&format!("running inventory for {} with {}s timeout", network.name, timeout.as_secs()));
+            let output = run_sh_with_timeout(inventory_data, &network_env, timeout)
+                .map_err(|source| InventoryError::Run { network: network.name.clone(), source })?;
+
+            if !output.status.success() {
+                return Err(InventoryError::Failed {
+                    network: network.name.clone(),
+                    status: output.status,
+                    trace_id: "679E60E9-4F30-4129-884C-AE13FFE66306",
+                });
+            }
 
-    if !output.status.success() {
-        return Err(io::Error::new(
-            io::ErrorKind::Other,
-            format!("Command failed with status: {}", output.status),
-        ));
-    }
+            if ttl.is_some() {
+                if let Err(e) = cache.store(&cache_key, &output.stdout) {
+                    l(&format!("inventory cache: failed to store {}: {}", cache_key, e));
+                }
+            }
+            output.stdout
+        }
+    };
 
-    let output_str = String::from_utf8_lossy(&output.stdout);
+    let output_str = String::from_utf8_lossy(&stdout);
     let hosts = output_str
         .lines()
         .filter(|line| !line.trim().is_empty())
-        .map(|line| check_hosts_form(line))
+        .map(check_hosts_form)
         .collect();
     Ok(hosts)
 }
@@ -102,10 +146,13 @@ pub fn check_hosts_form(host: &str) -> HostEntry {
             pass: None,
             tube: None,
             env: None,
-            sudo: false
+            sudo: false,
+            tags: Vec::new(),
         };
-    
-    l( format!("host as string: {}", host).as_str());
+
+    let (host, tags) = split_tags(host);
+    host_details.tags = tags;
+    l( format!("host as string: {}", redact_host_string(host)).as_str());
 
     let password_start = find_password_start(host); // can be optional
     let mut password_end = find_password_end(host); // can be optional
@@ -132,59 +179,56 @@ tube_name_end: {:?}"#, password_start, password_end, tube_name_start, tube_name_
 
 
 
+    // the find_* helpers return where a separator starts, the part itself follows it
     if let Some(pass_start) = password_start {
         l("CheckHostsForm: password found");
-        // let new_host_start = pass_start - PASS_SEPARATOR.len();
-        new_host = host[..].to_string();
+        new_host = host[..pass_start].to_string();
 
         if tube_name_start != None {
             l("CheckHostsForm: password > tube name found");
-            password_end = Some(tube_name_start.unwrap() - TUBE_NAME_SEPARATOR.len());
+            password_end = tube_name_start;
         } else {
             l("CheckHostsForm: password > no tube");
             password_end = Some(host.len());
         }
 
         l("CheckHostsForm: done checking pass");
-        password = host[pass_start..password_end.unwrap()].to_string();
+        password = host[pass_start + PASS_SEPARATOR.len()..password_end.unwrap()].to_string();
     }
 
     if let Some(tube_name_start_pos) = tube_name_start {
 
         l("CheckHostsForm: tube found");
         if new_host.is_empty() {
-            new_host = host[..tube_name_start_pos - TUBE_NAME_SEPARATOR.len()].to_string();
+            new_host = host[..tube_name_start_pos].to_string();
         }
-        tube = host[tube_name_start_pos..tube_name_end.unwrap()].to_string();
+        tube = host[tube_name_start_pos + TUBE_NAME_SEPARATOR.len()..tube_name_end.unwrap()].to_string();
     }
 
     host_details.host = new_host;
-    host_details.pass = Some(password.to_string());
+    // `$(cmd)`, `env:` and `file:` passwords are resolved later by usecase::secret_resolver
+    host_details.pass = Some(Secret::new(password));
     host_details.tube = Some(tube.to_string());
 
-    if is_shell(&host_details.pass.clone().unwrap().as_ref()) {
-        match resolve_shell(&host_details.pass.clone().unwrap()) {
-            Ok(pass) => {
-                host_details.pass = Some(pass);
-            }
-            Err(e) => {
-                l(format!("CheckHostsForm: 765212F7-64B0-4974-9A50-E8B8C1807FFE: resolving password via shell, error: {}", e).as_str());
-            }
-        }
-    }
-
     l(format!("CheckHostsForm: dump: 3DB74440-E5D9-4BEE-89D8-9C4EEB1459A9, {:?} ", host_details).as_str());
     l("CheckHostsForm: finished checking nets ");
-    let result = HostEntry::Detailed(host_details);
+    let result = HostEntry::Detailed(Box::new(host_details));
     result
 }
 
 
-fn is_shell(cmd: &str) -> bool {
+pub fn is_shell(cmd: &str) -> bool {
     cmd.starts_with("$(") && cmd.ends_with(')')
 }
 
-pub fn resolve_shell(value: &str) -> Result<String, ShellResolveError> {
+pub fn resolve_shell(value: &str, policy: OutputPolicy) -> Result<String, ShellResolveError> {
+    resolve_shell_with_env(value, &EnvList::new(), policy)
+}
+
+/// Like `resolve_shell`, with `env` exported to the command on top of ours.
+/// The command is killed after `DEFAULT_SHELL_VALUE_TIMEOUT_SECS`, and a
+/// non-zero exit status is an error rather than an empty value.
+pub fn resolve_shell_with_env(value: &str, env: &EnvList, policy: OutputPolicy) -> Result<String, ShellResolveError> {
     // remove the prefix and suffix
     let value = value
         .strip_prefix("$(")
@@ -194,17 +238,25 @@ pub fn resolve_shell(value: &str) -> Result<String, ShellResolveError> {
 
     l(format!("to run command {:?}", value).as_str());
     
-    let output = Command::new("sh")
-        .arg("-c")
-        .arg(value)
-        .stderr(io::stderr())
-        .output()
-        .map_err(|e| ShellResolveError::command_error(value, e))?;
-
-    // limit value to only printable characters
-    let clean = filter_non_printable(&output.stdout);
-    l(format!("cmd dump: {}\nvalue: {}", format_output(&output), clean).as_str());
-    Ok(clean)
+    let timeout = Duration::from_secs(DEFAULT_SHELL_VALUE_TIMEOUT_SECS);
+    let output = run_sh_with_timeout(value, env, timeout).map_err(|source| ShellResolveError::CommandExecution {
+        cmd: value.to_string(),
+        source,
+    })?;
+    l(format!("cmd status: {}", output.status).as_str());
+    if !output.status.success() {
+        return Err(ShellResolveError::CommandFailed {
+            cmd: value.to_string(),
+            status: output.status,
+            trace_id: "6928F3B4-0D17-45FB-9633-DABA63E163A1",
+        });
+    }
+
+    apply_output_policy(output.stdout, policy).map_err(|source| ShellResolveError::InvalidUtf8 {
+        cmd: value.to_string(),
+        source,
+        trace_id: "CE16720F-D992-4EA3-9E68-3F1A740A66C1",
+    })
 }
 
 
@@ -212,35 +264,160 @@ pub fn resolve_shell(value: &str) -> Result<String, ShellResolveError> {
 
 
 
-fn filter_non_printable(input: &[u8]) -> String {
-    let mut filtered = String::new();
-    for byte in input {
-        if byte.is_ascii_graphic() || byte.is_ascii_whitespace() {
-            filtered.push(*byte as char);
+/// Decodes command or file output as UTF-8 and applies `policy` to it.
+pub fn apply_output_policy(output: Vec<u8>, policy: OutputPolicy) -> Result<String, FromUtf8Error> {
+    let decoded = String::from_utf8(output)?;
+    let trimmed = |s: &str| {
+        let s = s.strip_suffix('\n').unwrap_or(s);
+        s.strip_suffix('\r').unwrap_or(s).to_string()
+    };
+    Ok(match policy {
+        OutputPolicy::Raw => decoded,
+        OutputPolicy::Trim => trimmed(&decoded),
+        OutputPolicy::Strip => {
+            let printable: String = decoded.chars().filter(|c| !c.is_control() || c.is_whitespace()).collect();
+            trimmed(&printable)
         }
-    }
-    filtered
+    })
 }
 
 
 
 
 
-// Helper function to format command output
-fn format_output(output: &Output) -> impl fmt::Display + '_ {
-    struct OutputFormatter<'a>(&'a Output);
-    
-    impl<'a> fmt::Display for OutputFormatter<'a> {
-        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-            write!(
-                f,
-                "status: {}, stdout: {:?}, stderr: {:?}",
-                self.0.status,
-                String::from_utf8_lossy(&self.0.stdout),
-                String::from_utf8_lossy(&self.0.stderr)
-            )
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn inventory_network(name: &str, script: &str) -> Network {
+        Network {
+            hosts: vec![],
+            env: None,
+            inventory: Some(script.to_string()),
+            inventory_ttl: None,
+            inventory_timeout: None,
+            inventory_file: None,
+            inventory_format: None,
+            extends: None,
+            protected: None,
+            bastion: None,
+            user: None,
+            pass: None,
+            id_file: None,
+            name: name.to_string(),
         }
     }
-    
-    OutputFormatter(output)
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ssup-parse-inventory-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn host_names(hosts: &[HostEntry]) -> Vec<String> {
+        hosts.iter().map(|h| match h {
+            HostEntry::Detailed(d) => d.host.clone(),
+            HostEntry::Simple(s) => s.clone(),
+        }).collect()
+    }
+
+    #[test]
+    fn test_inventory_lines_carry_tags() {
+        let network = inventory_network("tagged", "echo 'root@10.0.0.1 [role=web, az=b]'; echo root@10.0.0.2");
+        let cache = InventoryCache::new(temp_dir("tagged"));
+        let hosts = parse_inventory_with_cache(&network, false, &cache).unwrap();
+        assert_eq!(host_names(&hosts), vec!["root@10.0.0.1", "root@10.0.0.2"]);
+        match &hosts[0] {
+            HostEntry::Detailed(details) => assert_eq!(details.tags, vec!["role=web", "az=b"]),
+            HostEntry::Simple(_) => panic!("inventory hosts are always detailed"),
+        }
+    }
+
+    #[test]
+    fn test_host_string_parts_are_split_apart() {
+        let details = match check_hosts_form("root@10.0.0.1 | hunter2 << web") {
+            HostEntry::Detailed(details) => details,
+            HostEntry::Simple(_) => unreachable!(),
+        };
+        assert_eq!(details.host, "root@10.0.0.1");
+        assert_eq!(details.pass.as_ref().map(|pass| pass.expose()), Some("hunter2"));
+        assert_eq!(details.tube.as_deref(), Some("web"));
+
+        let details = match check_hosts_form("root@10.0.0.2 | hunter2") {
+            HostEntry::Detailed(details) => details,
+            HostEntry::Simple(_) => unreachable!(),
+        };
+        assert_eq!(details.host, "root@10.0.0.2");
+        assert_eq!(details.pass.as_ref().map(|pass| pass.expose()), Some("hunter2"));
+    }
+
+    #[test]
+    fn test_no_inventory_runs_nothing() {
+        let mut network = inventory_network("empty", "");
+        network.inventory = None;
+        let cache = InventoryCache::new(temp_dir("none"));
+        assert!(parse_inventory_with_cache(&network, false, &cache).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_ttl_serves_cached_hosts_until_refresh() {
+        let dir = temp_dir("ttl");
+        let counter = dir.join("runs");
+        let script = format!("echo run >> {0}; echo host$(wc -l < {0} | tr -d ' ')", counter.display());
+        let mut network = inventory_network("cached", &script);
+        network.inventory_ttl = Some(300);
+        let cache = InventoryCache::new(dir.join("cache"));
+
+        let first = parse_inventory_with_cache(&network, false, &cache).unwrap();
+        let second = parse_inventory_with_cache(&network, false, &cache).unwrap();
+        assert_eq!(host_names(&first), vec!["host1"]);
+        assert_eq!(host_names(&second), vec!["host1"]);
+
+        let refreshed = parse_inventory_with_cache(&network, true, &cache).unwrap();
+        assert_eq!(host_names(&refreshed), vec!["host2"]);
+    }
+
+    #[test]
+    fn test_hanging_inventory_times_out() {
+        let mut network = inventory_network("slow", "sleep 10; echo late");
+        network.inventory_timeout = Some(0);
+        let cache = InventoryCache::new(temp_dir("slow"));
+        let err = parse_inventory_with_cache(&network, false, &cache).unwrap_err();
+        assert!(matches!(err, InventoryError::Run { source: ShellRunError::Timeout { .. }, .. }));
+        assert!(err.to_string().contains("network 'slow'"));
+    }
+
+    #[test]
+    fn test_resolve_shell_fails_on_exit_status() {
+        assert_eq!(resolve_shell("$(printf secret)", OutputPolicy::Trim).unwrap(), "secret");
+        assert!(matches!(resolve_shell("$(exit 4)", OutputPolicy::Trim), Err(ShellResolveError::CommandFailed { .. })));
+        assert!(matches!(resolve_shell("secret", OutputPolicy::Trim), Err(ShellResolveError::MissingPrefix)));
+    }
+
+    #[test]
+    fn test_output_policies_keep_utf8() {
+        let output = "пароль\u{7}  ünïcode\n\n".as_bytes().to_vec();
+        assert_eq!(apply_output_policy(output.clone(), OutputPolicy::Raw).unwrap(), "пароль\u{7}  ünïcode\n\n");
+        assert_eq!(apply_output_policy(output.clone(), OutputPolicy::Trim).unwrap(), "пароль\u{7}  ünïcode\n");
+        assert_eq!(apply_output_policy(output, OutputPolicy::Strip).unwrap(), "пароль  ünïcode\n");
+        assert_eq!(apply_output_policy(b"line\r\n".to_vec(), OutputPolicy::Trim).unwrap(), "line");
+
+        assert_eq!(resolve_shell("$(printf 'секрет\\n')", OutputPolicy::Trim).unwrap(), "секрет");
+        assert!(matches!(
+            resolve_shell("$(printf '\\377\\376')", OutputPolicy::Trim),
+            Err(ShellResolveError::InvalidUtf8 { .. })
+        ));
+    }
+
+    #[test]
+    fn test_failing_inventory_is_an_error() {
+        let network = inventory_network("broken", "exit 3");
+        let cache = InventoryCache::new(temp_dir("broken"));
+        assert!(matches!(
+            parse_inventory_with_cache(&network, false, &cache),
+            Err(InventoryError::Failed { .. })
+        ));
+    }
 }
@@ -0,0 +1,167 @@
+use crate::entity::env::EnvList;
+use crate::entity::playbook::PlayBook;
+use crate::entity::supfile::commands::RequiredEnv;
+use crate::gateways::logger::logger_func as l;
+use crate::usecase::error::SsupError;
+use indexmap::IndexMap;
+use std::fmt;
+
+/// Why a `requires_env` entry is not satisfied.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum EnvProblem {
+    /// unset or empty
+    Missing,
+    /// set, but not matching the whole pattern
+    Mismatch { pattern: String },
+}
+
+/// One unsatisfied requirement, with every `network/host` it applies to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnvViolation {
+    pub command: String,
+    pub name: String,
+    pub problem: EnvProblem,
+    pub targets: Vec<String>,
+}
+
+impl fmt::Display for EnvViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.problem {
+            EnvProblem::Missing => write!(f, "{}: {} is not set", self.command, self.name)?,
+            EnvProblem::Mismatch { pattern } => write!(f, "{}: {} does not match '{}'", self.command, self.name, pattern)?,
+        }
+        write!(f, " (on {})", self.targets.join(", "))
+    }
+}
+
+/// Checks every command's `requires_env` against the env it gets on each
+/// host of its play, as `env_resolver::evaluate_playbook_env` left it.
+/// Everything that fails is returned at once, one entry per command,
+/// variable and problem.
+pub fn check_required_env(playbook: &PlayBook) -> Vec<EnvViolation> {
+    let mut violations: IndexMap<(String, String, EnvProblem), Vec<String>> = IndexMap::new();
+
+    for play in playbook.plays.iter() {
+        for command_env in play.envs.iter() {
+            let command = &play.commands[command_env.command];
+            if command.requires_env.is_empty() {
+                continue;
+            }
+            for (required, problem) in unsatisfied(&command.requires_env, &command_env.env) {
+                violations
+                    .entry((command.name.clone(), required.name.clone(), problem))
+                    .or_default()
+                    .push(command_env.target.clone());
+            }
+        }
+    }
+
+    violations
+        .into_iter()
+        .map(|((command, name, problem), targets)| EnvViolation { command, name, problem, targets })
+        .collect()
+}
+
+fn unsatisfied<'a>(required: &'a [RequiredEnv], env: &EnvList) -> Vec<(&'a RequiredEnv, EnvProblem)> {
+    required
+        .iter()
+        .filter_map(|required| match env.get(&required.name) {
+            None | Some("") => Some((required, EnvProblem::Missing)),
+            Some(value) => match (required.regex(), &required.pattern) {
+                (Some(regex), Some(pattern)) if !regex.is_match(value) => {
+                    Some((required, EnvProblem::Mismatch { pattern: pattern.clone() }))
+                }
+                _ => None,
+            },
+        })
+        .collect()
+}
+
+/// Runs [`check_required_env`] before any host is contacted; the error
+/// lists every unsatisfied requirement.
+pub fn ensure_required_env(playbook: &PlayBook) -> Result<(), SsupError> {
+    l("check requires_env of every command");
+    let violations = check_required_env(playbook);
+    if violations.is_empty() {
+        return Ok(());
+    }
+    Err(SsupError::RequiredEnv { violations, trace_id: "001346BD-FB6E-42C0-B36D-3D246F833575" })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::usecase::env_resolver::evaluate_playbook_env;
+    use crate::usecase::modes_of_operation::test_support::init_state;
+    use crate::usecase::program_init::parse_initial_args;
+
+    const SUPFILE: &str = r#"
+version: 0.5
+env:
+  APP: shop
+networks:
+  prod:
+    env:
+      RELEASE: v1
+    hosts:
+    - root@10.0.0.1
+    - host: root@10.0.0.2
+      env:
+        DB_URL: postgres://db2
+commands:
+  deploy:
+    run: ./deploy
+    requires_env:
+    - DB_URL
+    - TOKEN
+    - RELEASE: 'v[0-9]+'
+    - APP
+  migrate:
+    run: ./migrate
+    env:
+      RELEASE: ${APP}-next
+    requires_env:
+    - RELEASE: 'v[0-9]+'
+"#;
+
+    fn violations(cli: &[&str]) -> Vec<String> {
+        let mut state = init_state(SUPFILE, cli);
+        let mut playbook = parse_initial_args(&mut state).unwrap();
+        evaluate_playbook_env(&mut playbook).unwrap();
+        check_required_env(&playbook).iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn test_all_missing_variables_are_listed() {
+        assert_eq!(
+            violations(&["prod", "deploy", "migrate"]),
+            vec![
+                "deploy: DB_URL is not set (on prod/10.0.0.1)",
+                "deploy: TOKEN is not set (on prod/10.0.0.1, prod/10.0.0.2)",
+                "migrate: RELEASE does not match 'v[0-9]+' (on prod/10.0.0.1, prod/10.0.0.2)",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cli_env_satisfies_requirements() {
+        assert!(violations(&["-e", "DB_URL=postgres://db", "-e", "TOKEN=t", "prod", "deploy"]).is_empty());
+        assert_eq!(
+            violations(&["-e", "DB_URL=x TOKEN=t RELEASE=latest", "prod", "deploy"]),
+            vec!["deploy: RELEASE does not match 'v[0-9]+' (on prod/10.0.0.1, prod/10.0.0.2)"]
+        );
+    }
+
+    #[test]
+    fn test_checks_the_exported_env() {
+        let yaml = "version: 0.5\nenv:\n  STAGE: dev\nnetworks:\n  prod:\n    hosts:\n    - host: root@10.0.0.1\n      env:\n        STAGE: canary\ncommands:\n  deploy:\n    run: ./deploy\n    env:\n      STAGE: live\n    requires_env:\n    - STAGE: prod\n    - SUP_HOST: '10[.]0[.]0[.]1'\n";
+        let check = |args: &[&str]| {
+            let mut state = init_state(yaml, args);
+            let mut playbook = parse_initial_args(&mut state).unwrap();
+            evaluate_playbook_env(&mut playbook).unwrap();
+            check_required_env(&playbook).iter().map(|v| v.to_string()).collect::<Vec<_>>()
+        };
+        assert_eq!(check(&["prod", "deploy"]), vec!["deploy: STAGE does not match 'prod' (on prod/10.0.0.1)"]);
+        assert!(check(&["-e", "STAGE=prod", "prod", "deploy"]).is_empty());
+    }
+}
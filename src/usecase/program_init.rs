@@ -3,18 +3,53 @@ use crate::entity::help_displayer::HelpDisplayer;
 use crate::entity::CommandLineArgs;
 use std::env;
 use std::fs;
-use std::path::PathBuf;
 use log::info;
 use crate::entity::{InitState, playbook::PlayBook};
 use crate::usecase::modes_of_operation::{special_target_mode::special_target_mode, normal_mode::normal_mode, makefile_mode::makefile_mode};
 use crate::gateways::logger::logger_func as l;
+use crate::usecase::inventory_file::load_inventory_files;
+use crate::usecase::error::SsupError;
+use crate::usecase::env_parser::env_from_args;
+use crate::usecase::env_resolver::resolve_playbook_env;
+use crate::usecase::diagnostics::{check_references, render_yaml_error};
+
+pub fn parse_supfile(args: CommandLineArgs) -> Result<supfile::Supfile, SsupError> {
+    let (shown_path, content) = read_supfile(&args)?;
+    supfile_from_str(&shown_path, &content)
+}
+
+/// Parses and validates the Supfile read from `shown_path`, then loads its
+/// inventory files.
+pub fn supfile_from_str(shown_path: &str, content: &str) -> Result<supfile::Supfile, SsupError> {
+    let mut supfile: supfile::Supfile = serde_yaml::from_str(content).map_err(|source| SsupError::ParseSupfile {
+        diagnostic: render_yaml_error(shown_path, content, &source),
+        path: shown_path.to_string(),
+        source,
+    })?;
+
+    let problems = check_references(shown_path, content, &supfile);
+    if !problems.is_empty() {
+        return Err(SsupError::UnknownReferences {
+            path: shown_path.to_string(),
+            problems,
+            trace_id: "DB0FAACD-3A80-4F1D-8FB0-0443D26F4A39",
+        });
+    }
+
+    load_inventory_files(&mut supfile.networks)?;
 
-pub fn parse_supfile(args: CommandLineArgs) -> supfile::Supfile {
+    Ok(supfile)
+
+}
+
+/// Finds the Supfile (`-f`, else `Supfile.yml`, else `Supfile`), reads it and
+/// changes to its directory, so relative paths in it resolve from there.
+/// Returns the path as given and the content.
+pub fn read_supfile(args: &CommandLineArgs) -> Result<(String, String), SsupError> {
     let mut file_to_read: std::path::PathBuf;
-    let start_dir = env::current_dir().expect("failed to get current dir");
-    l(&format!("supfile parsing started in folder: {:?}", start_dir));
+    l(&format!("supfile parsing started in folder: {:?}", env::current_dir()));
 
-    if args.file == "" {
+    if args.file.is_empty() {
         file_to_read = std::path::PathBuf::from(".".to_string());
         file_to_read.push("Supfile.yml");
     } else {
@@ -26,24 +61,22 @@ pub fn parse_supfile(args: CommandLineArgs) -> supfile::Supfile {
     let content = std::fs::read_to_string(file_to_read.clone());
     if let Err(error) = content {
         // // exit to os with non-zero status
-        println!("Could not open file: {}", file_to_read.display());
-        println!("reason: {}", error);
-        println!("Using default Supfile");
+        eprintln!("Could not open file: {}", file_to_read.display());
+        eprintln!("reason: {}", error);
+        eprintln!("Using default Supfile");
         file_to_read = std::path::PathBuf::from(".".to_string());
         file_to_read.push("Supfile");
     }
     
-    let content = std::fs::read_to_string(file_to_read.clone());
-    if let Err(error) = content {
-        // exit to os with non-zero status
-        println!("Could not open file: {}", file_to_read.display());
-        println!("reason: {}", error);
-        std::process::exit(1);
-    }
-    let content = content.unwrap();
-    let full_path = get_full_path(file_to_read.clone());
-    let full_path = std::path::PathBuf::from(full_path);
-    let basedir = full_path.parent().expect("directory probably doesnt exist");
+    let content = std::fs::read_to_string(file_to_read.clone()).map_err(|source| SsupError::ReadSupfile {
+        path: file_to_read.display().to_string(),
+        source,
+    })?;
+    let full_path = fs::canonicalize(&file_to_read).map_err(|source| SsupError::WorkingDir {
+        path: file_to_read.display().to_string(),
+        source,
+    })?;
+    let basedir = full_path.parent().unwrap_or(&full_path);
     
     // println!("start_dir is {:?}", start_dir);
     // println!("basedir is {:?}", basedir);
@@ -52,38 +85,41 @@ pub fn parse_supfile(args: CommandLineArgs) -> supfile::Supfile {
     if !content.is_empty() {
         // println!("cd to supfile dir: {}", basedir.to_str().unwrap());
 
-        if let Err(e) = env::set_current_dir(basedir) {
-            eprintln!("failed to cd to new Wd: {}", e);
-            std::process::exit(1);
-        }
+        env::set_current_dir(basedir).map_err(|source| SsupError::WorkingDir {
+            path: basedir.display().to_string(),
+            source,
+        })?;
 
         // println!("cd done");
     }
 
-    return serde_yaml::from_str(&content).expect(format!("could not parse file: {}", file_to_read.display()).as_str());
-
+    Ok((file_to_read.display().to_string(), content))
 }
 
-
-fn get_full_path(relative_path: PathBuf) -> String {
-    match fs::canonicalize(relative_path) {
-        Ok(path) => path.to_string_lossy().into_owned(),
-        Err(_) => String::from("Error resolving path"),
-    }
-}
-
-fn usage_on_no_args(init_data: InitState) {
+pub fn usage_on_no_args(init_data: InitState) -> Result<(), SsupError> {
     let mut help_menu = HelpDisplayer::new(init_data.clone());
-    if init_data.args.len() < 1 {
+    if init_data.args.is_empty() {
         help_menu.show_all(&init_data);
         let message = "Usage: ssup [OPTIONS] NETWORK COMMAND [...]\n       ssup [ --help | -v | --version ]";
-        println!("{}",message);
-        std::process::exit(2)
+        return Err(SsupError::Usage { message: message.to_string() });
     }
+    Ok(())
 }
 
 
-pub fn parse_initial_args(init_data: &mut InitState) -> PlayBook {
+/// Builds the playbook in the mode the arguments call for, then resolves
+/// the env of every command on every host. With networks in the Supfile,
+/// arguments that are all targets run in special target mode, anything else
+/// in normal mode; without networks, everything runs on localhost in
+/// makefile mode.
+pub fn parse_initial_args(init_data: &mut InitState) -> Result<PlayBook, SsupError> {
+    let mut playbook = playbook_for_mode(init_data)?;
+    let cli = env_from_args(init_data)?;
+    resolve_playbook_env(init_data, &mut playbook, &cli);
+    Ok(playbook)
+}
+
+fn playbook_for_mode(init_data: &mut InitState) -> Result<PlayBook, SsupError> {
     let conf = init_data.supfile.clone();
     let args = &init_data.args;
     let args_count = args.len();
@@ -91,21 +127,21 @@ pub fn parse_initial_args(init_data: &mut InitState) -> PlayBook {
 
     info!("Checking if we have any args at all, len: {}", args_count);
 
-    if conf.networks.is_empty() {
-        if all_args_are_targets(&init_data, &mut help_menu) {
+    if !conf.networks.is_empty() {
+        if all_args_are_targets(init_data) {
             info!("Special target mode");
-            return special_target_mode(&init_data, &mut help_menu);
+            return special_target_mode(init_data, &mut help_menu);
         }
-        
+
         info!("Normal mode");
-        return normal_mode(&init_data, &help_menu);
+        return normal_mode(init_data, &help_menu);
     }
 
     info!("Makefile mode");
     makefile_mode(init_data, &mut help_menu)
 }
 
-fn all_args_are_targets(init_data: &InitState, help_menu: &mut HelpDisplayer) -> bool {
+fn all_args_are_targets(init_data: &InitState) -> bool {
     let conf = &init_data.supfile;
     let args = &init_data.args;
     let mut no_missing_names = true;
@@ -118,9 +154,70 @@ fn all_args_are_targets(init_data: &InitState, help_menu: &mut HelpDisplayer) ->
         if !targets.has(single_argument) {
             info!("Targets check -> unknown keyword: {}", single_argument);
             no_missing_names = false;
-            help_menu.show_all(&init_data);
         }
     }
     
     no_missing_names
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::usecase::modes_of_operation::test_support::init_state;
+
+    const SUPFILE: &str = r#"
+version: 0.5
+networks:
+  web:
+    hosts: [root@10.0.0.1]
+  db:
+    hosts: [root@10.0.1.1]
+commands:
+  deploy:
+    run: ./deploy
+  migrate:
+    run: ./migrate
+targets:
+  release:
+  - migrate db
+  - deploy web
+"#;
+
+    fn plan(yaml: &str, args: &[&str]) -> (bool, Vec<(String, String)>) {
+        let mut state = init_state(yaml, args);
+        let playbook = parse_initial_args(&mut state).unwrap();
+        let steps = playbook
+            .plays
+            .iter()
+            .flat_map(|play| play.commands.iter().map(move |command| (play.network_name(), command.name.clone())))
+            .collect();
+        (playbook.is_makefile_mode(), steps)
+    }
+
+    fn steps(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(network, command)| (network.to_string(), command.to_string())).collect()
+    }
+
+    #[test]
+    fn test_mode_follows_networks_and_arguments() {
+        // only targets: each step runs on the network it names
+        assert_eq!(plan(SUPFILE, &["release"]), (false, steps(&[("db", "migrate"), ("web", "deploy")])));
+        // a network first: normal mode
+        assert_eq!(plan(SUPFILE, &["web", "deploy", "migrate"]), (false, steps(&[("web", "deploy"), ("web", "migrate")])));
+        // no networks: makefile mode on localhost
+        let local = "version: 0.5\ncommands:\n  build:\n    run: make\n";
+        assert_eq!(plan(local, &["build"]), (true, steps(&[("localhost", "build")])));
+    }
+
+    #[test]
+    fn test_every_command_gets_a_resolved_env() {
+        let mut state = init_state(SUPFILE, &["-e", "A=b", "web,db", "deploy", "migrate"]);
+        let playbook = parse_initial_args(&mut state).unwrap();
+        for play in &playbook.plays {
+            let targets: Vec<(usize, &str)> = play.envs.iter().map(|env| (env.command, env.target.as_str())).collect();
+            let host = if play.network_name() == "web" { "web/10.0.0.1" } else { "db/10.0.1.1" };
+            assert_eq!(targets, vec![(0, host), (1, host)]);
+            assert!(play.envs.iter().all(|env| env.env["A"] == "b"));
+        }
+    }
+}
@@ -0,0 +1,255 @@
+use crate::entity::playbook::PlayBook;
+use crate::entity::CommandLineArgs;
+use crate::gateways::logger::logger_func as l;
+use crate::gateways::terminal;
+use crate::usecase::error::SsupError;
+use std::io;
+
+/// Asks a question and returns the answer; `true` hides what is typed.
+type Ask<'a> = &'a mut dyn FnMut(&str, bool) -> io::Result<String>;
+
+/// A `prompt:` variable some command of the playbook would get.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Question {
+    key: String,
+    prompt: String,
+    default: String,
+    secret: bool,
+}
+
+/// Asks for every `prompt:` variable before any host is contacted, once per
+/// variable however many hosts and commands get it. Variables given with
+/// `-e` are not asked for; with `--yes` a default is taken without asking.
+/// Without a terminal, every variable that could not be settled is reported.
+pub fn ask_for_env(playbook: &mut PlayBook, flags: &CommandLineArgs) -> Result<(), SsupError> {
+    answer_env(playbook, flags.yes, terminal::is_interactive(), &mut terminal::ask)
+}
+
+fn answer_env(playbook: &mut PlayBook, yes: bool, interactive: bool, ask: Ask) -> Result<(), SsupError> {
+    let mut answers = Vec::new();
+    let mut unanswered = Vec::new();
+    for question in questions(playbook) {
+        if yes && !question.default.is_empty() {
+            l(&format!("--yes: {} takes its default", question.key));
+            answers.push((question.key, question.default));
+        } else if interactive {
+            let shown = if question.default.is_empty() || question.secret {
+                format!("{} ", question.prompt)
+            } else {
+                format!("{} [{}] ", question.prompt, question.default)
+            };
+            let answer = ask(&shown, question.secret).map_err(|e| SsupError::Interactive {
+                message: format!("could not read {}: {}", question.key, e),
+                trace_id: "C3B1107F-0358-4EE3-A9A5-57DAD3C27570",
+            })?;
+            answers.push((question.key, if answer.is_empty() { question.default } else { answer }));
+        } else {
+            unanswered.push(format!("{}: {}", question.key, question.prompt));
+        }
+    }
+    if !unanswered.is_empty() {
+        return Err(SsupError::UnansweredPrompts {
+            questions: unanswered,
+            trace_id: "860C3E69-E4E8-41D1-9BBA-F3338A88754E",
+        });
+    }
+
+    for (key, value) in answers {
+        for command_env in playbook.plays.iter_mut().flat_map(|play| play.envs.iter_mut()) {
+            if command_env.env.prompt(&key).is_some() {
+                command_env.env.set(key.clone(), value.clone());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The `prompt:` variables still to be asked for, in the order the
+/// commands would see them. A variable is asked for where the layer that
+/// sets it has a `prompt:`, so a value from a higher layer or `-e` settles it.
+fn questions(playbook: &PlayBook) -> Vec<Question> {
+    let mut questions: Vec<Question> = Vec::new();
+    for env in playbook.plays.iter().flat_map(|play| &play.envs).map(|command_env| &command_env.env) {
+        for (key, value) in env.iter() {
+            let Some(prompt) = env.prompt(key) else {
+                continue;
+            };
+            if questions.iter().any(|question| &question.key == key) {
+                continue;
+            }
+            questions.push(Question {
+                key: key.clone(),
+                prompt: prompt.to_string(),
+                default: value.clone(),
+                secret: env.is_secret(key),
+            });
+        }
+    }
+    questions
+}
+
+/// Asks for every confirmation the playbook needs before any host is
+/// contacted: each command's `confirm:`, and one for each `protected`
+/// network. `--yes` confirms everything; without a terminal and `--yes`
+/// nothing runs.
+pub fn confirm_run(playbook: &PlayBook, flags: &CommandLineArgs) -> Result<(), SsupError> {
+    confirm(playbook, flags.yes, terminal::is_interactive(), &mut terminal::ask)
+}
+
+fn confirm(playbook: &PlayBook, yes: bool, interactive: bool, ask: Ask) -> Result<(), SsupError> {
+    let questions = confirmations(playbook);
+    if questions.is_empty() {
+        return Ok(());
+    }
+    if yes {
+        questions.iter().for_each(|question| l(&format!("--yes: {}", question)));
+        return Ok(());
+    }
+    if !interactive {
+        return Err(SsupError::UnconfirmedRun {
+            questions,
+            trace_id: "2AB9A93E-9CAD-49B6-90B9-E3C6DA0F20D3",
+        });
+    }
+    for question in questions {
+        let answer = ask(&format!("{} [y/N] ", question), false).map_err(|e| SsupError::Interactive {
+            message: format!("could not read the confirmation: {}", e),
+            trace_id: "C3B1107F-0358-4EE3-A9A5-57DAD3C27570",
+        })?;
+        if !matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") {
+            return Err(SsupError::Declined {
+                question,
+                trace_id: "1224E847-206E-4340-A82F-810D0F4256E2",
+            });
+        }
+    }
+    Ok(())
+}
+
+/// The confirmations, protected networks first, each asked once.
+fn confirmations(playbook: &PlayBook) -> Vec<String> {
+    let mut questions: Vec<String> = Vec::new();
+    let mut push = |question: String| {
+        if !questions.contains(&question) {
+            questions.push(question);
+        }
+    };
+    for (network, plays) in playbook.plays_by_network() {
+        if plays.iter().any(|play| play.nets.as_ref().is_some_and(|net| net.is_protected())) {
+            let commands: Vec<&str> = plays.iter().flat_map(|play| &play.commands).map(|command| command.name.as_str()).collect();
+            push(format!("Run {} on protected network {}?", commands.join(", "), network));
+        }
+    }
+    for play in &playbook.plays {
+        for command in &play.commands {
+            if let Some(confirm) = &command.confirm {
+                push(confirm.replace("{{network}}", &play.network_name()).replace("{{command}}", &command.name));
+            }
+        }
+    }
+    questions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::env::EnvList;
+    use crate::usecase::modes_of_operation::test_support::init_state;
+    use crate::usecase::program_init::parse_initial_args;
+
+    const SUPFILE: &str = r#"
+version: 0.5
+env:
+  TAG:
+    prompt: Release tag?
+networks:
+  staging:
+    hosts: [root@10.0.0.1, root@10.0.0.2]
+  prod:
+    protected: true
+    env:
+      TOKEN:
+        prompt: Deploy token?
+        secret: true
+    hosts: [root@10.1.0.1]
+commands:
+  deploy:
+    run: ./deploy $TAG
+    env:
+      STAGE:
+        prompt: Stage?
+        value: blue
+  flush:
+    run: redis-cli flushall
+    confirm: Really drop cache on {{network}}?
+"#;
+
+    fn playbook(args: &[&str]) -> PlayBook {
+        let mut state = init_state(SUPFILE, args);
+        parse_initial_args(&mut state).unwrap()
+    }
+
+    fn command_env(playbook: &PlayBook, play: usize) -> EnvList {
+        playbook.plays[play].envs[0].env.clone()
+    }
+
+    #[test]
+    fn test_prompts_are_asked_once() {
+        let mut playbook = playbook(&["staging,prod", "deploy"]);
+        let mut asked = Vec::new();
+        let mut ask = |question: &str, hidden: bool| {
+            asked.push((question.to_string(), hidden));
+            Ok(if question.starts_with("Stage") { String::new() } else { "v1.2.3".to_string() })
+        };
+        answer_env(&mut playbook, false, true, &mut ask).unwrap();
+        assert_eq!(
+            asked,
+            vec![
+                ("Release tag? ".to_string(), false),
+                ("Stage? [blue] ".to_string(), false),
+                ("Deploy token? ".to_string(), true),
+            ]
+        );
+        let env = command_env(&playbook, 1);
+        assert_eq!((env.get("TAG"), env.get("STAGE"), env.get("TOKEN")), (Some("v1.2.3"), Some("blue"), Some("v1.2.3")));
+        assert!(env.is_secret("TOKEN") && env.prompt("TAG").is_none());
+    }
+
+    #[test]
+    fn test_prompts_without_a_terminal() {
+        let mut playbook = playbook(&["-e", "TAG=v2", "staging", "deploy"]);
+        let mut ask = |_: &str, _: bool| -> io::Result<String> { panic!("must not ask") };
+        answer_env(&mut playbook, true, false, &mut ask).unwrap();
+        assert_eq!(command_env(&playbook, 0).get("STAGE"), Some("blue"));
+        assert_eq!(command_env(&playbook, 0).get("TAG"), Some("v2"));
+
+        let mut playbook = self::playbook(&["staging", "deploy"]);
+        let err = answer_env(&mut playbook, true, false, &mut ask).unwrap_err();
+        assert!(matches!(err, SsupError::UnansweredPrompts { ref questions, .. } if questions == &["TAG: Release tag?"]), "{}", err);
+    }
+
+    #[test]
+    fn test_confirmations() {
+        let playbook = playbook(&["staging,prod", "deploy", "flush"]);
+        assert_eq!(
+            confirmations(&playbook),
+            vec![
+                "Run deploy, flush on protected network prod?",
+                "Really drop cache on staging?",
+                "Really drop cache on prod?",
+            ]
+        );
+
+        let mut never = |_: &str, _: bool| -> io::Result<String> { panic!("must not ask") };
+        assert!(confirm(&playbook, true, false, &mut never).is_ok());
+        let err = confirm(&playbook, false, false, &mut never).unwrap_err();
+        assert!(matches!(err, SsupError::UnconfirmedRun { ref questions, .. } if questions.len() == 3));
+
+        let mut answers = vec!["y", "YES", "n"].into_iter();
+        let mut ask = |_: &str, _: bool| Ok(answers.next().unwrap().to_string());
+        let err = confirm(&playbook, false, true, &mut ask).unwrap_err();
+        assert_eq!(err.to_string(), "not confirmed: Really drop cache on prod?");
+
+        assert!(confirmations(&self::playbook(&["staging", "deploy"])).is_empty());
+    }
+}
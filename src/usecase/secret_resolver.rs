@@ -0,0 +1,262 @@
+use crate::entity::env::EnvList;
+use crate::entity::secret::{register_secret, Secret};
+use crate::entity::supfile::networks::{HostEntry, Network};
+use crate::entity::supfile::OutputPolicy;
+use crate::gateways::logger::logger_func as l;
+use crate::usecase::parse_network::{apply_output_policy, is_shell, resolve_shell, ShellResolveError};
+use std::collections::HashMap;
+use std::string::FromUtf8Error;
+use std::sync::{Mutex, OnceLock};
+use std::{env, fs, io};
+use thiserror::Error;
+
+const ENV_PREFIX: &str = "env:";
+const FILE_PREFIX: &str = "file:";
+
+#[derive(Error, Debug)]
+pub enum SecretError {
+    #[error("{field}: {source}")]
+    Shell {
+        field: String,
+        #[source]
+        source: ShellResolveError,
+    },
+    #[error("{field}: environment variable {var} is not set")]
+    MissingEnv {
+        field: String,
+        var: String,
+        trace_id: &'static str,
+    },
+    #[error("{field}: failed to read {path}: {source}")]
+    ReadFile {
+        field: String,
+        path: String,
+        #[source]
+        source: io::Error,
+    },
+    #[error("{field}: {path} is not valid UTF-8: {source}")]
+    InvalidUtf8 {
+        field: String,
+        path: String,
+        #[source]
+        source: FromUtf8Error,
+        trace_id: &'static str,
+    },
+}
+
+impl SecretError {
+    pub fn trace_id(&self) -> &'static str {
+        match self {
+            SecretError::Shell { source, .. } => source.trace_id(),
+            SecretError::ReadFile { .. } => "6ED64A0A-4FC3-4216-9C50-F831D11DCD3C",
+            SecretError::MissingEnv { trace_id, .. } | SecretError::InvalidUtf8 { trace_id, .. } => trace_id,
+        }
+    }
+}
+
+/// Results of this run, keyed by the reference; a `$(cmd)` used by many
+/// hosts only runs once.
+fn cache() -> &'static Mutex<HashMap<String, String>> {
+    static CACHE: OnceLock<Mutex<HashMap<String, String>>> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Resolves a sensitive value. `$(cmd)` is replaced by the command's output,
+/// `env:VAR` by the local environment variable and `file:path` by the file's
+/// contents, the output of both decoded as UTF-8 and cleaned up according to
+/// `policy`. Anything else is returned as is.
+///
+/// `field` names the value in errors, e.g. `network prod: pass`.
+pub fn resolve_value(field: &str, value: &str, policy: OutputPolicy) -> Result<String, SecretError> {
+    let is_reference = is_shell(value) || value.starts_with(ENV_PREFIX) || value.starts_with(FILE_PREFIX);
+    if !is_reference {
+        return Ok(value.to_string());
+    }
+    if let Some(resolved) = cache().lock().unwrap().get(value) {
+        return Ok(resolved.clone());
+    }
+
+    l(&format!("resolving {}", field));
+    let resolved = if is_shell(value) {
+        resolve_shell(value, policy).map_err(|source| SecretError::Shell { field: field.to_string(), source })?
+    } else if let Some(var) = value.strip_prefix(ENV_PREFIX) {
+        env::var(var).map_err(|_| SecretError::MissingEnv {
+            field: field.to_string(),
+            var: var.to_string(),
+            trace_id: "A3E1F7C0-49D2-4B6E-8C15-7F0D2B94E6A8",
+        })?
+    } else {
+        let path = expand_home(value.strip_prefix(FILE_PREFIX).unwrap_or_default());
+        let content = fs::read(&path).map_err(|source| SecretError::ReadFile {
+            field: field.to_string(),
+            path: path.clone(),
+            source,
+        })?;
+        apply_output_policy(content, policy).map_err(|source| SecretError::InvalidUtf8 {
+            field: field.to_string(),
+            path,
+            source,
+            trace_id: "0E5B9D27-C84A-4F31-A6E2-5D9174B3C08F",
+        })?
+    };
+
+    cache().lock().unwrap().insert(value.to_string(), resolved.clone());
+    Ok(resolved)
+}
+
+fn expand_home(path: &str) -> String {
+    match (path.strip_prefix("~/"), env::var("HOME")) {
+        (Some(rest), Ok(home)) => format!("{}/{}", home, rest),
+        _ => path.to_string(),
+    }
+}
+
+fn resolve_option(field: &str, value: &mut Option<String>, policy: OutputPolicy) -> Result<(), SecretError> {
+    if let Some(current) = value {
+        *current = resolve_value(field, current, policy)?;
+    }
+    Ok(())
+}
+
+/// Resolves a password and registers the result for masking.
+fn resolve_secret(field: &str, value: &mut Option<Secret>, policy: OutputPolicy) -> Result<(), SecretError> {
+    if let Some(current) = value {
+        let resolved = resolve_value(field, current.expose(), policy)?;
+        register_secret(&resolved);
+        *current = Secret::new(resolved);
+    }
+    Ok(())
+}
+
+/// Resolves every value of `env` in place. Raw values are meant for the
+/// remote shell and are left alone. Values of secret keys are registered
+/// for masking.
+pub fn resolve_env_values(owner: &str, env: &mut EnvList, policy: OutputPolicy) -> Result<(), SecretError> {
+    let keys: Vec<String> = env.keys().cloned().collect();
+    for key in keys {
+        let value = env.get(&key).unwrap_or_default().to_string();
+        let resolved = if env.is_raw(&key) {
+            value.clone()
+        } else {
+            resolve_value(&format!("{}: env {}", owner, key), &value, policy)?
+        };
+        if env.is_secret(&key) {
+            register_secret(&resolved);
+        }
+        if resolved != value {
+            env.set(key, resolved);
+        }
+    }
+    Ok(())
+}
+
+/// Resolves `pass`, `id_file`, `bastion` and `env` of the network and of its
+/// detailed hosts.
+pub fn resolve_network_secrets(network: &mut Network, policy: OutputPolicy) -> Result<(), SecretError> {
+    let owner = format!("network {}", network.name);
+    resolve_secret(&format!("{}: pass", owner), &mut network.pass, policy)?;
+    resolve_option(&format!("{}: id_file", owner), &mut network.id_file, policy)?;
+    resolve_option(&format!("{}: bastion", owner), &mut network.bastion, policy)?;
+    if let Some(env) = network.env.as_mut() {
+        resolve_env_values(&owner, env, policy)?;
+    }
+
+    for host in network.hosts.iter_mut() {
+        if let HostEntry::Detailed(details) = host {
+            let owner = format!("host {}", details.host);
+            resolve_secret(&format!("{}: pass", owner), &mut details.pass, policy)?;
+            if let Some(env) = details.env.as_mut() {
+                resolve_env_values(&owner, env, policy)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reference_forms() {
+        env::set_var("SSUP_SECRET_RESOLVER_TEST", "from-env");
+        let file = env::temp_dir().join(format!("ssup-secret-{}", std::process::id()));
+        fs::write(&file, "from-file\n").unwrap();
+
+        assert_eq!(resolve_value("f", "plain", OutputPolicy::Trim).unwrap(), "plain");
+        assert_eq!(resolve_value("f", "$(echo from-cmd)", OutputPolicy::Trim).unwrap(), "from-cmd");
+        assert_eq!(resolve_value("f", "env:SSUP_SECRET_RESOLVER_TEST", OutputPolicy::Trim).unwrap(), "from-env");
+        assert_eq!(resolve_value("f", &format!("file:{}", file.display()), OutputPolicy::Trim).unwrap(), "from-file");
+        fs::remove_file(&file).unwrap();
+    }
+
+    #[test]
+    fn test_files_keep_utf8() {
+        let file = env::temp_dir().join(format!("ssup-secret-utf8-{}", std::process::id()));
+        fs::write(&file, "пароль с пробелом\n").unwrap();
+        let reference = format!("file:{}", file.display());
+        assert_eq!(resolve_value("f", &reference, OutputPolicy::Trim).unwrap(), "пароль с пробелом");
+
+        let invalid = env::temp_dir().join(format!("ssup-secret-latin1-{}", std::process::id()));
+        fs::write(&invalid, b"caf\xe9").unwrap();
+        let err = resolve_value("f", &format!("file:{}", invalid.display()), OutputPolicy::Trim).unwrap_err();
+        assert!(matches!(err, SecretError::InvalidUtf8 { .. }));
+        fs::remove_file(&file).unwrap();
+        fs::remove_file(&invalid).unwrap();
+    }
+
+    #[test]
+    fn test_commands_run_once_per_run() {
+        let counter = env::temp_dir().join(format!("ssup-secret-count-{}", std::process::id()));
+        let reference = format!("$(echo x >> {0}; wc -l < {0} | tr -d ' ')", counter.display());
+        assert_eq!(resolve_value("a", &reference, OutputPolicy::Trim).unwrap(), "1");
+        assert_eq!(resolve_value("b", &reference, OutputPolicy::Trim).unwrap(), "1");
+        fs::remove_file(&counter).unwrap();
+    }
+
+    #[test]
+    fn test_failures_are_errors() {
+        let err = resolve_value("network prod: pass", "$(exit 1)", OutputPolicy::Trim).unwrap_err();
+        assert!(matches!(err, SecretError::Shell { .. }));
+        assert!(err.to_string().starts_with("network prod: pass: "));
+        assert!(matches!(
+            resolve_value("f", "env:SSUP_SURELY_NOT_SET_ANYWHERE", OutputPolicy::Trim),
+            Err(SecretError::MissingEnv { .. })
+        ));
+        assert!(matches!(resolve_value("f", "file:/nonexistent/ssup/secret", OutputPolicy::Trim), Err(SecretError::ReadFile { .. })));
+    }
+
+    #[test]
+    fn test_network_fields_and_hosts() {
+        let yaml = r#"
+hosts:
+- host: root@10.0.0.1
+  pass: $(echo host-pass)
+  env:
+    TOKEN: $(echo token)
+    LATER:
+      raw: $(hostname)
+pass: $(echo net-pass)
+id_file: $(echo /keys/id)
+env:
+  PLAIN: value
+"#;
+        let mut network: Network = serde_yaml::from_str(yaml).unwrap();
+        network.name = "prod".to_string();
+        resolve_network_secrets(&mut network, OutputPolicy::Trim).unwrap();
+
+        assert_eq!(network.pass.as_ref().map(Secret::expose), Some("net-pass"));
+        assert_eq!(network.id_file.as_deref(), Some("/keys/id"));
+        assert_eq!(network.env.as_ref().unwrap().get("PLAIN"), Some("value"));
+        match &network.hosts[0] {
+            HostEntry::Detailed(details) => {
+                assert_eq!(details.pass.as_ref().map(Secret::expose), Some("host-pass"));
+                assert_eq!(crate::entity::secret::mask_secrets("pw=host-pass"), "pw=****");
+                let env = details.env.as_ref().unwrap();
+                assert_eq!(env.get("TOKEN"), Some("token"));
+                assert_eq!(env.get("LATER"), Some("$(hostname)"));
+            }
+            HostEntry::Simple(_) => panic!("expected a detailed host"),
+        }
+    }
+}
@@ -0,0 +1,212 @@
+use crate::entity::const_values::CSUP_PASSWD_ENV;
+use crate::entity::env::EnvList;
+use crate::entity::secret::register_secret;
+use crate::entity::supfile::Supfile;
+use crate::entity::InitState;
+use crate::gateways::logger::logger_func as l;
+use crate::gateways::secrets_file::{load_secrets, parse_plaintext, save_secrets};
+use crate::gateways::terminal;
+use crate::usecase::error::SsupError;
+use indexmap::IndexMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+use std::env;
+
+const USAGE: &str = "Usage: ssup secrets edit\n       ssup secrets get KEY\n       ssup secrets set KEY [-]\n\nset asks for the value without echoing it, `-` reads it from stdin";
+
+/// The passphrase from `$SUP_PASSWORD`, or asked for on the terminal.
+/// `confirm` asks twice, for files that are about to be created.
+fn passphrase(confirm: bool) -> Result<String, SsupError> {
+    if let Ok(passphrase) = env::var(CSUP_PASSWD_ENV) {
+        return Ok(passphrase);
+    }
+    let ask = |prompt: &str| {
+        rpassword::prompt_password(prompt).map_err(|e| SsupError::Interactive {
+            message: format!("could not read the secrets passphrase: {} (set ${} instead)", e, CSUP_PASSWD_ENV),
+            trace_id: "8C6E6D2B-1BB5-4A92-8E8E-105C31042F52",
+        })
+    };
+    let first = ask("Secrets passphrase: ")?;
+    if confirm && ask("Repeat passphrase: ")? != first {
+        return Err(SsupError::Interactive {
+            message: "passphrases do not match".to_string(),
+            trace_id: "575A2D7D-15DD-46AA-BFFB-C65AD405813D",
+        });
+    }
+    Ok(first)
+}
+
+/// Adds every secret to `env` as a secret value and registers it for masking.
+pub fn merge_secrets(env: &mut EnvList, secrets: &IndexMap<String, String>) {
+    for (key, value) in secrets {
+        env.mark_secret(key);
+        env.set(key.clone(), value.clone());
+        register_secret(value);
+    }
+}
+
+/// Decrypts the Supfile's `secrets:` file, if any, into its `env`.
+pub fn load_supfile_secrets(supfile: &mut Supfile) -> Result<(), SsupError> {
+    let Some(path) = supfile.secrets.clone() else {
+        return Ok(());
+    };
+    l(&format!("loading secrets from {}", path));
+    let secrets = load_secrets(Path::new(&path), &passphrase(false)?)?;
+    merge_secrets(&mut supfile.env, &secrets);
+    Ok(())
+}
+
+/// `ssup secrets edit|get|set`: manages the Supfile's encrypted `secrets:` file.
+pub fn secrets_command(init_state: &InitState) -> Result<(), SsupError> {
+    let Some(path) = init_state.supfile.secrets.clone().map(PathBuf::from) else {
+        return Err(SsupError::NoSecretsFile { trace_id: "DAFC1896-5593-4868-8901-89BF5B5E2EEA" });
+    };
+    let args: Vec<&str> = init_state.args.iter().skip(1).map(String::as_str).collect();
+    let exists = path.exists();
+    let open = |passphrase: &str| -> Result<IndexMap<String, String>, SsupError> {
+        if !exists {
+            return Ok(IndexMap::new());
+        }
+        Ok(load_secrets(&path, passphrase)?)
+    };
+
+    match args.as_slice() {
+        ["get", key] => {
+            let secrets = open(&passphrase(false)?)?;
+            let value = secrets.get(*key).ok_or_else(|| SsupError::SecretNotFound {
+                key: key.to_string(),
+                path: path.display().to_string(),
+                trace_id: "3B1F6A0E-7C42-4D95-A8E3-5F20C9D74B16",
+            })?;
+            println!("{}", value);
+        }
+        ["set", key] | ["set", key, "-"] => {
+            let passphrase = passphrase(!exists)?;
+            let mut secrets = open(&passphrase)?;
+            let value = if args.len() == 3 {
+                read_value(&mut io::stdin().lock())
+            } else {
+                terminal::ask(&format!("Value for {}: ", key), true)
+            };
+            let value = value.map_err(|e| SsupError::Interactive {
+                message: format!("could not read the value of {}: {} (pipe it in with `ssup secrets set {} -`)", key, e, key),
+                trace_id: "E07A4C59-2B8D-4F13-96C0-D1A8357F2E94",
+            })?;
+            secrets.insert(key.to_string(), value);
+            save_secrets(&path, &passphrase, &secrets)?;
+        }
+        ["edit"] => {
+            let passphrase = passphrase(!exists)?;
+            let secrets = open(&passphrase)?;
+            let edited = edit_in_editor(&secrets).map_err(|message| SsupError::Interactive {
+                message: format!("{}, nothing was saved", message),
+                trace_id: "6D93B2F1-0E57-4A8C-B4D6-29C1E8F05A73",
+            })?;
+            match edited {
+                Some(edited) => save_secrets(&path, &passphrase, &edited)?,
+                None => println!("no changes"),
+            }
+        }
+        _ => return Err(SsupError::Usage { message: USAGE.to_string() }),
+    }
+    Ok(())
+}
+
+/// A value piped in, e.g. `pass show db | ssup secrets set DB_PASS -`,
+/// without the final line break.
+fn read_value(input: &mut dyn Read) -> io::Result<String> {
+    let mut value = String::new();
+    input.read_to_string(&mut value)?;
+    let trimmed = value.strip_suffix('\n').map(|v| v.strip_suffix('\r').unwrap_or(v)).unwrap_or(&value);
+    Ok(trimmed.to_string())
+}
+
+/// Opens the decrypted secrets in `$VISUAL`/`$EDITOR`, in a private temporary
+/// file that is removed afterwards. Returns `None` when nothing changed.
+fn edit_in_editor(secrets: &IndexMap<String, String>) -> Result<Option<IndexMap<String, String>>, String> {
+    let editor = env::var("VISUAL").or_else(|_| env::var("EDITOR")).unwrap_or_else(|_| "vi".to_string());
+    let dir = env::temp_dir().join(format!("ssup-secrets-{}", process::id()));
+    fs::DirBuilder::new().mode(0o700).create(&dir).map_err(|e| format!("failed to create {}: {}", dir.display(), e))?;
+    let file = dir.join("secrets.yml");
+    let result = edit_file(&file, secrets, &editor);
+    let _ = fs::remove_dir_all(&dir);
+    result
+}
+
+fn edit_file(file: &Path, secrets: &IndexMap<String, String>, editor: &str) -> Result<Option<IndexMap<String, String>>, String> {
+    let before = if secrets.is_empty() {
+        "# KEY: value\n".to_string()
+    } else {
+        serde_yaml::to_string(secrets).expect("a map of strings always serializes")
+    };
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(file)
+        .and_then(|mut f| f.write_all(before.as_bytes()))
+        .map_err(|e| format!("failed to write {}: {}", file.display(), e))?;
+
+    // through sh, so EDITOR may carry arguments like `code --wait`
+    let status = Command::new("/bin/sh")
+        .arg("-c")
+        .arg(format!("{} \"$1\"", editor))
+        .arg("sh")
+        .arg(file)
+        .status()
+        .map_err(|e| format!("failed to start {}: {}", editor, e))?;
+    if !status.success() {
+        return Err(format!("{} exited with {}", editor, status));
+    }
+
+    let after = fs::read_to_string(file).map_err(|e| format!("failed to read {}: {}", file.display(), e))?;
+    if after == before {
+        return Ok(None);
+    }
+    parse_plaintext(&after).map(Some).map_err(|e| format!("edited secrets are not a YAML map of strings: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merged_secrets_are_secret() {
+        let mut env: EnvList = serde_yaml::from_str("APP: shop\nDB_PASS: placeholder").unwrap();
+        let secrets = IndexMap::from([("DB_PASS".to_string(), "merged-db-pass".to_string())]);
+        merge_secrets(&mut env, &secrets);
+        assert_eq!(env.get("DB_PASS"), Some("merged-db-pass"));
+        assert!(env.is_secret("DB_PASS") && !env.is_secret("APP"));
+        assert_eq!(crate::entity::secret::mask_secrets("pw merged-db-pass"), "pw ****");
+    }
+
+    #[test]
+    fn test_editor_changes_are_read_back() {
+        let dir = env::temp_dir().join(format!("ssup-secrets-edit-test-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("secrets.yml");
+        let editor = "printf 'NEW: value\\n' >>";
+        let edited = edit_file(&file, &IndexMap::from([("OLD".to_string(), "x".to_string())]), editor).unwrap().unwrap();
+        assert_eq!(edited.get("OLD").map(String::as_str), Some("x"));
+        assert_eq!(edited.get("NEW").map(String::as_str), Some("value"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_values_are_piped_in() {
+        assert_eq!(read_value(&mut "s3cr3t\n".as_bytes()).unwrap(), "s3cr3t");
+        assert_eq!(read_value(&mut "line one\nline two\r\n".as_bytes()).unwrap(), "line one\nline two");
+        assert_eq!(read_value(&mut "no newline".as_bytes()).unwrap(), "no newline");
+    }
+
+    #[test]
+    fn test_values_are_not_taken_from_argv() {
+        let yaml = "version: 0.5\nsecrets: secrets.enc.yml\ncommands: {}\n";
+        let state = crate::usecase::modes_of_operation::test_support::init_state(yaml, &["secrets", "set", "DB_PASS", "hunter2"]);
+        let err = secrets_command(&state).unwrap_err();
+        assert!(matches!(err, SsupError::Usage { .. }), "{}", err);
+    }
+}